@@ -2,6 +2,7 @@
 layout(binding = 1) uniform sampler2D texSampler;
 
 layout(location = 0) in vec2 o_uv;
+layout(location = 1) in float o_selected;
 
 layout(location = 0) out vec4 uFragColor;
 const vec4 fgColor = vec4(1.0, 1.0, 1.0, 0.0);
@@ -21,5 +22,7 @@ void main() {
    float screenPxDistance = screenPxRange() * (sd - 0.5);
    float opacity = clamp(screenPxDistance + 0.5, 0.0, 1.0);
    uFragColor = mix(bgColor, fgColor, opacity);
+   // Selected cells are drawn inverted
+   uFragColor = mix(uFragColor, fgColor - uFragColor, o_selected);
    // uFragColor = vec4(1.0, 0.0, 0.0, 1.0);
 }
@@ -0,0 +1,33 @@
+# Shell integration for dit, loaded through --rcfile in place of ~/.bashrc. Marks prompts,
+# commands and their output with OSC 133 and reports the working directory with OSC 7.
+# The system-wide file comes first, as bash reads it itself without --rcfile.
+if [ -f /etc/bash.bashrc ]; then
+    . /etc/bash.bashrc
+fi
+if [ -f ~/.bashrc ]; then
+    . ~/.bashrc
+fi
+
+if [[ $- == *i* && -z $__dit_integration ]]; then
+    __dit_integration=1
+
+    # First in PROMPT_COMMAND so it sees the status of the command, which it passes on
+    __dit_precmd() {
+        local status=$?
+        printf '\e]133;D;%s\a\e]133;A\a' "$status"
+        printf '\e]7;file://%s%s\a' "$HOSTNAME" "$PWD"
+        return "$status"
+    }
+    # Last, prompts set by an earlier PROMPT_COMMAND keep the mark
+    __dit_mark_prompt() {
+        local status=$?
+        if [[ $PS1 != *'\e]133;B\a'* ]]; then
+            PS1+='\[\e]133;B\a\]'
+        fi
+        if [[ $PS0 != *$'\e]133;C\a'* ]]; then
+            PS0+=$'\e]133;C\a'
+        fi
+        return "$status"
+    }
+    PROMPT_COMMAND="__dit_precmd${PROMPT_COMMAND:+;$PROMPT_COMMAND};__dit_mark_prompt"
+fi
@@ -0,0 +1,20 @@
+# Shell integration for dit, found by fish through XDG_DATA_DIRS. Marks prompts, commands and
+# their output with OSC 133 and reports the working directory with OSC 7.
+if set -q DIT_FISH_XDG_DATA_DIRS
+    set -gx XDG_DATA_DIRS $DIT_FISH_XDG_DATA_DIRS
+    set -e DIT_FISH_XDG_DATA_DIRS
+else
+    set -e XDG_DATA_DIRS
+end
+
+status is-interactive; or exit
+
+function __dit_prompt --on-event fish_prompt
+    printf '\e]133;A\a\e]7;file://%s%s\a' $hostname $PWD
+end
+function __dit_preexec --on-event fish_preexec
+    printf '\e]133;C\a'
+end
+function __dit_postexec --on-event fish_postexec
+    printf '\e]133;D;%s\a' $status
+end
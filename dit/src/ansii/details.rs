@@ -1,3 +1,5 @@
+use crate::ansii::ParamGroup;
+
 bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, Default)]
     pub struct AttrFlags: u16 {
@@ -12,13 +14,31 @@ bitflags::bitflags! {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Rgb {
     pub r: u8,
     pub g: u8,
     pub b: u8,
 }
-const THEME_SPECIFIC: [u8; 16] = [0u8; 16];
+// xterm's default palette for the 16 theme colors (0-7 normal, 8-15 bright)
+const THEME_SPECIFIC: [Rgb; 16] = [
+    Rgb::BLACK,
+    Rgb::new(205, 0, 0),
+    Rgb::new(0, 205, 0),
+    Rgb::new(205, 205, 0),
+    Rgb::new(0, 0, 238),
+    Rgb::new(205, 0, 205),
+    Rgb::new(0, 205, 205),
+    Rgb::new(229, 229, 229),
+    Rgb::new(127, 127, 127),
+    Rgb::RED,
+    Rgb::GREEN,
+    Rgb::YELLOW,
+    Rgb::new(92, 92, 255),
+    Rgb::MAGENTA,
+    Rgb::CYAN,
+    Rgb::WHITE,
+];
 const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
 
 impl Rgb {
@@ -47,23 +67,49 @@ impl Rgb {
         g: 255,
         b: 255,
     };
-    pub fn new(r: u8, g: u8, b: u8) -> Self {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
         Self { r, g, b }
     }
 
+    /// Resolves an entry of the 256 color palette.
+    /// 0-15 are the theme colors, 16-231 the 6x6x6 cube and 232-255 the greyscale ramp.
     pub fn from_index(n: u8) -> Self {
-        if n <= 231 && 16 <= n {
-            let i = n - 16;
-            let r = i / 36;
-            let g = (i & 36) / 6;
-            let b = i % 6;
-            return Self {
-                r: LEVELS[r as usize],
-                g: LEVELS[g as usize],
-                b: LEVELS[b as usize],
-            };
+        match n {
+            0..=15 => THEME_SPECIFIC[n as usize],
+            16..=231 => {
+                let i = n - 16;
+                let r = i / 36;
+                let g = (i / 6) % 6;
+                let b = i % 6;
+                Self {
+                    r: LEVELS[r as usize],
+                    g: LEVELS[g as usize],
+                    b: LEVELS[b as usize],
+                }
+            }
+            232..=255 => {
+                let level = 8 + (n - 232) * 10;
+                Self::new(level, level, level)
+            }
+        }
+    }
+}
+
+/// A color as set through SGR. `Default` defers to the theme's foreground/background.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    #[default]
+    Default,
+    Indexed(u8),
+    Rgb(Rgb),
+}
+impl Color {
+    pub fn resolve(&self, default: Rgb) -> Rgb {
+        match self {
+            Color::Default => default,
+            Color::Indexed(n) => Rgb::from_index(*n),
+            Color::Rgb(rgb) => *rgb,
         }
-        Self { r: 0, g: 0, b: 0 }
     }
 }
 
@@ -82,52 +128,143 @@ enum TerminalType {
     VT525 = 65,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum Intensity {
     #[default]
     Normal,
     Bold,
     Faint,
 }
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum Underline {
     #[default]
     None,
     Single,
     Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+impl Underline {
+    /// Style selected by the `4:n` sub-parameter
+    fn from_style(style: u16) -> Self {
+        match style {
+            0 => Underline::None,
+            2 => Underline::Double,
+            3 => Underline::Curly,
+            4 => Underline::Dotted,
+            5 => Underline::Dashed,
+            _ => Underline::Single,
+        }
+    }
 }
 //Maintains the state machine and also calls the respective functions
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct Attributes {
     pub italic: bool,
     pub blink: bool,
     pub inverse: bool,
     pub hidden: bool,
     pub strike: bool,
+    pub overline: bool,
     pub underline: Underline,
     pub intensity: Intensity,
-    pub fg: Rgb,
-    pub bg: Rgb,
-}
-impl Default for Attributes {
-    fn default() -> Self {
-        Self {
-            italic: false,
-            blink: false,
-            inverse: false,
-            hidden: false,
-            strike: false,
-            intensity: Intensity::default(),
-            underline: Underline::default(),
-            bg: Rgb::default(),
-            fg: Rgb::default(),
-        }
-    }
+    pub fg: Color,
+    pub bg: Color,
+    pub underline_color: Color,
 }
 impl Attributes {
     fn reset(&mut self) {
         *self = Self::default();
     }
+    /// Applies a full SGR sequence. Both the `;` form (`38;5;n`) and the `:` form
+    /// (`38:5:n`, `38:2::r:g:b`) of extended colors are understood.
+    pub fn apply_sgr(&mut self, params: &[ParamGroup]) {
+        let mut i = 0;
+        while i < params.len() {
+            let group = &params[i];
+            i += 1;
+            match group[0] {
+                0 => self.reset(),
+                1 => self.intensity = Intensity::Bold,
+                2 => self.intensity = Intensity::Faint,
+                3 => self.italic = true,
+                4 => {
+                    self.underline = match group.get(1) {
+                        Some(&style) => Underline::from_style(style),
+                        None => Underline::Single,
+                    }
+                }
+                5 | 6 => self.blink = true,
+                7 => self.inverse = true,
+                8 => self.hidden = true,
+                9 => self.strike = true,
+                21 => self.underline = Underline::Double,
+                22 => self.intensity = Intensity::Normal,
+                23 => self.italic = false,
+                24 => self.underline = Underline::None,
+                25 => self.blink = false,
+                27 => self.inverse = false,
+                28 => self.hidden = false,
+                29 => self.strike = false,
+                n @ 30..=37 => self.fg = Color::Indexed((n - 30) as u8),
+                39 => self.fg = Color::Default,
+                n @ 40..=47 => self.bg = Color::Indexed((n - 40) as u8),
+                49 => self.bg = Color::Default,
+                53 => self.overline = true,
+                55 => self.overline = false,
+                59 => self.underline_color = Color::Default,
+                n @ 90..=97 => self.fg = Color::Indexed((n - 90 + 8) as u8),
+                n @ 100..=107 => self.bg = Color::Indexed((n - 100 + 8) as u8),
+                target @ (38 | 48 | 58) => {
+                    let color = if group.len() > 1 {
+                        extended_color_colon(&group[1..])
+                    } else {
+                        let (color, consumed) = extended_color_semicolon(&params[i..]);
+                        i += consumed;
+                        color
+                    };
+                    if let Some(color) = color {
+                        match target {
+                            38 => self.fg = color,
+                            48 => self.bg = color,
+                            _ => self.underline_color = color,
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+/// Parses the sub-parameters of `38:5:n` / `38:2:[colorspace]:r:g:b`.
+fn extended_color_colon(sub: &[u16]) -> Option<Color> {
+    match sub {
+        [5, n, ..] => Some(Color::Indexed(*n as u8)),
+        // With the (usually empty) colorspace id
+        [2, _, r, g, b, ..] => Some(Color::Rgb(Rgb::new(*r as u8, *g as u8, *b as u8))),
+        // Without it, as emitted by a number of applications
+        [2, r, g, b] => Some(Color::Rgb(Rgb::new(*r as u8, *g as u8, *b as u8))),
+        _ => None,
+    }
+}
+/// Parses the parameters following a `38;` / `48;` / `58;` and returns how many were used.
+fn extended_color_semicolon(rest: &[ParamGroup]) -> (Option<Color>, usize) {
+    let value = |idx: usize| rest.get(idx).map(|g| g[0]);
+    match value(0) {
+        Some(5) => match value(1) {
+            Some(n) => (Some(Color::Indexed(n as u8)), 2),
+            None => (None, rest.len()),
+        },
+        Some(2) => match (value(1), value(2), value(3)) {
+            (Some(r), Some(g), Some(b)) => {
+                (Some(Color::Rgb(Rgb::new(r as u8, g as u8, b as u8))), 4)
+            }
+            _ => (None, rest.len()),
+        },
+        Some(_) => (None, 1),
+        None => (None, 0),
+    }
 }
 #[derive(Debug, Default)]
 enum SimpleColors {
@@ -141,11 +278,145 @@ enum SimpleColors {
     Cyan,
     White,
 }
+bitflags::bitflags! {
+    /// Terminal modes toggled through SM/RM and DECSET/DECRST.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct TermMode: u32 {
+        const INSERT            = 1 << 0; // IRM   (4)
+        const LINE_FEED_NEWLINE = 1 << 1; // LNM   (20)
+        const CURSOR_KEYS       = 1 << 2; // DECCKM (?1)
+        const ORIGIN            = 1 << 3; // DECOM (?6)
+        const AUTOWRAP          = 1 << 4; // DECAWM (?7)
+        const CURSOR_BLINK      = 1 << 5; // (?12)
+        const SHOW_CURSOR       = 1 << 6; // DECTCEM (?25)
+        const ALT_SCREEN        = 1 << 7; // (?47 / ?1047 / ?1049)
+        const BRACKETED_PASTE   = 1 << 8; // (?2004)
+        const LEFT_RIGHT_MARGIN = 1 << 9; // DECLRMM (?69)
+        const KEYPAD_APPLICATION = 1 << 10; // DECNKM (?66), also DECKPAM / DECKPNM
+        const MOUSE_X10         = 1 << 11; // (?9)    presses only
+        const MOUSE_NORMAL      = 1 << 12; // (?1000) presses and releases
+        const MOUSE_BUTTON_EVENT = 1 << 13; // (?1002) also motion while a button is held
+        const MOUSE_ANY_EVENT   = 1 << 14; // (?1003) also motion without buttons
+        const MOUSE_UTF8        = 1 << 15; // (?1005)
+        const MOUSE_SGR         = 1 << 16; // (?1006)
+        const MOUSE_URXVT       = 1 << 17; // (?1015)
+        const MOUSE_SGR_PIXELS  = 1 << 18; // (?1016)
+        const GRAPHEME_CLUSTERS = 1 << 19; // (?2027) combining by UAX #29 rather than by width
+
+        /// Tracking modes, only one is active at a time
+        const MOUSE_TRACKING = Self::MOUSE_X10.bits()
+            | Self::MOUSE_NORMAL.bits()
+            | Self::MOUSE_BUTTON_EVENT.bits()
+            | Self::MOUSE_ANY_EVENT.bits();
+        /// Report encodings, only one is active at a time
+        const MOUSE_ENCODING = Self::MOUSE_UTF8.bits()
+            | Self::MOUSE_SGR.bits()
+            | Self::MOUSE_URXVT.bits()
+            | Self::MOUSE_SGR_PIXELS.bits();
+    }
+}
+impl Default for TermMode {
+    fn default() -> Self {
+        TermMode::AUTOWRAP | TermMode::SHOW_CURSOR
+    }
+}
+impl TermMode {
+    /// Maps an SM/RM parameter to the mode it controls, `None` when unsupported.
+    pub fn from_param(param: u16, private: bool) -> Option<Self> {
+        let mode = match (param, private) {
+            (4, false) => TermMode::INSERT,
+            (20, false) => TermMode::LINE_FEED_NEWLINE,
+            (1, true) => TermMode::CURSOR_KEYS,
+            (6, true) => TermMode::ORIGIN,
+            (7, true) => TermMode::AUTOWRAP,
+            (12, true) => TermMode::CURSOR_BLINK,
+            (25, true) => TermMode::SHOW_CURSOR,
+            (66, true) => TermMode::KEYPAD_APPLICATION,
+            (69, true) => TermMode::LEFT_RIGHT_MARGIN,
+            (47 | 1047 | 1049, true) => TermMode::ALT_SCREEN,
+            (2004, true) => TermMode::BRACKETED_PASTE,
+            (9, true) => TermMode::MOUSE_X10,
+            (1000, true) => TermMode::MOUSE_NORMAL,
+            (1002, true) => TermMode::MOUSE_BUTTON_EVENT,
+            (1003, true) => TermMode::MOUSE_ANY_EVENT,
+            (1005, true) => TermMode::MOUSE_UTF8,
+            (1006, true) => TermMode::MOUSE_SGR,
+            (1015, true) => TermMode::MOUSE_URXVT,
+            (1016, true) => TermMode::MOUSE_SGR_PIXELS,
+            (2027, true) => TermMode::GRAPHEME_CLUSTERS,
+            _ => return None,
+        };
+        Some(mode)
+    }
+}
+bitflags::bitflags! {
+    /// Progressive enhancements of the kitty keyboard protocol, set through `CSI = flags u`
+    /// and the `CSI > flags u` / `CSI < u` stack.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct KeyboardFlags: u8 {
+        const DISAMBIGUATE       = 1 << 0;
+        const REPORT_EVENT_TYPES = 1 << 1;
+        const REPORT_ALTERNATES  = 1 << 2;
+        const REPORT_ALL_KEYS    = 1 << 3;
+        const REPORT_TEXT        = 1 << 4;
+    }
+}
+/// G0–G3, the slots character sets are designated into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharsetSlot {
+    G0,
+    G1,
+    G2,
+    G3,
+}
+/// 94-character sets a slot can hold, by the final byte of their SCS sequence
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    /// `B`
+    #[default]
+    Ascii,
+    /// `0`, line drawing and a few symbols in place of the lowercase letters
+    DecSpecialGraphics,
+    /// `A`, `#` is the pound sign
+    Uk,
+    /// `<` or `%5`, the upper half of DEC's multinational set
+    DecSupplemental,
+}
+impl Charset {
+    /// The set an SCS sequence ending in `intermediate` (after the slot's) and `final_byte`
+    /// designates, `None` for sets we don't have
+    pub fn from_designation(intermediate: Option<u8>, final_byte: u8) -> Option<Self> {
+        Some(match (intermediate, final_byte) {
+            (None, b'B') => Charset::Ascii,
+            (None, b'0') => Charset::DecSpecialGraphics,
+            (None, b'A') => Charset::Uk,
+            (None, b'<') | (Some(b'%'), b'5') => Charset::DecSupplemental,
+            _ => return None,
+        })
+    }
+}
+/// Pm values of a DECRPM report (`CSI ? Ps ; Pm $ y`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeStatus {
+    NotRecognized = 0,
+    Set = 1,
+    Reset = 2,
+    PermanentlySet = 3,
+    PermanentlyReset = 4,
+}
 #[derive(Debug, Clone, Copy)]
 pub enum DeviceStatusReport {
     Ok,
     CursorPosition(u16, u16),
 }
+impl DeviceStatusReport {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::Ok => b"\x1b[0n".to_vec(),
+            Self::CursorPosition(row, col) => format!("\x1b[{};{}R", row, col).into_bytes(),
+        }
+    }
+}
 #[derive(Debug, Clone, Copy)]
 pub enum DeviceAttributes {
     VT100AdvancedVideo, // CSI ? 1 ; 2 c
@@ -159,3 +430,24 @@ pub enum DeviceAttributes {
     VT420(u16),         // CSI ? 64 ; Ps c
     VT510(u16),         // CSI ? 65 ; Ps c
 }
+impl DeviceAttributes {
+    pub fn encode(&self) -> Vec<u8> {
+        let params = match self {
+            Self::VT100AdvancedVideo => "1;2".to_string(),
+            Self::VT101NoOptions => "1;0".to_string(),
+            Self::VT132 => "4;6".to_string(),
+            Self::VT102 => "6".to_string(),
+            Self::VT131 => "7".to_string(),
+            Self::VT125(ps) => format!("12;{}", ps),
+            Self::VT220(ps) => format!("62;{}", ps),
+            Self::VT320(ps) => format!("63;{}", ps),
+            Self::VT420(ps) => format!("64;{}", ps),
+            Self::VT510(ps) => format!("65;{}", ps),
+        };
+        format!("\x1b[?{}c", params).into_bytes()
+    }
+}
+/// DA2 – `CSI > Pp ; Pv ; Pc c`
+pub fn secondary_attributes(version: u16) -> Vec<u8> {
+    format!("\x1b[>{};{};0c", TerminalType::VT220 as u16, version).into_bytes()
+}
@@ -1,5 +1,7 @@
 use smallvec::SmallVec;
 
+use details::{Charset, CharsetSlot};
+
 pub mod details;
 pub mod tests;
 pub mod utf_decoder;
@@ -81,6 +83,12 @@ pub trait Handler {
     fn save_cursor_position(&mut self);
     /// DECRC / ESC 8 – restore cursor + attributes
     fn restore_cursor_position(&mut self);
+    /// SCS – `ESC ( ) * + <final>` designates `charset` into G0–G3
+    fn designate_charset(&mut self, slot: CharsetSlot, charset: Charset);
+    /// LS2 / LS3 – `ESC n` / `ESC o`, SI and SO shift G0 and G1 through `execute`
+    fn locking_shift(&mut self, slot: CharsetSlot);
+    /// SS2 / SS3 – `ESC N` / `ESC O`, the next character only comes from G2 / G3
+    fn single_shift(&mut self, slot: CharsetSlot);
 
     /// ED  – erase in display  (0=below, 1=above, 2=all, 3=saved lines)
     fn erase_display(&mut self, mode: u16);
@@ -102,7 +110,11 @@ pub trait Handler {
     fn scroll_down(&mut self, n: u16);
     /// DECSTBM – set scrolling region [top, bottom] (1-based, inclusive)
     fn set_scrolling_region(&mut self, top: u16, bottom: u16);
-    fn char_attributes(&mut self, params: &SmallVec<[u16; 8]>);
+    /// DECSLRM – set left/right margins [left, right] (1-based, inclusive). Only honoured while
+    /// DECLRMM (?69) is set, otherwise `CSI s` is SCOSC and saves the cursor.
+    fn set_left_right_margins(&mut self, left: u16, right: u16);
+    /// SGR – select graphic rendition, one group per `;` separated parameter
+    fn char_attributes(&mut self, params: &[ParamGroup]);
     /// HTS / ESC H – set tab stop at current column
     fn set_tab_stop(&mut self);
     /// TBC – clear tab stops (0=current col, 3=all)
@@ -111,6 +123,8 @@ pub trait Handler {
     fn cursor_forward_tab(&mut self, n: u16);
     /// CBT – cursor backward tabulation n tab stops
     fn cursor_backward_tab(&mut self, n: u16);
+    /// DECST8C – tab stops back to every 8 columns
+    fn reset_tab_stops(&mut self);
     /// SM / RM – set/reset mode.  `private` = true when '?' intermediate present
     fn set_mode(&mut self, params: &SmallVec<[u16; 8]>, private: bool);
     fn reset_mode(&mut self, params: &SmallVec<[u16; 8]>, private: bool);
@@ -120,8 +134,14 @@ pub trait Handler {
     fn secondary_device_attributes(&mut self);
     /// DSR  – device status report
     fn device_status_report(&mut self, param: u16);
+    /// DECRQM – request mode.  `private` = true when '?' intermediate present
+    fn request_mode(&mut self, mode: u16, private: bool);
+    /// XTVERSION – report terminal name and version (intermediate b'>')
+    fn report_version(&mut self);
     /// DECSTR – soft terminal reset
     fn soft_reset(&mut self);
+    /// RIS – reset to initial state
+    fn full_reset(&mut self);
     /// DECSCUSR – set cursor style (0/1=blinking block, 2=steady block, …)
     fn set_cursor_style(&mut self, style: u16);
     /// XTWINOPS – window manipulation
@@ -135,9 +155,21 @@ pub trait Handler {
     /// DECKPAM / DECKPNM – application / normal keypad mode
     fn set_keypad_application_mode(&mut self);
     fn unset_keypad_application_mode(&mut self);
+    /// XTMODKEYS – `CSI > Pp ; Pv m`, `value` is `None` when omitted which resets the resource
+    fn set_modify_keys(&mut self, resource: u16, value: Option<u16>);
+    /// Kitty keyboard protocol – `CSI > flags u` pushes onto the flags stack
+    fn push_keyboard_flags(&mut self, flags: u16);
+    /// `CSI < n u` – pops `n` entries off the flags stack
+    fn pop_keyboard_flags(&mut self, n: u16);
+    /// `CSI = flags ; mode u` – mode 1 replaces the current flags, 2 sets and 3 clears bits
+    fn set_keyboard_flags(&mut self, flags: u16, mode: u16);
+    /// `CSI ? u` – reply with `CSI ? flags u`
+    fn report_keyboard_flags(&mut self);
 
     fn execute(&mut self, ctl_seq: u8);
-    fn handle_osc(&mut self, osc: &Vec<u8>);
+    /// `OSC Ps ; Pt ST` – `command` is the numeric `Ps`, `payload` everything after the first
+    /// `;`, further `;` included. Strings without a numeric command never get here.
+    fn handle_osc(&mut self, command: u16, payload: &[u8]);
     fn accumluate_utf8(&mut self, byte: u8);
     fn bell(&mut self);
     fn csi(&mut self) {}
@@ -145,13 +177,28 @@ pub trait Handler {
 
 // ─── Parser ──────────────────────────────────────────────────────────────────
 
+/// A CSI parameter followed by its `:` separated sub-parameters.
+/// `38:2::255:0:0` is stored as `[38, 2, 0, 255, 0, 0]`, a plain `1` as `[1]`.
+pub type ParamGroup = SmallVec<[u16; 6]>;
+
+/// Longest OSC string kept, in bytes. Longer ones are dropped whole once they end rather than
+/// growing the buffer without bound.
+pub const MAX_OSC_LEN: usize = 2 << 20;
+
 #[derive(Debug)]
 pub struct Parser {
     pub state: State,
     pub params: SmallVec<[u16; 8]>,
+    /// Same parameters as `params` but with their sub-parameters kept
+    pub groups: SmallVec<[ParamGroup; 8]>,
     pub intermediates: SmallVec<[u8; 4]>,
     current_param: u16,
+    current_group: ParamGroup,
     osc_buffer: Vec<u8>,
+    /// The OSC string went past `MAX_OSC_LEN`
+    osc_overflow: bool,
+    /// Continuation bytes still expected by the UTF-8 sequence being printed
+    utf8_remaining: u8,
 }
 
 #[inline(always)]
@@ -159,6 +206,17 @@ fn is_execute(byte: u8) -> bool {
     matches!(byte, 0x00..=0x17 | 0x19 | 0x1c..=0x1f)
 }
 
+/// Continuation bytes following `byte` when it leads a UTF-8 sequence
+#[inline(always)]
+fn utf8_continuations(byte: u8) -> u8 {
+    match byte {
+        0xc0..=0xdf => 1,
+        0xe0..=0xef => 2,
+        0xf0..=0xf7 => 3,
+        _ => 0,
+    }
+}
+
 #[inline(always)]
 fn anywhere_transition(byte: u8) -> Option<State> {
     match byte {
@@ -171,6 +229,19 @@ fn anywhere_transition(byte: u8) -> Option<State> {
     }
 }
 
+/// The numeric `Ps` of an OSC string, `None` when empty, not a number or too big
+fn osc_command(bytes: &[u8]) -> Option<u16> {
+    if bytes.is_empty() {
+        return None;
+    }
+    bytes.iter().try_fold(0u16, |n, &b| {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        n.checked_mul(10)?.checked_add((b - b'0') as u16)
+    })
+}
+
 /// Return the first param (or `default` when absent / zero).
 /// Most CSI sequences treat an omitted or zero param as meaning "1".
 #[inline(always)]
@@ -186,18 +257,31 @@ impl Parser {
         Self {
             state: State::Ground,
             params: SmallVec::new(),
+            groups: SmallVec::new(),
             intermediates: SmallVec::new(),
             current_param: 0,
+            current_group: SmallVec::new(),
             osc_buffer: Vec::new(),
+            osc_overflow: false,
+            utf8_remaining: 0,
         }
     }
 
     fn handle_esc<H: Handler>(&mut self, final_byte: u8, handler: &mut H) {
         match self.intermediates.first() {
             // ESC <intermediate> <final> – two-character escape sequences
-            Some(&b'(') | Some(&b')') | Some(&b'*') | Some(&b'+') => {
-                // Character-set designation – ignored for now; a full
-                // implementation would update the G0/G1/G2/G3 slot.
+            Some(&slot @ (b'(' | b')' | b'*' | b'+')) => {
+                let slot = match slot {
+                    b'(' => CharsetSlot::G0,
+                    b')' => CharsetSlot::G1,
+                    b'*' => CharsetSlot::G2,
+                    _ => CharsetSlot::G3,
+                };
+                if let Some(charset) =
+                    Charset::from_designation(self.intermediates.get(1).copied(), final_byte)
+                {
+                    handler.designate_charset(slot, charset);
+                }
             }
             Some(&b'#') => {
                 match final_byte {
@@ -226,12 +310,17 @@ impl Parser {
                     b'E' => handler.next_line_esc(),
                     b'H' => handler.set_tab_stop(),
                     b'M' => handler.reverse_index(),
-                    b'c' => handler.soft_reset(), // RIS – reset to initial state
-                    b'n' => { /* LS2 – locking shift G2 */ }
-                    b'o' => { /* LS3 – locking shift G3 */ }
+                    b'c' => handler.full_reset(), // RIS – reset to initial state
+                    b'n' => handler.locking_shift(CharsetSlot::G2),
+                    b'o' => handler.locking_shift(CharsetSlot::G3),
+                    b'N' => handler.single_shift(CharsetSlot::G2),
+                    b'O' => handler.single_shift(CharsetSlot::G3),
+                    // GR only matters to 8-bit characters, which are UTF-8 here
                     b'|' => { /* LS3R */ }
                     b'}' => { /* LS2R */ }
                     b'~' => { /* LS1R */ }
+                    // ST, the string it ends was dispatched when its ESC arrived
+                    b'\\' => {}
                     _ => {
                         eprintln!("[parser] unhandled ESC {:?}", final_byte as char);
                     }
@@ -272,6 +361,7 @@ impl Parser {
             b'I' => handler.cursor_forward_tab(p(&self.params, 0, 1)),
             b'Z' => handler.cursor_backward_tab(p(&self.params, 0, 1)),
             b'g' => handler.clear_tab_stop(p(&self.params, 0, 0)),
+            b'W' if inter == Some(b'?') && p(&self.params, 0, 0) == 5 => handler.reset_tab_stops(),
 
             // ── Erase ─────────────────────────────────────────────────────
             b'J' => {
@@ -297,12 +387,16 @@ impl Parser {
             }
 
             // ── SGR ───────────────────────────────────────────────────────
+            b'm' if inter == Some(b'>') => {
+                let value = (self.params.len() > 1).then(|| self.params[1]);
+                handler.set_modify_keys(p(&self.params, 0, 0), value);
+            }
             b'm' => {
                 // Empty params is a valid SGR 0 (reset) – do not panic.
-                if self.params.is_empty() {
-                    self.params.push(0);
+                if self.groups.is_empty() {
+                    self.groups.push(SmallVec::from_slice(&[0]));
                 }
-                handler.char_attributes(&self.params);
+                handler.char_attributes(&self.groups);
             }
 
             // ── Modes ─────────────────────────────────────────────────────
@@ -329,6 +423,7 @@ impl Parser {
             // ── Cursor style / state ──────────────────────────────────────
             b'q' => match inter {
                 Some(b' ') => handler.set_cursor_style(p(&self.params, 0, 0)),
+                Some(b'>') => handler.report_version(),
                 _ => { /* DECLL – load LEDs */ }
             },
             b'r' => match inter {
@@ -341,8 +436,21 @@ impl Parser {
                     )
                 }
             },
-            b's' => handler.save_cursor_position(),
-            b'u' => handler.restore_cursor_position(),
+            // DECSLRM or SCOSC, only the handler knows whether DECLRMM is set. Handler must
+            // clamp to screen width.
+            b's' => {
+                handler.set_left_right_margins(p(&self.params, 0, 1), p(&self.params, 1, u16::MAX))
+            }
+            b'u' => match inter {
+                None => handler.restore_cursor_position(),
+                Some(b'>') => handler.push_keyboard_flags(p(&self.params, 0, 0)),
+                Some(b'<') => handler.pop_keyboard_flags(p(&self.params, 0, 1)),
+                Some(b'=') => {
+                    handler.set_keyboard_flags(p(&self.params, 0, 0), p(&self.params, 1, 1))
+                }
+                Some(b'?') => handler.report_keyboard_flags(),
+                _ => {}
+            },
 
             // ── Window ops ────────────────────────────────────────────────
             b't' => handler.window_ops(&self.params),
@@ -351,7 +459,11 @@ impl Parser {
             b'p' => match inter {
                 Some(b'!') => handler.soft_reset(), // DECSTR
                 Some(b'"') => { /* DECSCL – conformance level */ }
-                Some(b'$') => { /* DECRQM  – request mode */ }
+                Some(b'$') => handler.request_mode(p(&self.params, 0, 0), false),
+                Some(b'?') if self.intermediates.contains(&b'$') => {
+                    // DECRQM – request DEC private mode
+                    handler.request_mode(p(&self.params, 0, 0), true)
+                }
                 _ => {}
             },
 
@@ -394,17 +506,45 @@ impl Parser {
         }
 
         self.params.clear();
+
+        self.groups.clear();
+
+        self.current_group.clear();
         self.intermediates.clear();
         self.current_param = 0;
     }
 
+    fn osc_start(&mut self) {
+        // A string cancelled by CAN or SUB is never dispatched, don't let it leak into this one
+        self.osc_buffer.clear();
+        self.osc_overflow = false;
+        self.utf8_remaining = 0;
+        self.state = State::OscString;
+    }
+
     fn osc_put(&mut self, byte: u8) {
-        self.osc_buffer.push(byte);
+        if self.osc_buffer.len() < MAX_OSC_LEN {
+            self.osc_buffer.push(byte);
+        } else {
+            self.osc_overflow = true;
+        }
     }
 
     fn handle_osc<H: Handler>(&mut self, handler: &mut H) {
-        handler.handle_osc(&self.osc_buffer);
+        if !self.osc_overflow {
+            let buffer = &self.osc_buffer;
+            let split = buffer
+                .iter()
+                .position(|&b| b == b';')
+                .unwrap_or(buffer.len());
+            if let Some(command) = osc_command(&buffer[..split]) {
+                handler.handle_osc(command, buffer.get(split + 1..).unwrap_or(&[]));
+            }
+        }
         self.osc_buffer.clear();
+        // Don't hold on to what a huge string (OSC 52) needed
+        self.osc_buffer.shrink_to(4096);
+        self.osc_overflow = false;
     }
 
     pub fn consume<H: Handler>(&mut self, byte: u8, handler: &mut H) {
@@ -413,8 +553,13 @@ impl Parser {
                 0x07 => {
                     self.handle_osc(handler);
                     self.state = State::Ground;
+                    self.utf8_remaining = 0;
                     return;
                 }
+                // ESC starts the 7-bit ST, the string ends whatever follows. The ESC itself
+                // goes on to the escape state below, where a `\` after it is taken as the
+                // end of ST and ignored.
+                0x1b => self.handle_osc(handler),
                 _ => {}
             },
             _ => {}
@@ -429,14 +574,32 @@ impl Parser {
             return;
         }
 
+        // Inside a UTF-8 sequence, printed or in an OSC string, 0x90, 0x9b, 0x9c and 0x9d are
+        // continuation bytes rather than C1 controls
+        if self.utf8_remaining > 0 && (0x80..=0xbf).contains(&byte) {
+            match self.state {
+                State::Ground => handler.accumluate_utf8(byte),
+                State::OscString => self.osc_put(byte),
+                _ => {}
+            }
+            self.utf8_remaining -= 1;
+            return;
+        }
         // Anywhere transitions take priority over the current state
         if let Some(new_state) = anywhere_transition(byte) {
+            self.utf8_remaining = 0;
             // Entering Escape from any state: clear accumulated data
             if matches!(new_state, State::Escape) {
                 self.params.clear();
+                self.groups.clear();
+                self.current_group.clear();
                 self.intermediates.clear();
                 self.current_param = 0;
             }
+            if matches!(new_state, State::OscString) {
+                self.osc_start();
+                return;
+            }
             self.state = new_state;
             return;
         }
@@ -444,8 +607,15 @@ impl Parser {
         match self.state {
             // ── Ground ────────────────────────────────────────────────────
             State::Ground => match byte {
-                0x20..=0x7f => handler.accumluate_utf8(byte),
-                0x80..=0xff => handler.accumluate_utf8(byte), // high bytes (UTF-8 continuations / GR)
+                0x20..=0x7f => {
+                    self.utf8_remaining = 0;
+                    handler.accumluate_utf8(byte);
+                }
+                // high bytes (UTF-8 leads and stray continuations / GR)
+                0x80..=0xff => {
+                    self.utf8_remaining = utf8_continuations(byte);
+                    handler.accumluate_utf8(byte);
+                }
                 _ => {}
             },
 
@@ -455,11 +625,13 @@ impl Parser {
                     // '['
                     self.state = State::CsiEntry;
                     self.params.clear();
+                    self.groups.clear();
+                    self.current_group.clear();
                     self.intermediates.clear();
                     self.current_param = 0;
                 }
-                0x5d => self.state = State::OscString, // ']'
-                0x50 => self.state = State::DcsEntry,  // 'P'
+                0x5d => self.osc_start(),             // ']'
+                0x50 => self.state = State::DcsEntry, // 'P'
                 0x20..=0x2f => {
                     self.intermediates.push(byte);
                     self.state = State::EscapeIntermediate;
@@ -498,16 +670,14 @@ impl Parser {
                     self.state = State::Ground;
                     self.handle_osc(handler);
                 }
-                0x1b => {
-                    // ESC \ (ST via two bytes) — the ESC byte itself is
-                    // caught by anywhere_transition above and transitions
-                    // us to State::Escape; the next byte '\\' = 0x5c will
-                    // be a no-op ESC final that we can detect there.
-                    // Handle as terminator right here for safety.
-                    self.state = State::Ground;
-                    self.handle_osc(handler);
+                0x20..=0x7f => {
+                    self.utf8_remaining = 0;
+                    self.osc_put(byte);
+                }
+                0x80..=0xff => {
+                    self.utf8_remaining = utf8_continuations(byte);
+                    self.osc_put(byte);
                 }
-                0x20..=0x7f | 0x80..=0xff => self.osc_put(byte),
                 _ => { /* ignore other control bytes inside OSC */ }
             },
 
@@ -517,8 +687,7 @@ impl Parser {
                 0x40..=0x7e => {
                     // Push the (zero) current_param so handle_csi sees at
                     // least one entry for sequences that need params[0].
-                    self.params.push(self.current_param);
-                    self.current_param = 0;
+                    self.push_param();
                     self.state = State::Ground;
                     self.handle_csi(byte, handler);
                 }
@@ -526,7 +695,11 @@ impl Parser {
                     self.collect_intermediate(byte);
                     self.state = State::CsiIntermediate;
                 }
-                0x3a => self.state = State::CsiIgnore, // ':' — sub-params not supported
+                0x3a => {
+                    // ':' before any digit — implicit leading 0 with sub-params
+                    self.push_sub_param();
+                    self.state = State::CsiParam;
+                }
                 0x30..=0x39 => {
                     // First digit of first parameter
                     self.current_param = (byte - b'0') as u16;
@@ -534,8 +707,7 @@ impl Parser {
                 }
                 0x3b => {
                     // ';' before any digit — implicit leading 0
-                    self.params.push(0);
-                    self.current_param = 0;
+                    self.push_param();
                     self.state = State::CsiParam;
                 }
                 0x3c..=0x3f => {
@@ -554,18 +726,16 @@ impl Parser {
                 0x30..=0x39 => self.collect_param(byte),
                 0x3b => {
                     // ';' – parameter separator
-                    self.params.push(self.current_param);
-                    self.current_param = 0;
+                    self.push_param();
                 }
-                0x3a => self.state = State::CsiIgnore, // ':' sub-param
+                0x3a => self.push_sub_param(), // ':' sub-param separator
                 0x3c..=0x3f => self.state = State::CsiIgnore, // private after params
                 0x20..=0x2f => {
                     self.collect_intermediate(byte);
                     self.state = State::CsiIntermediate;
                 }
                 0x40..=0x7e => {
-                    self.params.push(self.current_param);
-                    self.current_param = 0;
+                    self.push_param();
                     self.state = State::Ground;
                     self.handle_csi(byte, handler);
                 }
@@ -580,8 +750,7 @@ impl Parser {
                 0x20..=0x2f => self.collect_intermediate(byte),
                 0x30..=0x3f => self.state = State::CsiIgnore, // digit after intermediate
                 0x40..=0x7e => {
-                    self.params.push(self.current_param);
-                    self.current_param = 0;
+                    self.push_param();
                     self.state = State::Ground;
                     self.handle_csi(byte, handler);
                 }
@@ -593,6 +762,8 @@ impl Parser {
                 0x40..=0x7e => {
                     // Consume until final byte, then return to ground
                     self.params.clear();
+                    self.groups.clear();
+                    self.current_group.clear();
                     self.intermediates.clear();
                     self.current_param = 0;
                     self.state = State::Ground;
@@ -616,8 +787,7 @@ impl Parser {
             State::DcsParam => match byte {
                 0x30..=0x39 => self.collect_param(byte),
                 0x3b => {
-                    self.params.push(self.current_param);
-                    self.current_param = 0;
+                    self.push_param();
                 }
                 0x3a | 0x3c..=0x3f => self.state = State::DcsIgnore,
                 0x20..=0x2f => {
@@ -642,6 +812,8 @@ impl Parser {
                     // ST or ESC \ — end of DCS; ESC is also caught by
                     // anywhere_transition, so 0x9c handles the 8-bit path.
                     self.params.clear();
+                    self.groups.clear();
+                    self.current_group.clear();
                     self.intermediates.clear();
                     self.current_param = 0;
                     self.state = State::Ground;
@@ -657,6 +829,21 @@ impl Parser {
         }
     }
 
+    /// Finishes the parameter being collected along with any sub-parameters.
+    #[inline(always)]
+    fn push_param(&mut self) {
+        self.current_group.push(self.current_param);
+        self.current_param = 0;
+        self.params.push(self.current_group[0]);
+        self.groups.push(std::mem::take(&mut self.current_group));
+    }
+
+    #[inline(always)]
+    fn push_sub_param(&mut self) {
+        self.current_group.push(self.current_param);
+        self.current_param = 0;
+    }
+
     #[inline(always)]
     pub fn collect_param(&mut self, byte: u8) {
         self.current_param = self
@@ -1,6 +1,9 @@
 #[cfg(test)]
 mod tests {
-    use crate::ansii::{Handler, Parser, State};
+    use crate::ansii::{
+        Handler, MAX_OSC_LEN, ParamGroup, Parser, State,
+        details::{Attributes, Charset, CharsetSlot, Color, Intensity, Rgb, Underline},
+    };
     use smallvec::SmallVec;
 
     // Every method records its call as a `Call` variant so tests can assert
@@ -29,26 +32,39 @@ mod tests {
         ScrollUp(u16),
         ScrollDown(u16),
         SetScrollingRegion(u16, u16),
-        CharAttributes(Vec<u16>),
+        SetLeftRightMargins(u16, u16),
+        CharAttributes(Vec<Vec<u16>>),
         SetTabStop,
         ClearTabStop(u16),
         CursorForwardTab(u16),
         CursorBackwardTab(u16),
+        ResetTabStops,
+        DesignateCharset(CharsetSlot, Charset),
+        LockingShift(CharsetSlot),
+        SingleShift(CharsetSlot),
         SetMode(Vec<u16>, bool),
         ResetMode(Vec<u16>, bool),
         PrimaryDeviceAttributes,
         SecondaryDeviceAttributes,
         DeviceStatusReport(u16),
+        RequestMode(u16, bool),
+        ReportVersion,
         SoftReset,
+        FullReset,
         SetCursorStyle(u16),
         WindowOps(Vec<u16>),
         Index,
         ReverseIndex,
         NextLineEsc,
         SetKeypadApplicationMode,
+        SetModifyKeys(u16, Option<u16>),
+        PushKeyboardFlags(u16),
+        PopKeyboardFlags(u16),
+        SetKeyboardFlags(u16, u16),
+        ReportKeyboardFlags,
         UnsetKeypadApplicationMode,
         Execute(u8),
-        HandleOsc(Vec<u8>),
+        HandleOsc(u16, Vec<u8>),
         AccumluateUtf8(u8),
         Bell,
     }
@@ -122,8 +138,12 @@ mod tests {
         fn set_scrolling_region(&mut self, t: u16, b: u16) {
             self.calls.push(Call::SetScrollingRegion(t, b));
         }
-        fn char_attributes(&mut self, p: &SmallVec<[u16; 8]>) {
-            self.calls.push(Call::CharAttributes(p.to_vec()));
+        fn set_left_right_margins(&mut self, l: u16, r: u16) {
+            self.calls.push(Call::SetLeftRightMargins(l, r));
+        }
+        fn char_attributes(&mut self, p: &[ParamGroup]) {
+            self.calls
+                .push(Call::CharAttributes(p.iter().map(|g| g.to_vec()).collect()));
         }
         fn set_tab_stop(&mut self) {
             self.calls.push(Call::SetTabStop);
@@ -137,6 +157,18 @@ mod tests {
         fn cursor_backward_tab(&mut self, n: u16) {
             self.calls.push(Call::CursorBackwardTab(n));
         }
+        fn reset_tab_stops(&mut self) {
+            self.calls.push(Call::ResetTabStops);
+        }
+        fn designate_charset(&mut self, slot: CharsetSlot, charset: Charset) {
+            self.calls.push(Call::DesignateCharset(slot, charset));
+        }
+        fn locking_shift(&mut self, slot: CharsetSlot) {
+            self.calls.push(Call::LockingShift(slot));
+        }
+        fn single_shift(&mut self, slot: CharsetSlot) {
+            self.calls.push(Call::SingleShift(slot));
+        }
         fn set_mode(&mut self, p: &SmallVec<[u16; 8]>, priv_: bool) {
             self.calls.push(Call::SetMode(p.to_vec(), priv_));
         }
@@ -152,9 +184,18 @@ mod tests {
         fn device_status_report(&mut self, p: u16) {
             self.calls.push(Call::DeviceStatusReport(p));
         }
+        fn request_mode(&mut self, m: u16, priv_: bool) {
+            self.calls.push(Call::RequestMode(m, priv_));
+        }
+        fn report_version(&mut self) {
+            self.calls.push(Call::ReportVersion);
+        }
         fn soft_reset(&mut self) {
             self.calls.push(Call::SoftReset);
         }
+        fn full_reset(&mut self) {
+            self.calls.push(Call::FullReset);
+        }
         fn set_cursor_style(&mut self, s: u16) {
             self.calls.push(Call::SetCursorStyle(s));
         }
@@ -176,11 +217,26 @@ mod tests {
         fn unset_keypad_application_mode(&mut self) {
             self.calls.push(Call::UnsetKeypadApplicationMode);
         }
+        fn set_modify_keys(&mut self, resource: u16, value: Option<u16>) {
+            self.calls.push(Call::SetModifyKeys(resource, value));
+        }
+        fn push_keyboard_flags(&mut self, flags: u16) {
+            self.calls.push(Call::PushKeyboardFlags(flags));
+        }
+        fn pop_keyboard_flags(&mut self, n: u16) {
+            self.calls.push(Call::PopKeyboardFlags(n));
+        }
+        fn set_keyboard_flags(&mut self, flags: u16, mode: u16) {
+            self.calls.push(Call::SetKeyboardFlags(flags, mode));
+        }
+        fn report_keyboard_flags(&mut self) {
+            self.calls.push(Call::ReportKeyboardFlags);
+        }
         fn execute(&mut self, b: u8) {
             self.calls.push(Call::Execute(b));
         }
-        fn handle_osc(&mut self, v: &Vec<u8>) {
-            self.calls.push(Call::HandleOsc(v.clone()));
+        fn handle_osc(&mut self, command: u16, payload: &[u8]) {
+            self.calls.push(Call::HandleOsc(command, payload.to_vec()));
         }
         fn accumluate_utf8(&mut self, b: u8) {
             self.calls.push(Call::AccumluateUtf8(b));
@@ -389,7 +445,10 @@ mod tests {
     fn many_params_sgr() {
         // SGR: ESC [ 1 ; 32 ; 40 m
         let m = feed(b"\x1b[1;32;40m");
-        assert_eq!(m.calls, vec![Call::CharAttributes(vec![1, 32, 40])]);
+        assert_eq!(
+            m.calls,
+            vec![Call::CharAttributes(vec![vec![1], vec![32], vec![40]])]
+        );
     }
 
     #[test]
@@ -534,6 +593,20 @@ mod tests {
         assert!(matches!(m.calls[0], Call::SetScrollingRegion(1, _)));
     }
 
+    #[test]
+    fn set_left_right_margins() {
+        assert_eq!(
+            feed(b"\x1b[5;20s").calls,
+            vec![Call::SetLeftRightMargins(5, 20)]
+        );
+    }
+
+    #[test]
+    fn set_left_right_margins_default_right() {
+        let m = feed(b"\x1b[5s");
+        assert!(matches!(m.calls[0], Call::SetLeftRightMargins(5, _)));
+    }
+
     // ─────────────────────────────────────────────────────────────────────────
     // 8. SGR (Select Graphic Rendition)
     // ─────────────────────────────────────────────────────────────────────────
@@ -542,19 +615,25 @@ mod tests {
     fn sgr_reset_empty() {
         // ESC [ m  — no params = SGR 0 reset, must NOT panic
         let m = feed(b"\x1b[m");
-        assert_eq!(m.calls, vec![Call::CharAttributes(vec![0])]);
+        assert_eq!(m.calls, vec![Call::CharAttributes(vec![vec![0]])]);
     }
 
     #[test]
     fn sgr_bold() {
-        assert_eq!(feed(b"\x1b[1m").calls, vec![Call::CharAttributes(vec![1])]);
+        assert_eq!(
+            feed(b"\x1b[1m").calls,
+            vec![Call::CharAttributes(vec![vec![1]])]
+        );
     }
 
     #[test]
     fn sgr_256_color_fg() {
         // ESC [ 38 ; 5 ; 200 m
         let m = feed(b"\x1b[38;5;200m");
-        assert_eq!(m.calls, vec![Call::CharAttributes(vec![38, 5, 200])]);
+        assert_eq!(
+            m.calls,
+            vec![Call::CharAttributes(vec![vec![38], vec![5], vec![200]])]
+        );
     }
 
     #[test]
@@ -563,7 +642,13 @@ mod tests {
         let m = feed(b"\x1b[38;2;255;128;0m");
         assert_eq!(
             m.calls,
-            vec![Call::CharAttributes(vec![38, 2, 255, 128, 0])]
+            vec![Call::CharAttributes(vec![
+                vec![38],
+                vec![2],
+                vec![255],
+                vec![128],
+                vec![0]
+            ])]
         );
     }
 
@@ -619,13 +704,38 @@ mod tests {
         assert_eq!(feed(b"\x1b[5n").calls, vec![Call::DeviceStatusReport(5)]);
     }
 
+    #[test]
+    fn request_ansi_mode() {
+        // ESC [ 4 $ p  — DECRQM for IRM
+        assert_eq!(feed(b"\x1b[4$p").calls, vec![Call::RequestMode(4, false)]);
+    }
+
+    #[test]
+    fn request_private_mode() {
+        // ESC [ ? 2004 $ p  — DECRQM for bracketed paste
+        assert_eq!(
+            feed(b"\x1b[?2004$p").calls,
+            vec![Call::RequestMode(2004, true)]
+        );
+    }
+
+    #[test]
+    fn report_version() {
+        // ESC [ > q  — XTVERSION
+        assert_eq!(feed(b"\x1b[>q").calls, vec![Call::ReportVersion]);
+    }
+
     // ─────────────────────────────────────────────────────────────────────────
     // 11. Cursor save / restore
     // ─────────────────────────────────────────────────────────────────────────
 
     #[test]
-    fn csi_save_cursor_s() {
-        assert_eq!(feed(b"\x1b[s").calls, vec![Call::SaveCursorPosition]);
+    fn csi_s_is_left_to_the_handler() {
+        // SCOSC or DECSLRM with default margins, depending on DECLRMM
+        assert_eq!(
+            feed(b"\x1b[s").calls,
+            vec![Call::SetLeftRightMargins(1, u16::MAX)]
+        );
     }
 
     #[test]
@@ -678,8 +788,67 @@ mod tests {
     }
 
     #[test]
-    fn esc_soft_reset_ris() {
-        assert_eq!(feed(b"\x1bc").calls, vec![Call::SoftReset]);
+    fn esc_designates_charsets() {
+        assert_eq!(
+            feed(b"\x1b(0\x1b)B\x1b*A\x1b+%5").calls,
+            vec![
+                Call::DesignateCharset(CharsetSlot::G0, Charset::DecSpecialGraphics),
+                Call::DesignateCharset(CharsetSlot::G1, Charset::Ascii),
+                Call::DesignateCharset(CharsetSlot::G2, Charset::Uk),
+                Call::DesignateCharset(CharsetSlot::G3, Charset::DecSupplemental),
+            ]
+        );
+        // Sets we don't have leave the slot alone
+        assert!(feed(b"\x1b(K").calls.is_empty());
+    }
+
+    #[test]
+    fn esc_shifts_charsets() {
+        assert_eq!(
+            feed(b"\x1bn\x1bo\x1bN\x1bO").calls,
+            vec![
+                Call::LockingShift(CharsetSlot::G2),
+                Call::LockingShift(CharsetSlot::G3),
+                Call::SingleShift(CharsetSlot::G2),
+                Call::SingleShift(CharsetSlot::G3),
+            ]
+        );
+    }
+
+    #[test]
+    fn modify_other_keys_is_not_sgr() {
+        assert_eq!(
+            feed(b"\x1b[>4;2m").calls,
+            vec![Call::SetModifyKeys(4, Some(2))]
+        );
+    }
+
+    #[test]
+    fn modify_other_keys_reset() {
+        assert_eq!(feed(b"\x1b[>4m").calls, vec![Call::SetModifyKeys(4, None)]);
+    }
+
+    #[test]
+    fn kitty_keyboard_push_and_pop() {
+        assert_eq!(feed(b"\x1b[>5u").calls, vec![Call::PushKeyboardFlags(5)]);
+        assert_eq!(feed(b"\x1b[>u").calls, vec![Call::PushKeyboardFlags(0)]);
+        assert_eq!(feed(b"\x1b[<u").calls, vec![Call::PopKeyboardFlags(1)]);
+        assert_eq!(feed(b"\x1b[<3u").calls, vec![Call::PopKeyboardFlags(3)]);
+    }
+
+    #[test]
+    fn kitty_keyboard_set_and_query() {
+        assert_eq!(feed(b"\x1b[=1u").calls, vec![Call::SetKeyboardFlags(1, 1)]);
+        assert_eq!(
+            feed(b"\x1b[=8;3u").calls,
+            vec![Call::SetKeyboardFlags(8, 3)]
+        );
+        assert_eq!(feed(b"\x1b[?u").calls, vec![Call::ReportKeyboardFlags]);
+    }
+
+    #[test]
+    fn esc_full_reset_ris() {
+        assert_eq!(feed(b"\x1bc").calls, vec![Call::FullReset]);
     }
 
     // ─────────────────────────────────────────────────────────────────────────
@@ -727,6 +896,14 @@ mod tests {
         assert_eq!(feed(b"\x1b[3g").calls, vec![Call::ClearTabStop(3)]);
     }
 
+    #[test]
+    fn decst8c_resets_tab_stops() {
+        assert_eq!(feed(b"\x1b[?5W").calls, vec![Call::ResetTabStops]);
+        // Other tab controls and a missing '?' are left alone
+        assert!(feed(b"\x1b[?2W").calls.is_empty());
+        assert!(feed(b"\x1b[5W").calls.is_empty());
+    }
+
     // ─────────────────────────────────────────────────────────────────────────
     // 16. OSC strings
     // ─────────────────────────────────────────────────────────────────────────
@@ -737,7 +914,7 @@ mod tests {
         let seq = b"\x1b]0;my title\x07";
         let m = feed(seq);
         println!("{:?}", m.calls);
-        assert_eq!(m.calls, vec![Call::HandleOsc(b"0;my title".to_vec())]);
+        assert_eq!(m.calls, vec![Call::HandleOsc(0, b"my title".to_vec())]);
     }
 
     #[test]
@@ -746,36 +923,112 @@ mod tests {
         let mut seq = b"\x1b]0;hello".to_vec();
         seq.push(0x9c);
         let m = feed(&seq);
-        assert_eq!(m.calls, vec![Call::HandleOsc(b"0;hello".to_vec())]);
+        assert_eq!(m.calls, vec![Call::HandleOsc(0, b"hello".to_vec())]);
+    }
+
+    #[test]
+    fn osc_terminated_by_7bit_st() {
+        let m = feed(b"\x1b]0;hello\x1b\\A");
+        assert_eq!(
+            m.calls,
+            vec![
+                Call::HandleOsc(0, b"hello".to_vec()),
+                Call::AccumluateUtf8(b'A')
+            ]
+        );
     }
 
     #[test]
-    fn osc_empty_payload() {
-        let m = feed(b"\x1b]\x07");
-        assert_eq!(m.calls, vec![Call::HandleOsc(vec![])]);
+    fn osc_without_command_is_dropped() {
+        assert_eq!(feed(b"\x1b]\x07").calls, vec![]);
+        assert_eq!(feed(b"\x1b];title\x07").calls, vec![]);
+        assert_eq!(feed(b"\x1b]one\x07").calls, vec![]);
+        // Too big for a u16
+        assert_eq!(feed(b"\x1b]99999;x\x07").calls, vec![]);
+    }
+
+    #[test]
+    fn osc_command_without_payload() {
+        let m = feed(b"\x1b]104\x07");
+        assert_eq!(m.calls, vec![Call::HandleOsc(104, vec![])]);
+    }
+
+    #[test]
+    fn osc_payload_keeps_later_semicolons() {
+        let m = feed(b"\x1b]2;a;b;\x07");
+        assert_eq!(m.calls, vec![Call::HandleOsc(2, b"a;b;".to_vec())]);
     }
 
     #[test]
     fn osc_buffer_cleared_between_sequences() {
-        let m = feed(b"\x1b]one\x07\x1b]two\x07");
+        let m = feed(b"\x1b]1;one\x07\x1b]2;two\x07");
         assert_eq!(
             m.calls,
             vec![
-                Call::HandleOsc(b"one".to_vec()),
-                Call::HandleOsc(b"two".to_vec()),
+                Call::HandleOsc(1, b"one".to_vec()),
+                Call::HandleOsc(2, b"two".to_vec()),
             ]
         );
     }
 
+    #[test]
+    fn cancelled_osc_does_not_leak_into_the_next() {
+        let m = feed(b"\x1b]0;abc\x18\x1b]2;x\x07");
+        assert_eq!(m.calls, vec![Call::HandleOsc(2, b"x".to_vec())]);
+    }
+
+    #[test]
+    fn osc_over_the_length_limit_is_dropped() {
+        let mut seq = b"\x1b]2;".to_vec();
+        seq.resize(seq.len() + MAX_OSC_LEN, b'a');
+        seq.extend_from_slice(b"\x07\x1b]2;ok\x07");
+        let m = feed(&seq);
+        assert_eq!(m.calls, vec![Call::HandleOsc(2, b"ok".to_vec())]);
+    }
+
     // ─────────────────────────────────────────────────────────────────────────
-    // 17. CSI ignore path (sub-params / invalid)
+    // 17. Colon sub-parameters and the CSI ignore path
     // ─────────────────────────────────────────────────────────────────────────
 
     #[test]
-    fn csi_colon_sub_param_is_ignored() {
-        // ESC [ 38 : 2 m  — colon sub-params not supported, sequence ignored
-        let m = feed(b"\x1b[38:2m");
-        // No CharAttributes call should have been dispatched
+    fn csi_colon_sub_params_are_grouped() {
+        // ESC [ 38 : 2 : : 255 : 128 : 0 m  — truecolor with empty colorspace id
+        let m = feed(b"\x1b[38:2::255:128:0m");
+        assert_eq!(
+            m.calls,
+            vec![Call::CharAttributes(vec![vec![38, 2, 0, 255, 128, 0]])]
+        );
+    }
+
+    #[test]
+    fn csi_colon_and_semicolon_mixed() {
+        let m = feed(b"\x1b[1;4:3;58:5:196m");
+        assert_eq!(
+            m.calls,
+            vec![Call::CharAttributes(vec![
+                vec![1],
+                vec![4, 3],
+                vec![58, 5, 196]
+            ])]
+        );
+    }
+
+    #[test]
+    fn csi_leading_colon_implies_zero() {
+        let m = feed(b"\x1b[:3m");
+        assert_eq!(m.calls, vec![Call::CharAttributes(vec![vec![0, 3]])]);
+    }
+
+    #[test]
+    fn csi_sub_params_do_not_change_main_params() {
+        // Non-SGR sequences only look at the leading value of each group
+        let m = feed(b"\x1b[5:1;10:2H");
+        assert_eq!(m.calls, vec![Call::CursorPosition(5, 10)]);
+    }
+
+    #[test]
+    fn csi_private_marker_after_params_is_ignored() {
+        let m = feed(b"\x1b[1?m");
         assert!(!m.calls.iter().any(|c| matches!(c, Call::CharAttributes(_))));
     }
 
@@ -783,8 +1036,8 @@ mod tests {
     fn csi_ignore_does_not_leak_into_next_sequence() {
         // After an ignored sequence the parser must return to Ground and
         // handle the next sequence cleanly.
-        let m = feed(b"\x1b[38:2m\x1b[1m");
-        assert_eq!(m.calls, vec![Call::CharAttributes(vec![1])]);
+        let m = feed(b"\x1b[1?m\x1b[1m");
+        assert_eq!(m.calls, vec![Call::CharAttributes(vec![vec![1]])]);
     }
 
     // ─────────────────────────────────────────────────────────────────────────
@@ -879,6 +1132,137 @@ mod tests {
         seq.extend_from_slice(b"0;title");
         seq.push(0x9c); // ST
         let m = feed(&seq);
-        assert_eq!(m.calls, vec![Call::HandleOsc(b"0;title".to_vec())]);
+        assert_eq!(m.calls, vec![Call::HandleOsc(0, b"title".to_vec())]);
+    }
+
+    #[test]
+    fn c1_bytes_inside_utf8_stay_in_osc_strings() {
+        // 日本 is e6 97 a5 e6 9c ac, 0x9c would otherwise end the string
+        let mut seq = b"\x1b]0;".to_vec();
+        seq.extend_from_slice("日本".as_bytes());
+        seq.extend_from_slice(b"\x1b\\");
+        let m = feed(&seq);
+        assert_eq!(
+            m.calls,
+            vec![Call::HandleOsc(0, "日本".as_bytes().to_vec())]
+        );
+        // The 8-bit ST still ends it when it isn't inside a character
+        let mut seq = vec![0x9du8];
+        seq.extend_from_slice("0;❤".as_bytes());
+        seq.push(0x9c);
+        let m = feed(&seq);
+        assert_eq!(m.calls, vec![Call::HandleOsc(0, "❤".as_bytes().to_vec())]);
+    }
+
+    #[test]
+    fn c1_bytes_inside_utf8_are_printed() {
+        // U+2764 is E2 9D A4, U+1F6C0 is F0 9F 9B 80
+        let m = feed("❤🛀".as_bytes());
+        let bytes: Vec<Call> = "❤🛀".bytes().map(Call::AccumluateUtf8).collect();
+        assert_eq!(m.calls, bytes);
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // 22. SGR application on Attributes
+    // ─────────────────────────────────────────────────────────────────────────
+
+    /// Runs `input` through the parser and applies every SGR to fresh attributes.
+    fn sgr(input: &[u8]) -> Attributes {
+        let mut attrs = Attributes::default();
+        for call in feed(input).calls {
+            if let Call::CharAttributes(groups) = call {
+                let groups: Vec<ParamGroup> =
+                    groups.iter().map(|g| SmallVec::from_slice(g)).collect();
+                attrs.apply_sgr(&groups);
+            }
+        }
+        attrs
+    }
+
+    #[test]
+    fn sgr_basic_flags() {
+        let a = sgr(b"\x1b[1;3;5;7;8;9;53m");
+        assert_eq!(a.intensity, Intensity::Bold);
+        assert!(a.italic && a.blink && a.inverse && a.hidden && a.strike && a.overline);
+    }
+
+    #[test]
+    fn sgr_individual_resets() {
+        let a = sgr(b"\x1b[2;3;4;5;7;8;9;53m\x1b[22;23;24;25;27;28;29;55m");
+        assert_eq!(a, Attributes::default());
+    }
+
+    #[test]
+    fn sgr_zero_resets_everything() {
+        let a = sgr(b"\x1b[1;31;44;4m\x1b[0m");
+        assert_eq!(a, Attributes::default());
+    }
+
+    #[test]
+    fn sgr_underline_styles() {
+        assert_eq!(sgr(b"\x1b[4m").underline, Underline::Single);
+        assert_eq!(sgr(b"\x1b[21m").underline, Underline::Double);
+        assert_eq!(sgr(b"\x1b[4:3m").underline, Underline::Curly);
+        assert_eq!(sgr(b"\x1b[4:5m").underline, Underline::Dashed);
+        assert_eq!(sgr(b"\x1b[4;4:0m").underline, Underline::None);
+    }
+
+    #[test]
+    fn sgr_16_colors() {
+        let a = sgr(b"\x1b[31;42m");
+        assert_eq!(a.fg, Color::Indexed(1));
+        assert_eq!(a.bg, Color::Indexed(2));
+        let a = sgr(b"\x1b[97;100m");
+        assert_eq!(a.fg, Color::Indexed(15));
+        assert_eq!(a.bg, Color::Indexed(8));
+        let a = sgr(b"\x1b[97;100;39;49m");
+        assert_eq!((a.fg, a.bg), (Color::Default, Color::Default));
+    }
+
+    #[test]
+    fn sgr_256_colors_both_forms() {
+        assert_eq!(sgr(b"\x1b[38;5;200m").fg, Color::Indexed(200));
+        assert_eq!(sgr(b"\x1b[48:5:17m").bg, Color::Indexed(17));
+        assert_eq!(sgr(b"\x1b[58:5:196m").underline_color, Color::Indexed(196));
+    }
+
+    #[test]
+    fn sgr_truecolor_both_forms() {
+        let orange = Color::Rgb(Rgb::new(255, 128, 0));
+        assert_eq!(sgr(b"\x1b[38;2;255;128;0m").fg, orange);
+        assert_eq!(sgr(b"\x1b[38:2::255:128:0m").fg, orange);
+        assert_eq!(sgr(b"\x1b[48:2:255:128:0m").bg, orange);
+        assert_eq!(sgr(b"\x1b[58:2::255:128:0m").underline_color, orange);
+    }
+
+    #[test]
+    fn sgr_semicolon_color_consumes_its_params() {
+        // The 1 after the color must still be read as bold
+        let a = sgr(b"\x1b[38;5;3;1m");
+        assert_eq!(a.fg, Color::Indexed(3));
+        assert_eq!(a.intensity, Intensity::Bold);
+    }
+
+    #[test]
+    fn sgr_truncated_extended_color_is_ignored() {
+        let a = sgr(b"\x1b[38;2;255m");
+        assert_eq!(a.fg, Color::Default);
+    }
+
+    #[test]
+    fn palette_cube_and_greyscale() {
+        assert_eq!(Rgb::from_index(16), Rgb::new(0, 0, 0));
+        // 16 + 36 * 1 + 6 * 2 + 3
+        assert_eq!(Rgb::from_index(67), Rgb::new(95, 135, 175));
+        assert_eq!(Rgb::from_index(231), Rgb::new(255, 255, 255));
+        assert_eq!(Rgb::from_index(232), Rgb::new(8, 8, 8));
+        assert_eq!(Rgb::from_index(255), Rgb::new(238, 238, 238));
+    }
+
+    #[test]
+    fn palette_theme_colors() {
+        assert_eq!(Rgb::from_index(0), Rgb::new(0, 0, 0));
+        assert_eq!(Rgb::from_index(1), Rgb::new(205, 0, 0));
+        assert_eq!(Rgb::from_index(15), Rgb::new(255, 255, 255));
     }
 }
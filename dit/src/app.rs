@@ -1,4 +1,14 @@
-use std::{collections::HashSet, time::Instant};
+use std::{
+    collections::HashSet,
+    env,
+    ffi::OsStr,
+    fs,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::mpsc::{Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
 
 use atlas_gen::{
     allocator::ShelfAllocator, atlas::Atlas, cont_comb::SimpleContourCombiner,
@@ -9,66 +19,255 @@ use font_parser::TtfFont;
 use image::{ImageBuffer, Rgb};
 use libc::winsize;
 use math::lalg::Vec2;
+use nix::errno::Errno;
 use winit::{
     application::ApplicationHandler,
-    dpi::{LogicalSize, PhysicalSize},
-    event::{DeviceEvent, ElementState, WindowEvent},
-    keyboard::{KeyCode, PhysicalKey},
+    dpi::{LogicalPosition, LogicalSize, PhysicalSize},
+    event::{ElementState, MouseScrollDelta, StartCause, WindowEvent},
+    event_loop::{ControlFlow, EventLoopProxy},
+    keyboard::{Key, KeyCode, ModifiersState, NamedKey, PhysicalKey},
     window::Window,
 };
 
 use crate::{
-    ansii::Parser,
+    ansii::{Parser, details::TermMode},
+    clipboard::{Clipboard, ClipboardKind, ClipboardPolicy, MemoryClipboard, SystemClipboard},
+    input::{
+        keyboard::{self, KeyInput},
+        mouse::{self, MouseAction, MouseButton, MouseEvent},
+        paste,
+    },
     renderer::{shader::Vertex, vkapp::VkApplication},
-    screen::Screen,
-    shell::Pty,
+    screen::{
+        DEFAULT_SCROLLBACK_LINES, Screen, charset,
+        hints::{self, HintKind, HintMatch, HintRule},
+        hyperlink::{self, LinkId},
+        osc::{self, ClipboardRequest},
+        selection::SelectionKind,
+    },
+    shell::{Pty, PtyConfig, ShellConfig},
 };
+/// Events sent to the event loop from other threads
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UserEvent {
+    /// The pty reader queued output, or hung up
+    PtyOutput,
+}
+/// What happens once the shell exits
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ExitAction {
+    /// Close the window
+    #[default]
+    Close,
+    /// Keep the window open showing `[process exited N]`
+    Hold,
+    /// Start a new shell in the same window
+    Respawn,
+}
+/// A question shown in the title, answered by the next key press. `y` means yes.
+enum Prompt {
+    /// The child asked to read a clipboard through OSC 52
+    ClipboardRead(ClipboardKind),
+    /// A paste that would run commands as it goes, bracketed paste being off
+    Paste(String),
+}
+impl Prompt {
+    fn title(&self) -> String {
+        let question = match self {
+            Prompt::ClipboardRead(_) => "allow reading the clipboard?".to_string(),
+            Prompt::Paste(text) => format!("paste {} lines?", text.lines().count()),
+        };
+        format!("{} - {} [y/N]", WINDOW_TITLE, question)
+    }
+}
+/// What happens to the match picked in hints mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HintAction {
+    /// URLs go to the link opener, paths too once resolved, anything else is copied
+    Open,
+    Copy,
+    /// Typed into the child like a paste
+    Paste,
+}
 // In seconds
 
 pub struct Application {
     screen: Option<Screen>,
     pressed_keys: HashSet<KeyCode>,
-    input_buffer: String,
+    /// Encoded key presses waiting to be written to the pty
+    input_buffer: Vec<u8>,
+    modifiers: ModifiersState,
+    /// Pointer position over the grid in logical pixels
+    mouse_position: LogicalPosition<f64>,
+    /// Button held down, reported along with motion in button-event tracking
+    mouse_button: Option<MouseButton>,
+    /// Set while the left button drags out a selection instead of being reported
+    selecting: bool,
+    /// Clicks in quick succession on the same cell, 1 to 3
+    click_count: u8,
+    /// When and where the last left click landed
+    last_click: Instant,
+    last_click_cell: (usize, usize),
+    /// The system clipboards, or ones kept in memory without a graphical session
+    clipboard: Box<dyn Clipboard>,
+    /// Whether the child may read the clipboards through OSC 52
+    pub clipboard_read: ClipboardPolicy,
+    /// Ask before pasting text with line breaks while bracketed paste is off
+    pub confirm_multiline_paste: bool,
+    /// A question waiting for the user to answer
+    prompt: Option<Prompt>,
+    /// Command Ctrl+click runs with the URI of a hyperlink, and hints mode with a match
+    pub link_opener: String,
+    /// What hints mode looks for, earlier rules winning where matches overlap
+    pub hint_rules: Vec<HintRule>,
+    /// Letters hint labels are made of
+    pub hint_alphabet: String,
+    /// What to do with the match picked in hints mode
+    hint_action: Option<HintAction>,
+    /// Lines of history kept for the primary screen
+    pub scrollback_lines: usize,
     window: Option<Window>,
     last_frame: Instant,
     last_blink: Instant,
-    pty: Pty,
+    pty: Option<Pty>,
+    /// Output read by the pty thread, waiting to be parsed
+    pty_output: Option<Receiver<Vec<u8>>>,
+    pty_config: PtyConfig,
+    proxy: EventLoopProxy<UserEvent>,
+    /// Set once the pty hung up, until the child has been reaped
+    hung_up: bool,
+    pub exit_action: ExitAction,
     parser: Parser,
     vk_app: Option<VkApplication>,
     frame_count: u32,
 }
 const WIDTH: u32 = 1920;
 const HEIGHT: u32 = 1080;
+// Lines of history moved per notch of the mouse wheel
+const WHEEL_SCROLL_LINES: f32 = 3.0;
+// Longest gap between the clicks of a double or triple click
+const MULTI_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+const WINDOW_TITLE: &str = "dit";
+// Hyperlinks Ctrl+click hands to the opener, others could run whatever handler a scheme has
+const OPENABLE_SCHEMES: &[&str] = &["http", "https", "ftp", "file", "mailto"];
+// Time spent parsing output per frame, whatever is left waits for the next one so bursts
+// like `cat` on a big file don't freeze the window
+const PARSE_BUDGET: Duration = Duration::from_millis(8);
+// How long a blinking cursor stays shown, then hidden
+const BLINK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Answers an OSC 52 read with the content of `kind`
+fn reply_clipboard(clipboard: &mut dyn Clipboard, kind: ClipboardKind, screen: &mut Screen) {
+    // A clipboard that can't be read is as good as empty
+    let text = clipboard.load(kind).unwrap_or_default();
+    screen.reply(osc::clipboard_reply(kind, &text).as_bytes());
+}
+/// Writes as much of `pending` as the pty takes and drops it from the buffer, `false` once the
+/// pty is gone
+fn write_pending(pty: &mut Pty, pending: &mut Vec<u8>) -> bool {
+    match pty.write(pending) {
+        Ok(written) => {
+            pending.drain(..written);
+            true
+        }
+        Err(_) => false,
+    }
+}
+/// The pty window size matching the screen's grid
+fn pty_size(screen: &Screen) -> winsize {
+    let (width, height) = screen.pixel_size();
+    winsize {
+        ws_row: screen.y_size as u16,
+        ws_col: screen.col_size as u16,
+        ws_xpixel: width as u16,
+        ws_ypixel: height as u16,
+    }
+}
 
 impl Application {
-    fn update(&mut self) {
+    fn update(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
         self.frame_count += 1;
+        let blink_shown = self.blink_phases().is_multiple_of(2);
         let screen = self.screen.as_mut().unwrap();
-        if self.last_blink.elapsed().as_secs_f64() <= 0.5 {
-            screen.cursor.visible = true;
-        }
-        // only write if the input buffer is not empty
-        if !self.input_buffer.is_empty() {
-            self.pty.write(&self.input_buffer).unwrap();
-            for char in self.input_buffer.chars() {
-                screen.write_char(char);
+        screen.cursor.visible = blink_shown;
+        // Any error talking to the pty means the other side is gone
+        if let Some(pty) = self.pty.as_mut() {
+            // only write if the input buffer is not empty
+            if !self.input_buffer.is_empty() {
+                // Typing jumps back to the live screen
+                screen.scroll_to_bottom();
+                self.hung_up |= !write_pending(pty, &mut self.input_buffer);
+            }
+            if let Some(output) = self.pty_output.as_ref() {
+                let start = Instant::now();
+                let mut drained = false;
+                while !drained && start.elapsed() < PARSE_BUDGET {
+                    match output.try_recv() {
+                        Ok(chunk) => {
+                            for byte in chunk {
+                                self.parser.consume(byte, screen);
+                            }
+                        }
+                        Err(TryRecvError::Empty) => drained = true,
+                        Err(TryRecvError::Disconnected) => {
+                            self.hung_up = true;
+                            drained = true;
+                        }
+                    }
+                }
+                // Out of time with output possibly left, come back next frame
+                if !drained {
+                    self.window.as_ref().unwrap().request_redraw();
+                }
+            }
+            for request in std::mem::take(&mut screen.clipboard_requests) {
+                match request {
+                    ClipboardRequest::Store(kind, text) => {
+                        if let Err(e) = self.clipboard.store(kind, &text) {
+                            eprintln!("failed to set the clipboard: {}", e);
+                        }
+                    }
+                    ClipboardRequest::Load(kind) => match self.clipboard_read {
+                        ClipboardPolicy::Deny => {}
+                        ClipboardPolicy::Allow => {
+                            reply_clipboard(self.clipboard.as_mut(), kind, screen)
+                        }
+                        ClipboardPolicy::Ask => {
+                            let prompt = Prompt::ClipboardRead(kind);
+                            self.window.as_ref().unwrap().set_title(&prompt.title());
+                            self.prompt = Some(prompt);
+                        }
+                    },
+                }
             }
+            // A pending prompt keeps the title until it's answered
+            if self.prompt.is_none() && screen.take_title_changed() {
+                let title = screen.title().unwrap_or(WINDOW_TITLE);
+                self.window.as_ref().unwrap().set_title(title);
+            }
+            // Answer any queries the child made while parsing (DA, DSR, DECRQM, …), after
+            // whatever input is still waiting
+            if !screen.outbound.is_empty() {
+                self.input_buffer.append(&mut screen.outbound);
+                self.hung_up |= !write_pending(pty, &mut self.input_buffer);
+            }
+            // The pty is full, the rest goes out once the child has read some of it
+            if !self.input_buffer.is_empty() {
+                self.window.as_ref().unwrap().request_redraw();
+            }
+        } else {
+            // Nobody is listening anymore
             self.input_buffer.clear();
+            screen.outbound.clear();
         }
-        if self.pty.poll(0).unwrap() {
-            // Checks for data to read from
-            let mut buf = [0u8; 4096];
-            // Staging buffer is created to handle the bytes
-            let n = self.pty.read(&mut buf).unwrap();
-            // Read writes into said buffer
-            if n != 0 {
-                println!("recieved input: {}", String::from_utf8_lossy(&buf));
-                // Checks for if there are bytes to consume
-                for byte in &buf[..n] {
-                    self.parser.consume(*byte, screen);
-                }
+        if self.hung_up {
+            self.reap_child(event_loop);
+            // The child may take a moment to exit after closing the pty
+            if self.hung_up {
+                self.window.as_ref().unwrap().request_redraw();
             }
         }
+        let screen = self.screen.as_mut().unwrap();
         // at the end of the poll check if the mesh needs to be reupdated
         // if yes do so
         let vertex_size = size_of::<Vertex>();
@@ -90,24 +289,307 @@ impl Application {
             vk_app.write_to_device(&regions);
         }
     }
-    pub fn new() -> Self {
+    /// Halves of a cursor blink since the blink last restarted
+    fn blink_phases(&self) -> u32 {
+        (self.last_blink.elapsed().as_millis() / BLINK_INTERVAL.as_millis()) as u32
+    }
+    /// Runs the exit action once the hung up child can be reaped. A child that closed the pty
+    /// but hasn't exited yet is checked again on the next update.
+    fn reap_child(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        let Some(pty) = self.pty.as_mut() else {
+            return;
+        };
+        let status = match pty.try_wait() {
+            Ok(None) => return,
+            Ok(Some(status)) => Some(status),
+            // Already reaped elsewhere, the status is lost
+            Err(_) => None,
+        };
+        self.pty = None;
+        self.pty_output = None;
+        self.hung_up = false;
+        let message = match self.exit_action {
+            ExitAction::Close => {
+                event_loop.exit();
+                return;
+            }
+            ExitAction::Hold => match status {
+                Some(status) => format!("\r\n[process exited {}]", status.code()),
+                None => "\r\n[process exited]".to_string(),
+            },
+            ExitAction::Respawn => match self.spawn_pty() {
+                Ok(()) => return,
+                Err(e) => format!(
+                    "\r\n[failed to start {}: {}]",
+                    self.pty_config.shell.shell, e
+                ),
+            },
+        };
+        let screen = self.screen.as_mut().unwrap();
+        for byte in message.bytes() {
+            self.parser.consume(byte, screen);
+        }
+    }
+    /// Sends a mouse event to the child if it enabled tracking for it
+    fn report_mouse(&mut self, action: MouseAction) {
+        let Some(screen) = self.screen.as_ref() else {
+            return;
+        };
+        let LogicalPosition { x, y } = self.mouse_position;
+        let (col, row) = screen.cell_at(x, y);
+        let event = MouseEvent {
+            action,
+            col,
+            row,
+            x: x.max(0.0) as u32,
+            y: y.max(0.0) as u32,
+            mods: self.modifiers,
+        };
+        if let Some(bytes) = mouse::encode(&event, screen.modes) {
+            self.input_buffer.extend(bytes);
+            self.window.as_ref().unwrap().request_redraw();
+        }
+    }
+    /// Starts a selection under the pointer. Successive clicks select a word then a line, Alt
+    /// selects a block.
+    fn start_selection(&mut self) {
+        let Some(screen) = self.screen.as_mut() else {
+            return;
+        };
+        let LogicalPosition { x, y } = self.mouse_position;
+        let cell = screen.cell_at(x, y);
+        self.click_count =
+            if self.last_click.elapsed() < MULTI_CLICK_INTERVAL && self.last_click_cell == cell {
+                self.click_count % 3 + 1
+            } else {
+                1
+            };
+        (self.last_click, self.last_click_cell) = (Instant::now(), cell);
+        let kind = match self.click_count {
+            2 => SelectionKind::Word,
+            3 => SelectionKind::Line,
+            _ if self.modifiers.alt_key() => SelectionKind::Block,
+            _ => SelectionKind::Simple,
+        };
+        screen.start_selection(kind, cell.0, cell.1);
+        self.selecting = true;
+        self.window.as_ref().unwrap().request_redraw();
+    }
+    /// The hyperlink under the pointer while Ctrl is held
+    fn link_under_pointer(&self) -> Option<LinkId> {
+        if !self.modifiers.control_key() {
+            return None;
+        }
+        let screen = self.screen.as_ref()?;
+        let (col, row) = screen.cell_at(self.mouse_position.x, self.mouse_position.y);
+        screen.link_at(col, row)
+    }
+    fn update_hovered_link(&mut self) {
+        let link = self.link_under_pointer();
+        if let Some(screen) = self.screen.as_mut()
+            && screen.set_hovered_link(link)
+        {
+            self.window.as_ref().unwrap().request_redraw();
+        }
+    }
+    fn open_link(&self, link: LinkId) {
+        if let Some(link) = self
+            .screen
+            .as_ref()
+            .and_then(|screen| screen.hyperlink(link))
+        {
+            self.open_uri(&link.uri);
+        }
+    }
+    fn open_uri(&self, uri: &str) {
+        if !hyperlink::scheme(uri).is_some_and(|scheme| OPENABLE_SCHEMES.contains(&scheme.as_str()))
+        {
+            eprintln!("not opening {}: unsupported scheme", uri);
+            return;
+        }
+        self.run_opener(OsStr::new(uri));
+    }
+    /// Opens a file named in the output, relative to the shell's working directory
+    fn open_path(&self, text: &str) {
+        let mut path = text;
+        // Compiler diagnostics follow the path with `:line` or `:line:col`
+        for _ in 0..2 {
+            if let Some((rest, number)) = path.rsplit_once(':')
+                && !number.is_empty()
+                && number.bytes().all(|b| b.is_ascii_digit())
+            {
+                path = rest;
+            }
+        }
+        let mut resolved = PathBuf::from(path);
+        if let Some(rest) = path.strip_prefix("~/")
+            && let Some(home) = env::var_os("HOME")
+        {
+            resolved = PathBuf::from(home).join(rest);
+        } else if resolved.is_relative()
+            && let Some(directory) = self.shell_directory()
+        {
+            resolved = directory.join(resolved);
+        }
+        self.run_opener(resolved.as_os_str());
+    }
+    /// Where the shell is, as it last said through OSC 7 or else as the kernel knows it
+    fn shell_directory(&self) -> Option<PathBuf> {
+        if let Some(directory) = &self.screen.as_ref()?.working_directory
+            && directory.is_local()
+        {
+            return Some(directory.path.clone());
+        }
+        fs::read_link(format!("/proc/{}/cwd", self.pty.as_ref()?.child)).ok()
+    }
+    /// Hands a URI or path to the link opener
+    fn run_opener(&self, target: &OsStr) {
+        let child = Command::new(&self.link_opener)
+            .arg(target)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        match child {
+            // Waited on in the background so it doesn't linger as a zombie
+            Ok(mut child) => {
+                thread::spawn(move || child.wait());
+            }
+            Err(e) => eprintln!("failed to run {}: {}", self.link_opener, e),
+        }
+    }
+    /// Labels the matches on screen, the one picked gets `action`
+    fn start_hints(&mut self, action: HintAction) {
+        let Some(screen) = self.screen.as_mut() else {
+            return;
+        };
+        if screen.start_hints(&self.hint_rules, &self.hint_alphabet) > 0 {
+            self.hint_action = Some(action);
+            self.window.as_ref().unwrap().request_redraw();
+        }
+    }
+    /// A key press while hints mode is on: letters type a label, Escape leaves
+    fn hint_key(&mut self, action: HintAction, key: &Key) {
+        let Some(screen) = self.screen.as_mut() else {
+            return;
+        };
+        match key {
+            Key::Named(NamedKey::Escape) => screen.cancel_hints(),
+            Key::Named(NamedKey::Backspace) => screen.hint_backspace(),
+            Key::Character(text) => {
+                let mut chars = text.chars();
+                if let (Some(ch), None) = (chars.next(), chars.next())
+                    && let Some(hint) = screen.hint_input(ch.to_ascii_lowercase())
+                {
+                    self.use_hint(action, hint);
+                }
+            }
+            _ => {}
+        }
+        self.window.as_ref().unwrap().request_redraw();
+    }
+    fn use_hint(&mut self, action: HintAction, hint: HintMatch) {
+        match (action, hint.kind) {
+            (HintAction::Open, HintKind::Url) => self.open_uri(&hint.text),
+            (HintAction::Open, HintKind::Path) => self.open_path(&hint.text),
+            (HintAction::Open | HintAction::Copy, _) => {
+                if let Err(e) = self.clipboard.store(ClipboardKind::Clipboard, &hint.text) {
+                    eprintln!("failed to copy the hint: {}", e);
+                }
+            }
+            (HintAction::Paste, _) => {
+                let Some(screen) = self.screen.as_ref() else {
+                    return;
+                };
+                let bracketed = screen.modes.contains(TermMode::BRACKETED_PASTE);
+                self.input_buffer
+                    .extend(paste::encode(&hint.text, bracketed));
+            }
+        }
+    }
+    /// Copies the selected text into `kind`
+    fn copy_selection(&mut self, kind: ClipboardKind) {
+        let Some(text) = self.screen.as_ref().and_then(Screen::selection_text) else {
+            return;
+        };
+        if let Err(e) = self.clipboard.store(kind, &text) {
+            eprintln!("failed to copy the selection: {}", e);
+        }
+    }
+    /// Types the content of `kind` into the child
+    fn paste(&mut self, kind: ClipboardKind) {
+        let text = match self.clipboard.load(kind) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("failed to read the clipboard: {}", e);
+                return;
+            }
+        };
+        let Some(screen) = self.screen.as_ref() else {
+            return;
+        };
+        let bracketed = screen.modes.contains(TermMode::BRACKETED_PASTE);
+        if !bracketed && self.confirm_multiline_paste && paste::is_multiline(&text) {
+            let prompt = Prompt::Paste(text);
+            self.window.as_ref().unwrap().set_title(&prompt.title());
+            self.prompt = Some(prompt);
+            return;
+        }
+        self.input_buffer.extend(paste::encode(&text, bracketed));
+        self.window.as_ref().unwrap().request_redraw();
+    }
+    /// Starts the shell sized to the screen, along with the thread reading its output.
+    fn spawn_pty(&mut self) -> nix::Result<()> {
+        let screen = self.screen.as_ref().unwrap();
+        let pty = Pty::attempt_create(self.pty_config.clone(), pty_size(screen))?;
+        let proxy = self.proxy.clone();
+        let output = pty
+            .spawn_reader(move || {
+                // Only fails once the event loop is gone
+                let _ = proxy.send_event(UserEvent::PtyOutput);
+            })
+            .map_err(|e| Errno::from_raw(e.raw_os_error().unwrap_or(0)))?;
+        self.pty = Some(pty);
+        self.pty_output = Some(output);
+        Ok(())
+    }
+    pub fn new(proxy: EventLoopProxy<UserEvent>) -> Self {
         Self {
             screen: None,
             // arbitrary pre allocated space
-            input_buffer: String::with_capacity(4096),
+            input_buffer: Vec::with_capacity(4096),
+            modifiers: ModifiersState::empty(),
+            mouse_position: LogicalPosition::new(0.0, 0.0),
+            mouse_button: None,
+            selecting: false,
+            click_count: 0,
+            last_click: Instant::now(),
+            last_click_cell: (0, 0),
+            clipboard: match SystemClipboard::new() {
+                Some(clipboard) => Box::new(clipboard),
+                None => Box::new(MemoryClipboard::default()),
+            },
+            clipboard_read: ClipboardPolicy::default(),
+            confirm_multiline_paste: true,
+            link_opener: "xdg-open".to_string(),
+            hint_rules: hints::default_rules(),
+            hint_alphabet: hints::DEFAULT_HINT_ALPHABET.to_string(),
+            hint_action: None,
+            scrollback_lines: DEFAULT_SCROLLBACK_LINES,
+            prompt: None,
             window: None,
             last_frame: Instant::now(),
             last_blink: Instant::now(),
-            pty: Pty::attempt_create(
-                "__PLACEHOLDER__",
-                winsize {
-                    ws_row: 0,
-                    ws_col: 0,
-                    ws_xpixel: 0,
-                    ws_ypixel: 0,
-                },
-            )
-            .unwrap(),
+            // Spawned once the window exists and the grid size is known
+            pty: None,
+            pty_output: None,
+            proxy,
+            pty_config: PtyConfig {
+                shell: ShellConfig::default(),
+                shell_integration: true,
+            },
+            hung_up: false,
+            exit_action: ExitAction::default(),
             parser: Parser::new(),
             vk_app: None,
             pressed_keys: HashSet::new(),
@@ -121,9 +603,15 @@ const CROSS_THRESHOLD: f64 = 3.0;
 fn preload_latin(font: &mut TtfFont, texture_atlas: &mut Atlas<char, Rgb<u8>, ShelfAllocator>) {
     let target_font_px = 32;
     let dmax_px = 1.0;
-    for ch in '!'..'~' {
+    // Line drawing too, the font may not have every symbol of the set
+    let line_drawing = charset::DEC_SPECIAL_GRAPHICS
+        .into_iter()
+        .filter(|ch| !ch.is_whitespace());
+    for ch in ('!'..'~').chain(line_drawing) {
         let mut seed = 0;
-        let gid = font.lookup(ch as u32).unwrap();
+        let Some(gid) = font.lookup(ch as u32) else {
+            continue;
+        };
         let mut shape = font.assemble_glyf(gid as u16).unwrap();
         // shape.normalize();
         edge_coloring_simple(&mut shape, CROSS_THRESHOLD.sin(), &mut seed);
@@ -186,13 +674,13 @@ fn preload_latin(font: &mut TtfFont, texture_atlas: &mut Atlas<char, Rgb<u8>, Sh
         .unwrap();
 }
 
-impl ApplicationHandler for Application {
+impl ApplicationHandler<UserEvent> for Application {
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
         let window_size = PhysicalSize::new(1920, 1080);
         let window = event_loop
             .create_window(
                 Window::default_attributes()
-                    .with_title("dit")
+                    .with_title(WINDOW_TITLE)
                     .with_inner_size(window_size),
             )
             .unwrap();
@@ -208,12 +696,14 @@ impl ApplicationHandler for Application {
             texture_atlas,
             LogicalSize::from_physical(window_size, window.scale_factor()),
         );
+        screen.set_scrollback_limit(self.scrollback_lines);
         screen.construct_mesh();
         self.vk_app = Some(VkApplication::new(&window, &screen.mesh));
         self.window = Some(window);
         self.last_frame = Instant::now();
         self.last_blink = Instant::now();
         self.screen = Some(screen);
+        self.spawn_pty().unwrap();
     }
     fn window_event(
         &mut self,
@@ -226,7 +716,7 @@ impl ApplicationHandler for Application {
                 // let now = Instant::now();
                 // let dt = now - self.last_frame;
                 // self.last_frame = now;
-                self.update();
+                self.update(event_loop);
                 let app = self.vk_app.as_mut().unwrap();
                 let window = self.window.as_ref().unwrap();
                 if app.dirty_swapchain {
@@ -239,56 +729,284 @@ impl ApplicationHandler for Application {
                     }
                 }
                 app.dirty_swapchain = app.draw_frame();
+                if app.dirty_swapchain {
+                    self.window.as_ref().unwrap().request_redraw();
+                }
             }
             WindowEvent::CloseRequested => {
                 event_loop.exit();
             }
             WindowEvent::Resized(new_size) => {
                 let app = self.vk_app.as_mut().unwrap();
+                if let (Some(screen), Some(window)) = (self.screen.as_mut(), self.window.as_ref())
+                    && new_size.width > 0
+                    && new_size.height > 0
+                {
+                    let (rows, cols) = (screen.y_size, screen.col_size);
+                    let font_size = screen.cell_metrics().font_size;
+                    screen.resize(
+                        font_size,
+                        LogicalSize::from_physical(new_size, window.scale_factor()),
+                    );
+                    if (rows, cols) != (screen.y_size, screen.col_size) {
+                        app.replace_mesh(&screen.mesh);
+                        let size = pty_size(screen);
+                        // Fails once the child exited and took the pty with it
+                        if let Some(pty) = self.pty.as_ref()
+                            && let Err(e) =
+                                pty.resize(size.ws_row, size.ws_col, size.ws_xpixel, size.ws_ypixel)
+                        {
+                            eprintln!("failed to resize the pty: {}", e);
+                        }
+                    }
+                }
                 app.resize_dimensions = [new_size.width, new_size.height];
                 app.recreate_swapchain();
+                self.window.as_ref().unwrap().request_redraw();
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let scale_factor = self.window.as_ref().unwrap().scale_factor();
+                let position = position.to_logical(scale_factor);
+                let Some(screen) = self.screen.as_ref() else {
+                    return;
+                };
+                let old_cell = screen.cell_at(self.mouse_position.x, self.mouse_position.y);
+                let moved = screen.cell_at(position.x, position.y) != old_cell
+                    || screen.modes.contains(TermMode::MOUSE_SGR_PIXELS);
+                self.mouse_position = position;
+                self.update_hovered_link();
+                if self.selecting {
+                    let screen = self.screen.as_mut().unwrap();
+                    let (col, row) = screen.cell_at(position.x, position.y);
+                    screen.update_selection(col, row);
+                    self.window.as_ref().unwrap().request_redraw();
+                } else if moved {
+                    // Motion is reported once per cell, or per pixel when positions are in pixels
+                    self.report_mouse(MouseAction::Motion(self.mouse_button));
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    winit::event::MouseButton::Left => MouseButton::Left,
+                    winit::event::MouseButton::Middle => MouseButton::Middle,
+                    winit::event::MouseButton::Right => MouseButton::Right,
+                    winit::event::MouseButton::Back => MouseButton::Back,
+                    winit::event::MouseButton::Forward => MouseButton::Forward,
+                    winit::event::MouseButton::Other(_) => return,
+                };
+                // Ctrl+click opens hyperlinks, the release goes nowhere either
+                if button == MouseButton::Left
+                    && !self.selecting
+                    && let Some(link) = self.link_under_pointer()
+                {
+                    if state == ElementState::Pressed {
+                        self.open_link(link);
+                    }
+                    return;
+                }
+                let Some(screen) = self.screen.as_ref() else {
+                    return;
+                };
+                // Shift selects even while the child tracks the mouse
+                let tracking = screen.modes.intersects(TermMode::MOUSE_TRACKING)
+                    && !self.modifiers.shift_key();
+                if button == MouseButton::Left && (!tracking || self.selecting) {
+                    match state {
+                        ElementState::Pressed => self.start_selection(),
+                        ElementState::Released => {
+                            self.selecting = false;
+                            self.copy_selection(ClipboardKind::Primary);
+                        }
+                    }
+                    return;
+                }
+                if button == MouseButton::Middle && !tracking {
+                    if state == ElementState::Pressed {
+                        self.paste(ClipboardKind::Primary);
+                    }
+                    return;
+                }
+                let action = match state {
+                    ElementState::Pressed => {
+                        self.mouse_button = Some(button);
+                        MouseAction::Press(button)
+                    }
+                    ElementState::Released => {
+                        self.mouse_button = None;
+                        MouseAction::Release(button)
+                    }
+                };
+                self.report_mouse(action);
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let Some(screen) = self.screen.as_mut() else {
+                    return;
+                };
+                // Notches of the wheel, and the history lines they scroll
+                let (notches, lines) = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => {
+                        (y as isize, (y * WHEEL_SCROLL_LINES) as isize)
+                    }
+                    MouseScrollDelta::PixelDelta(pos) => {
+                        let scale_factor = self.window.as_ref().unwrap().scale_factor();
+                        let pos = pos.to_logical::<f64>(scale_factor);
+                        let lines = (pos.y / screen.cell_metrics().height as f64) as isize;
+                        (lines, lines)
+                    }
+                };
+                if screen.modes.intersects(TermMode::MOUSE_TRACKING) {
+                    // The wheel is reported as one button press per notch
+                    let button = if notches > 0 {
+                        MouseButton::WheelUp
+                    } else {
+                        MouseButton::WheelDown
+                    };
+                    for _ in 0..notches.unsigned_abs() {
+                        self.report_mouse(MouseAction::Press(button));
+                    }
+                } else {
+                    screen.scroll_viewport(lines);
+                    self.window.as_ref().unwrap().request_redraw();
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                self.update_hovered_link();
             }
             WindowEvent::KeyboardInput {
                 device_id,
                 event,
                 is_synthetic,
             } => {
-                if let PhysicalKey::Code(key) = event.physical_key {
-                    match event.state {
-                        ElementState::Pressed => {
-                            if let Some(text) = &event.text {
-                                self.input_buffer.push_str(text);
+                // The next key press answers a pending prompt
+                if event.state == ElementState::Pressed
+                    && let Some(prompt) = self.prompt.take()
+                {
+                    let title = self.screen.as_ref().and_then(Screen::title);
+                    self.window
+                        .as_ref()
+                        .unwrap()
+                        .set_title(title.unwrap_or(WINDOW_TITLE));
+                    if event.logical_key == Key::Character("y".into()) {
+                        match prompt {
+                            Prompt::ClipboardRead(kind) => {
+                                if let Some(screen) = self.screen.as_mut() {
+                                    reply_clipboard(self.clipboard.as_mut(), kind, screen);
+                                }
                             }
-                            // When a user is holding a key it still generates a Pressed event
-                            if !event.repeat {
-                                self.pressed_keys.insert(key);
+                            // Only asked while bracketed paste is off
+                            Prompt::Paste(text) => {
+                                self.input_buffer.extend(paste::encode(&text, false))
                             }
                         }
+                    }
+                    self.window.as_ref().unwrap().request_redraw();
+                    return;
+                }
+                // Hints mode takes every key until a label is picked or it's left
+                if let Some(action) = self.hint_action
+                    && self.screen.as_ref().is_some_and(Screen::hints_active)
+                {
+                    if event.state == ElementState::Pressed {
+                        self.hint_key(action, &event.logical_key);
+                    }
+                    return;
+                }
+                if let PhysicalKey::Code(key) = event.physical_key {
+                    let pressed = event.state == ElementState::Pressed;
+                    let shift = self.modifiers == ModifiersState::SHIFT;
+                    let ctrl_shift =
+                        self.modifiers == ModifiersState::CONTROL | ModifiersState::SHIFT;
+                    let copy_paste = ctrl_shift && matches!(key, KeyCode::KeyC | KeyCode::KeyV);
+                    let hint_action = match key {
+                        KeyCode::KeyO => Some(HintAction::Open),
+                        KeyCode::KeyY => Some(HintAction::Copy),
+                        KeyCode::KeyP => Some(HintAction::Paste),
+                        _ => None,
+                    }
+                    .filter(|_| ctrl_shift);
+                    if let Some(action) = hint_action {
+                        if pressed {
+                            self.start_hints(action);
+                        }
+                    } else if copy_paste {
+                        if pressed && key == KeyCode::KeyC {
+                            self.copy_selection(ClipboardKind::Clipboard);
+                        } else if pressed {
+                            self.paste(ClipboardKind::Clipboard);
+                        }
+                    } else if ctrl_shift && matches!(key, KeyCode::ArrowUp | KeyCode::ArrowDown) {
+                        // Jumps between the prompts marked by shell integration
+                        if pressed && let Some(screen) = self.screen.as_mut() {
+                            screen.scroll_to_prompt(key == KeyCode::ArrowUp);
+                        }
+                    } else if ctrl_shift && key == KeyCode::KeyG {
+                        if pressed && self.screen.as_mut().is_some_and(Screen::select_last_output) {
+                            self.copy_selection(ClipboardKind::Primary);
+                        }
+                    } else if shift && matches!(key, KeyCode::PageUp | KeyCode::PageDown) {
+                        // Shift+PageUp/PageDown browse history instead of reaching the shell
+                        if pressed && let Some(screen) = self.screen.as_mut() {
+                            let page = screen.y_size.saturating_sub(1).max(1) as isize;
+                            let lines = if key == KeyCode::PageUp { page } else { -page };
+                            screen.scroll_viewport(lines);
+                        }
+                    } else if let Some(screen) = self.screen.as_ref()
+                        && let Some(bytes) = keyboard::encode(
+                            &KeyInput::new(&event, self.modifiers),
+                            screen.modes,
+                            screen.modify_other_keys,
+                            screen.keyboard_flags(),
+                        )
+                    {
+                        self.input_buffer.extend(bytes);
+                        // The cursor stays shown while typing
+                        self.last_blink = Instant::now();
+                    }
+                    match event.state {
+                        // When a user is holding a key it still generates a Pressed event
+                        ElementState::Pressed if !event.repeat => {
+                            self.pressed_keys.insert(key);
+                        }
+                        ElementState::Pressed => {}
                         ElementState::Released => {
                             self.pressed_keys.remove(&key);
                         }
                     }
+                    self.window.as_ref().unwrap().request_redraw();
                 }
             }
             _ => {}
         }
     }
+    fn new_events(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop, cause: StartCause) {
+        // The cursor is due to blink
+        if let StartCause::ResumeTimeReached { .. } = cause
+            && let Some(window) = self.window.as_ref()
+        {
+            window.request_redraw();
+        }
+    }
     fn about_to_wait(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
         if self.last_frame.elapsed().as_secs_f32() >= 1.0 {
             self.frame_count = 0;
             self.last_frame = Instant::now();
         }
-        self.window.as_mut().unwrap().request_redraw();
+        // Sleeps until the next half of the blink, or until something happens
+        let control_flow = if self.screen.as_ref().is_some_and(Screen::cursor_blinks) {
+            ControlFlow::WaitUntil(self.last_blink + BLINK_INTERVAL * (self.blink_phases() + 1))
+        } else {
+            ControlFlow::Wait
+        };
+        event_loop.set_control_flow(control_flow);
     }
-    fn device_event(
-        &mut self,
-        event_loop: &winit::event_loop::ActiveEventLoop,
-        device_id: winit::event::DeviceId,
-        event: winit::event::DeviceEvent,
-    ) {
+    fn user_event(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop, event: UserEvent) {
         match event {
-            DeviceEvent::MouseWheel { delta } => {}
-            _ => {}
+            UserEvent::PtyOutput => {
+                if let Some(window) = self.window.as_ref() {
+                    window.request_redraw();
+                }
+            }
         }
     }
     fn exiting(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
@@ -0,0 +1,129 @@
+//! Access to the system clipboards, behind a trait so tests can use one kept in memory.
+use std::{
+    env,
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardKind {
+    /// The clipboard written by explicit copies, Ctrl+Shift+C
+    Clipboard,
+    /// The X11 primary selection, set by selecting and pasted with a middle click
+    Primary,
+}
+pub trait Clipboard {
+    fn store(&mut self, kind: ClipboardKind, text: &str) -> io::Result<()>;
+    fn load(&mut self, kind: ClipboardKind) -> io::Result<String>;
+}
+/// What happens when the child asks to read a clipboard through OSC 52
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardPolicy {
+    Deny,
+    /// The user answers each read
+    #[default]
+    Ask,
+    Allow,
+}
+/// Clipboards that only live as long as the value, for tests and systems without one
+#[derive(Debug, Default, Clone)]
+pub struct MemoryClipboard {
+    clipboard: String,
+    primary: String,
+}
+impl Clipboard for MemoryClipboard {
+    fn store(&mut self, kind: ClipboardKind, text: &str) -> io::Result<()> {
+        match kind {
+            ClipboardKind::Clipboard => self.clipboard = text.to_string(),
+            ClipboardKind::Primary => self.primary = text.to_string(),
+        }
+        Ok(())
+    }
+    fn load(&mut self, kind: ClipboardKind) -> io::Result<String> {
+        Ok(match kind {
+            ClipboardKind::Clipboard => self.clipboard.clone(),
+            ClipboardKind::Primary => self.primary.clone(),
+        })
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Display {
+    Wayland,
+    X11,
+}
+/// The desktop's clipboards, reached through `wl-copy`/`wl-paste` on Wayland and `xclip` on
+/// X11. Both keep serving a copied selection from the background after they exit.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClipboard {
+    display: Display,
+}
+impl SystemClipboard {
+    /// Picks the backend from the environment, `None` outside of a graphical session
+    pub fn new() -> Option<Self> {
+        let display = if env::var_os("WAYLAND_DISPLAY").is_some() {
+            Display::Wayland
+        } else if env::var_os("DISPLAY").is_some() {
+            Display::X11
+        } else {
+            return None;
+        };
+        Some(Self { display })
+    }
+    fn command(&self, kind: ClipboardKind, store: bool) -> Command {
+        match self.display {
+            Display::Wayland => {
+                let mut command = Command::new(if store { "wl-copy" } else { "wl-paste" });
+                if !store {
+                    command.arg("--no-newline");
+                }
+                if kind == ClipboardKind::Primary {
+                    command.arg("--primary");
+                }
+                command
+            }
+            Display::X11 => {
+                let mut command = Command::new("xclip");
+                let selection = match kind {
+                    ClipboardKind::Clipboard => "clipboard",
+                    ClipboardKind::Primary => "primary",
+                };
+                command
+                    .args(["-selection", selection])
+                    .arg(if store { "-in" } else { "-out" });
+                command
+            }
+        }
+    }
+}
+impl Clipboard for SystemClipboard {
+    fn store(&mut self, kind: ClipboardKind, text: &str) -> io::Result<()> {
+        let mut child = self
+            .command(kind, true)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        // Closing stdin lets the tool take ownership of the selection and detach
+        child.stdin.take().unwrap().write_all(text.as_bytes())?;
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "clipboard tool failed: {}",
+                status
+            )));
+        }
+        Ok(())
+    }
+    fn load(&mut self, kind: ClipboardKind) -> io::Result<String> {
+        let output = self
+            .command(kind, false)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()?;
+        // Both tools fail on an empty selection, which reads as nothing
+        if !output.status.success() {
+            return Ok(String::new());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
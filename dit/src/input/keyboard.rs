@@ -0,0 +1,232 @@
+use winit::{
+    event::{ElementState, KeyEvent},
+    keyboard::{Key, KeyLocation, ModifiersState, NamedKey},
+};
+
+use crate::{
+    ansii::details::{KeyboardFlags, TermMode},
+    input::kitty,
+};
+
+/// The parts of a key press the encoder looks at. Built from a winit [`KeyEvent`] in the app,
+/// or by hand in tests since `KeyEvent` can't be constructed outside winit.
+#[derive(Debug, Clone)]
+pub struct KeyInput<'a> {
+    pub key: &'a Key,
+    pub location: KeyLocation,
+    /// Text the key produces with the current layout, if any
+    pub text: Option<&'a str>,
+    pub mods: ModifiersState,
+    pub state: ElementState,
+    /// Set for the presses generated while a key is held down
+    pub repeat: bool,
+}
+impl<'a> KeyInput<'a> {
+    pub fn new(event: &'a KeyEvent, mods: ModifiersState) -> Self {
+        Self {
+            key: &event.logical_key,
+            location: event.location,
+            text: event.text.as_deref(),
+            mods,
+            state: event.state,
+            repeat: event.repeat,
+        }
+    }
+}
+/// xterm's modifier parameter, 1 when nothing is held
+fn modifier_param(mods: ModifiersState) -> u8 {
+    1 + mods.shift_key() as u8 + 2 * mods.alt_key() as u8 + 4 * mods.control_key() as u8
+}
+/// How a key without text is sent. `Tilde(n)` is `CSI n ~`, `Letter(c)` is `CSI 1 ; m c` when
+/// modified and `CSI c` / `SS3 c` otherwise.
+enum Special {
+    Tilde(u8),
+    Letter(u8),
+    /// F1 to F4, always SS3 without modifiers
+    Ss3Letter(u8),
+}
+fn special(key: NamedKey) -> Option<Special> {
+    let special = match key {
+        NamedKey::ArrowUp => Special::Letter(b'A'),
+        NamedKey::ArrowDown => Special::Letter(b'B'),
+        NamedKey::ArrowRight => Special::Letter(b'C'),
+        NamedKey::ArrowLeft => Special::Letter(b'D'),
+        NamedKey::Home => Special::Letter(b'H'),
+        NamedKey::End => Special::Letter(b'F'),
+        NamedKey::Insert => Special::Tilde(2),
+        NamedKey::Delete => Special::Tilde(3),
+        NamedKey::PageUp => Special::Tilde(5),
+        NamedKey::PageDown => Special::Tilde(6),
+        NamedKey::F1 => Special::Ss3Letter(b'P'),
+        NamedKey::F2 => Special::Ss3Letter(b'Q'),
+        NamedKey::F3 => Special::Ss3Letter(b'R'),
+        NamedKey::F4 => Special::Ss3Letter(b'S'),
+        NamedKey::F5 => Special::Tilde(15),
+        NamedKey::F6 => Special::Tilde(17),
+        NamedKey::F7 => Special::Tilde(18),
+        NamedKey::F8 => Special::Tilde(19),
+        NamedKey::F9 => Special::Tilde(20),
+        NamedKey::F10 => Special::Tilde(21),
+        NamedKey::F11 => Special::Tilde(23),
+        NamedKey::F12 => Special::Tilde(24),
+        _ => return None,
+    };
+    Some(special)
+}
+/// SS3 final byte of a numpad key in application keypad mode
+fn keypad_final(key: &Key) -> Option<u8> {
+    let final_byte = match key {
+        Key::Named(NamedKey::Enter) => b'M',
+        Key::Character(ch) => match ch.as_str() {
+            digit @ ("0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9") => {
+                b'p' + (digit.as_bytes()[0] - b'0')
+            }
+            "*" => b'j',
+            "+" => b'k',
+            "," => b'l',
+            "-" => b'm',
+            "." => b'n',
+            "/" => b'o',
+            "=" => b'X',
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(final_byte)
+}
+/// The C0 control Ctrl turns `ch` into, `None` for keys without a well known mapping
+fn ctrl_byte(ch: char) -> Option<u8> {
+    let byte = match ch {
+        'a'..='z' => ch as u8 - b'a' + 1,
+        'A'..='Z' => ch as u8 - b'A' + 1,
+        '@' | ' ' | '2' => 0x00,
+        '[' | '3' => 0x1b,
+        '\\' | '4' => 0x1c,
+        ']' | '5' => 0x1d,
+        '^' | '6' => 0x1e,
+        '_' | '/' | '7' => 0x1f,
+        '8' | '?' => 0x7f,
+        _ => return None,
+    };
+    Some(byte)
+}
+/// `CSI 27 ; m ; code ~`, the modifyOtherKeys form of a key
+fn other_key(code: u32, mods: ModifiersState) -> Vec<u8> {
+    format!("\x1b[27;{};{}~", modifier_param(mods), code).into_bytes()
+}
+/// Prefixes `bytes` with ESC when Alt is held, Alt acting as Meta
+fn meta(mut bytes: Vec<u8>, mods: ModifiersState) -> Vec<u8> {
+    if mods.alt_key() {
+        bytes.insert(0, 0x1b);
+    }
+    bytes
+}
+/// Turns a key event into the bytes xterm would send for it, `None` for keys that send nothing.
+/// `modify_other_keys` is the level set through XTMODKEYS, `flags` the kitty keyboard protocol
+/// enhancements which replace the legacy encoding entirely while any is set.
+pub fn encode(
+    input: &KeyInput,
+    modes: TermMode,
+    modify_other_keys: u8,
+    flags: KeyboardFlags,
+) -> Option<Vec<u8>> {
+    if !flags.is_empty() {
+        return kitty::encode(input, flags);
+    }
+    // Legacy encodings have no way to report releases
+    if input.state == ElementState::Released {
+        return None;
+    }
+    let mods = input.mods;
+    // Super is left to the window manager and app shortcuts
+    if mods.super_key() {
+        return None;
+    }
+    let param = modifier_param(mods);
+    if modes.contains(TermMode::KEYPAD_APPLICATION)
+        && input.location == KeyLocation::Numpad
+        && param == 1
+        && let Some(final_byte) = keypad_final(input.key)
+    {
+        return Some(vec![0x1b, b'O', final_byte]);
+    }
+    match input.key {
+        Key::Named(named) => {
+            if let Some(special) = special(*named) {
+                let bytes = match special {
+                    Special::Tilde(n) if param == 1 => format!("\x1b[{}~", n),
+                    Special::Tilde(n) => format!("\x1b[{};{}~", n, param),
+                    Special::Letter(c) | Special::Ss3Letter(c) if param > 1 => {
+                        format!("\x1b[1;{}{}", param, c as char)
+                    }
+                    Special::Letter(c) if !modes.contains(TermMode::CURSOR_KEYS) => {
+                        format!("\x1b[{}", c as char)
+                    }
+                    Special::Letter(c) | Special::Ss3Letter(c) => format!("\x1bO{}", c as char),
+                };
+                return Some(bytes.into_bytes());
+            }
+            encode_named(*named, modes, modify_other_keys, mods)
+        }
+        Key::Character(ch) => {
+            let mut chars = ch.chars();
+            let (Some(first), None) = (chars.next(), chars.next()) else {
+                // Composed input, send it as is
+                return Some(meta(ch.as_bytes().to_vec(), mods));
+            };
+            encode_char(first, input.text, modify_other_keys, mods)
+        }
+        _ => input.text.map(|text| meta(text.as_bytes().to_vec(), mods)),
+    }
+}
+/// Enter, Tab, Backspace, Escape and Space
+fn encode_named(
+    key: NamedKey,
+    modes: TermMode,
+    modify_other_keys: u8,
+    mods: ModifiersState,
+) -> Option<Vec<u8>> {
+    let (code, plain): (u32, &[u8]) = match key {
+        NamedKey::Enter if modes.contains(TermMode::LINE_FEED_NEWLINE) => (13, b"\r\n"),
+        NamedKey::Enter => (13, b"\r"),
+        NamedKey::Tab if mods == ModifiersState::SHIFT => return Some(b"\x1b[Z".to_vec()),
+        NamedKey::Tab => (9, b"\t"),
+        NamedKey::Backspace if mods.control_key() => (127, b"\x08"),
+        NamedKey::Backspace => (127, b"\x7f"),
+        NamedKey::Escape => (27, b"\x1b"),
+        NamedKey::Space if mods.control_key() => (32, b"\x00"),
+        NamedKey::Space => (32, b" "),
+        _ => return None,
+    };
+    // Level 2 reports every modified key, Shift+Space is still just a space
+    if modify_other_keys == 2
+        && !mods.is_empty()
+        && !(key == NamedKey::Space && mods == ModifiersState::SHIFT)
+    {
+        return Some(other_key(code, mods));
+    }
+    Some(meta(plain.to_vec(), mods))
+}
+fn encode_char(
+    ch: char,
+    text: Option<&str>,
+    modify_other_keys: u8,
+    mods: ModifiersState,
+) -> Option<Vec<u8>> {
+    let ctrl = mods.control_key();
+    // Shift alone only changes which character is typed
+    let modified = ctrl || mods.alt_key();
+    if modified && modify_other_keys == 2 {
+        return Some(other_key(ch as u32, mods));
+    }
+    if ctrl {
+        return match ctrl_byte(ch) {
+            Some(byte) => Some(meta(vec![byte], mods)),
+            // Level 1 only reports the keys that have no legacy encoding
+            None if modify_other_keys == 1 => Some(other_key(ch as u32, mods)),
+            None => text.map(|text| meta(text.as_bytes().to_vec(), mods)),
+        };
+    }
+    let text = text.map_or_else(|| ch.to_string(), str::to_string);
+    Some(meta(text.into_bytes(), mods))
+}
@@ -0,0 +1,215 @@
+//! Key encoding for the kitty keyboard protocol, used while the child has pushed any
+//! progressive enhancement flags.
+use winit::{
+    event::ElementState,
+    keyboard::{Key, KeyLocation, ModifiersState, NamedKey},
+};
+
+use crate::{ansii::details::KeyboardFlags, input::keyboard::KeyInput};
+
+/// Event type sub-parameter of the modifiers field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EventType {
+    Press = 1,
+    Repeat = 2,
+    Release = 3,
+}
+/// Modifier field, 1 when nothing is held. Super is reported here, unlike in legacy mode.
+fn modifier_param(mods: ModifiersState) -> u8 {
+    1 + mods.shift_key() as u8
+        + 2 * mods.alt_key() as u8
+        + 4 * mods.control_key() as u8
+        + 8 * mods.super_key() as u8
+}
+/// Kitty's key number and the final byte it is sent with for keys that don't produce text
+fn functional(key: NamedKey, location: KeyLocation) -> Option<(u32, u8)> {
+    let right = location == KeyLocation::Right;
+    let code = match key {
+        NamedKey::Escape => (27, b'u'),
+        NamedKey::Enter => (13, b'u'),
+        NamedKey::Tab => (9, b'u'),
+        NamedKey::Backspace => (127, b'u'),
+        NamedKey::Space => (32, b'u'),
+        NamedKey::Insert => (2, b'~'),
+        NamedKey::Delete => (3, b'~'),
+        NamedKey::PageUp => (5, b'~'),
+        NamedKey::PageDown => (6, b'~'),
+        NamedKey::ArrowUp => (1, b'A'),
+        NamedKey::ArrowDown => (1, b'B'),
+        NamedKey::ArrowRight => (1, b'C'),
+        NamedKey::ArrowLeft => (1, b'D'),
+        NamedKey::Home => (1, b'H'),
+        NamedKey::End => (1, b'F'),
+        NamedKey::F1 => (1, b'P'),
+        NamedKey::F2 => (1, b'Q'),
+        // `CSI R` would be mistaken for a cursor position report
+        NamedKey::F3 => (13, b'~'),
+        NamedKey::F4 => (1, b'S'),
+        NamedKey::F5 => (15, b'~'),
+        NamedKey::F6 => (17, b'~'),
+        NamedKey::F7 => (18, b'~'),
+        NamedKey::F8 => (19, b'~'),
+        NamedKey::F9 => (20, b'~'),
+        NamedKey::F10 => (21, b'~'),
+        NamedKey::F11 => (23, b'~'),
+        NamedKey::F12 => (24, b'~'),
+        NamedKey::F13 => (57376, b'u'),
+        NamedKey::F14 => (57377, b'u'),
+        NamedKey::F15 => (57378, b'u'),
+        NamedKey::F16 => (57379, b'u'),
+        NamedKey::F17 => (57380, b'u'),
+        NamedKey::F18 => (57381, b'u'),
+        NamedKey::F19 => (57382, b'u'),
+        NamedKey::F20 => (57383, b'u'),
+        NamedKey::CapsLock => (57358, b'u'),
+        NamedKey::ScrollLock => (57359, b'u'),
+        NamedKey::NumLock => (57360, b'u'),
+        NamedKey::PrintScreen => (57361, b'u'),
+        NamedKey::Pause => (57362, b'u'),
+        NamedKey::ContextMenu => (57363, b'u'),
+        NamedKey::Shift => (if right { 57447 } else { 57441 }, b'u'),
+        NamedKey::Control => (if right { 57448 } else { 57442 }, b'u'),
+        NamedKey::Alt => (if right { 57449 } else { 57443 }, b'u'),
+        NamedKey::Super => (if right { 57450 } else { 57444 }, b'u'),
+        NamedKey::Hyper => (if right { 57451 } else { 57445 }, b'u'),
+        NamedKey::Meta => (if right { 57452 } else { 57446 }, b'u'),
+        NamedKey::AltGraph => (57453, b'u'),
+        _ => return None,
+    };
+    Some(code)
+}
+/// Dedicated numbers for numpad keys, so they can be told apart from the main block
+fn keypad(key: &Key) -> Option<u32> {
+    let code = match key {
+        Key::Named(NamedKey::Enter) => 57414,
+        Key::Character(ch) => match ch.as_str() {
+            digit @ ("0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9") => {
+                57399 + (digit.as_bytes()[0] - b'0') as u32
+            }
+            "." => 57409,
+            "/" => 57410,
+            "*" => 57411,
+            "-" => 57412,
+            "+" => 57413,
+            "=" => 57415,
+            "," => 57416,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(code)
+}
+fn is_modifier(key: &Key) -> bool {
+    matches!(
+        key,
+        Key::Named(
+            NamedKey::Shift
+                | NamedKey::Control
+                | NamedKey::Alt
+                | NamedKey::Super
+                | NamedKey::Hyper
+                | NamedKey::Meta
+                | NamedKey::AltGraph
+        )
+    )
+}
+/// Encodes a key event under the given enhancement flags, `None` when nothing is reported
+pub fn encode(input: &KeyInput, flags: KeyboardFlags) -> Option<Vec<u8>> {
+    let all_keys = flags.contains(KeyboardFlags::REPORT_ALL_KEYS);
+    let event = match (input.state, input.repeat) {
+        (ElementState::Released, _) => EventType::Release,
+        (ElementState::Pressed, true) => EventType::Repeat,
+        (ElementState::Pressed, false) => EventType::Press,
+    };
+    let event = match event {
+        _ if flags.contains(KeyboardFlags::REPORT_EVENT_TYPES) => event,
+        EventType::Release => return None,
+        _ => EventType::Press,
+    };
+    let mods = input.mods;
+    // The key's own number, the character it typed with Shift and the final byte
+    let (code, shifted, final_byte) = if input.location == KeyLocation::Numpad
+        && let Some(code) = keypad(input.key)
+    {
+        (code, None, b'u')
+    } else {
+        match input.key {
+            Key::Named(named) => {
+                let (code, final_byte) = functional(*named, input.location)?;
+                (code, None, final_byte)
+            }
+            Key::Character(ch) => {
+                let mut chars = ch.chars();
+                let (Some(first), None) = (chars.next(), chars.next()) else {
+                    // Composed input has no key number, only its text can be sent
+                    return (event != EventType::Release).then(|| ch.as_bytes().to_vec());
+                };
+                let base = first.to_lowercase().next().unwrap_or(first);
+                let shifted = (base != first && input.mods.shift_key()).then_some(first as u32);
+                (base as u32, shifted, b'u')
+            }
+            _ => {
+                return input
+                    .text
+                    .filter(|_| event != EventType::Release)
+                    .map(|text| text.as_bytes().to_vec());
+            }
+        }
+    };
+    if !all_keys {
+        if is_modifier(input.key) {
+            return None;
+        }
+        let plain = (mods - ModifiersState::SHIFT).is_empty();
+        let legacy: Option<&[u8]> = match input.key {
+            _ if input.location == KeyLocation::Numpad => None,
+            // Kept as is so a shell stays usable when a program exits without popping its flags
+            Key::Named(NamedKey::Enter) if mods.is_empty() => Some(b"\r"),
+            Key::Named(NamedKey::Tab) if mods.is_empty() => Some(b"\t"),
+            Key::Named(NamedKey::Backspace) if mods.is_empty() => Some(b"\x7f"),
+            // Text is typed as text unless a modifier other than Shift changes its meaning
+            Key::Character(_) | Key::Named(NamedKey::Space) if plain => {
+                input.text.map(str::as_bytes)
+            }
+            _ => None,
+        };
+        if let Some(bytes) = legacy {
+            return (event != EventType::Release).then(|| bytes.to_vec());
+        }
+    }
+    let mut seq = format!("\x1b[{}", code);
+    if flags.contains(KeyboardFlags::REPORT_ALTERNATES)
+        && let Some(shifted) = shifted
+    {
+        seq.push_str(&format!(":{}", shifted));
+    }
+    let param = modifier_param(mods);
+    let text: Option<Vec<String>> = input
+        .text
+        .filter(|_| all_keys && flags.contains(KeyboardFlags::REPORT_TEXT))
+        .filter(|_| event != EventType::Release)
+        .map(|text| {
+            text.chars()
+                .filter(|ch| !ch.is_control())
+                .map(|ch| (ch as u32).to_string())
+                .collect()
+        })
+        .filter(|codes: &Vec<String>| !codes.is_empty());
+    if param > 1 || event != EventType::Press {
+        seq.push_str(&format!(";{}", param));
+        if event != EventType::Press {
+            seq.push_str(&format!(":{}", event as u8));
+        }
+    } else if text.is_some() {
+        seq.push(';');
+    }
+    if let Some(codes) = text {
+        seq.push_str(&format!(";{}", codes.join(":")));
+    }
+    // `CSI 1 A` and `CSI 1 ~` style keys drop the number when there's nothing after it
+    if final_byte != b'u' && code == 1 && !seq.contains(';') {
+        seq.truncate(2);
+    }
+    seq.push(final_byte as char);
+    Some(seq.into_bytes())
+}
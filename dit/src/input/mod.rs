@@ -0,0 +1,6 @@
+pub mod keyboard;
+mod kitty;
+pub mod mouse;
+pub mod paste;
+#[cfg(test)]
+mod tests;
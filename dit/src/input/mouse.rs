@@ -0,0 +1,150 @@
+use winit::keyboard::ModifiersState;
+
+use crate::ansii::details::TermMode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+    Back,
+    Forward,
+}
+impl MouseButton {
+    /// Button number of the report, before modifiers and the motion flag are added
+    fn code(self) -> u32 {
+        match self {
+            MouseButton::Left => 0,
+            MouseButton::Middle => 1,
+            MouseButton::Right => 2,
+            MouseButton::WheelUp => 64,
+            MouseButton::WheelDown => 65,
+            MouseButton::WheelLeft => 66,
+            MouseButton::WheelRight => 67,
+            MouseButton::Back => 128,
+            MouseButton::Forward => 129,
+        }
+    }
+    fn is_wheel(self) -> bool {
+        matches!(
+            self,
+            MouseButton::WheelUp
+                | MouseButton::WheelDown
+                | MouseButton::WheelLeft
+                | MouseButton::WheelRight
+        )
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseAction {
+    Press(MouseButton),
+    Release(MouseButton),
+    /// The pointer moved to another cell, with the button held down if any
+    Motion(Option<MouseButton>),
+}
+/// A mouse event at a position on the grid
+#[derive(Debug, Clone, Copy)]
+pub struct MouseEvent {
+    pub action: MouseAction,
+    /// 0-based cell under the pointer
+    pub col: usize,
+    pub row: usize,
+    /// Pointer position in pixels from the top left of the grid, used by SGR-pixels
+    pub x: u32,
+    pub y: u32,
+    pub mods: ModifiersState,
+}
+/// Whether the child asked for `action` through the active tracking mode
+fn is_tracked(action: MouseAction, modes: TermMode) -> bool {
+    match action {
+        MouseAction::Press(button) if modes.contains(TermMode::MOUSE_X10) => !button.is_wheel(),
+        MouseAction::Press(_) => modes.intersects(TermMode::MOUSE_TRACKING),
+        // The wheel has nothing to release
+        MouseAction::Release(button) => {
+            !button.is_wheel()
+                && modes.intersects(
+                    TermMode::MOUSE_NORMAL
+                        | TermMode::MOUSE_BUTTON_EVENT
+                        | TermMode::MOUSE_ANY_EVENT,
+                )
+        }
+        MouseAction::Motion(Some(_)) => {
+            modes.intersects(TermMode::MOUSE_BUTTON_EVENT | TermMode::MOUSE_ANY_EVENT)
+        }
+        MouseAction::Motion(None) => modes.contains(TermMode::MOUSE_ANY_EVENT),
+    }
+}
+/// Pushes `value` as a single byte offset by 32, `false` when it doesn't fit
+fn push_legacy(out: &mut Vec<u8>, value: u32) -> bool {
+    match u8::try_from(value + 32) {
+        Ok(byte) => {
+            out.push(byte);
+            true
+        }
+        Err(_) => false,
+    }
+}
+/// Pushes `value` offset by 32 as a UTF-8 character, `false` past what 1005 can represent
+fn push_utf8(out: &mut Vec<u8>, value: u32) -> bool {
+    match char::from_u32(value + 32).filter(|_| value + 32 < 0x800) {
+        Some(ch) => {
+            let mut buf = [0; 4];
+            out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+            true
+        }
+        None => false,
+    }
+}
+/// Encodes a mouse event the way the child asked for, `None` when it isn't reported
+pub fn encode(event: &MouseEvent, modes: TermMode) -> Option<Vec<u8>> {
+    if !is_tracked(event.action, modes) {
+        return None;
+    }
+    let (button, motion) = match event.action {
+        MouseAction::Press(button) | MouseAction::Release(button) => (Some(button), false),
+        MouseAction::Motion(button) => (button, true),
+    };
+    let released = matches!(event.action, MouseAction::Release(_));
+    let sgr = modes.intersects(TermMode::MOUSE_SGR | TermMode::MOUSE_SGR_PIXELS);
+    // Only SGR can tell which button was released, the others send 3 for any release
+    let mut code = match button {
+        Some(_) if released && !sgr => 3,
+        Some(button) => button.code(),
+        None => 3,
+    };
+    // X10 reports carry no modifiers
+    if !modes.contains(TermMode::MOUSE_X10) {
+        let mods = event.mods;
+        code += 4 * mods.shift_key() as u32 + 8 * mods.alt_key() as u32;
+        code += 16 * mods.control_key() as u32;
+    }
+    if motion {
+        code += 32;
+    }
+    // Reports are 1-based
+    let (col, row) = (event.col as u32 + 1, event.row as u32 + 1);
+    if sgr {
+        let (x, y) = if modes.contains(TermMode::MOUSE_SGR_PIXELS) {
+            (event.x + 1, event.y + 1)
+        } else {
+            (col, row)
+        };
+        let final_byte = if released { 'm' } else { 'M' };
+        return Some(format!("\x1b[<{};{};{}{}", code, x, y, final_byte).into_bytes());
+    }
+    if modes.contains(TermMode::MOUSE_URXVT) {
+        return Some(format!("\x1b[{};{};{}M", code + 32, col, row).into_bytes());
+    }
+    let push = if modes.contains(TermMode::MOUSE_UTF8) {
+        push_utf8
+    } else {
+        push_legacy
+    };
+    let mut out = b"\x1b[M".to_vec();
+    // Positions past what the encoding can carry are dropped like xterm does
+    (push(&mut out, code) && push(&mut out, col) && push(&mut out, row)).then_some(out)
+}
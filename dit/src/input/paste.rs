@@ -0,0 +1,22 @@
+/// Opens and closes a paste while bracketed paste mode (2004) is set
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Whether pasting `text` unbracketed would run something, line breaks being sent as Enter
+pub fn is_multiline(text: &str) -> bool {
+    text.contains(['\n', '\r'])
+}
+/// Turns pasted text into the bytes sent to the child. Bracketed pastes lose any ESC so the
+/// text can't end the paste early and have the rest run as typed, others have their line
+/// breaks sent the way Enter sends them.
+pub fn encode(text: &str, bracketed: bool) -> Vec<u8> {
+    if !bracketed {
+        return text.replace("\r\n", "\r").replace('\n', "\r").into_bytes();
+    }
+    let text = text.replace("\x1b[201~", "").replace('\x1b', "");
+    let mut out = Vec::with_capacity(PASTE_START.len() + text.len() + PASTE_END.len());
+    out.extend_from_slice(PASTE_START);
+    out.extend_from_slice(text.as_bytes());
+    out.extend_from_slice(PASTE_END);
+    out
+}
@@ -0,0 +1,448 @@
+use winit::{
+    event::ElementState,
+    keyboard::{Key, KeyLocation, ModifiersState, NamedKey},
+};
+
+use crate::{
+    ansii::details::{KeyboardFlags, TermMode},
+    input::{
+        keyboard::{KeyInput, encode},
+        mouse::{self, MouseAction, MouseButton, MouseEvent},
+        paste,
+    },
+};
+
+const NONE: ModifiersState = ModifiersState::empty();
+const SHIFT: ModifiersState = ModifiersState::SHIFT;
+const CTRL: ModifiersState = ModifiersState::CONTROL;
+const ALT: ModifiersState = ModifiersState::ALT;
+
+/// A fresh press of `key` on the main block
+fn press<'a>(key: &'a Key, text: Option<&'a str>, mods: ModifiersState) -> KeyInput<'a> {
+    KeyInput {
+        key,
+        location: KeyLocation::Standard,
+        text,
+        mods,
+        state: ElementState::Pressed,
+        repeat: false,
+    }
+}
+
+fn legacy(input: &KeyInput, modes: TermMode, level: u8) -> Option<String> {
+    encode(input, modes, level, KeyboardFlags::empty()).map(|b| String::from_utf8(b).unwrap())
+}
+
+fn named(key: NamedKey, mods: ModifiersState) -> Option<String> {
+    named_in(key, mods, TermMode::default())
+}
+
+fn named_in(key: NamedKey, mods: ModifiersState, modes: TermMode) -> Option<String> {
+    let key = Key::Named(key);
+    legacy(&press(&key, None, mods), modes, 0)
+}
+
+fn character(ch: &str, mods: ModifiersState, level: u8) -> Option<String> {
+    let key = Key::Character(ch.into());
+    legacy(&press(&key, Some(ch), mods), TermMode::default(), level)
+}
+
+fn keypad(ch: &str, modes: TermMode) -> Option<String> {
+    let key = Key::Character(ch.into());
+    let input = KeyInput {
+        location: KeyLocation::Numpad,
+        ..press(&key, Some(ch), NONE)
+    };
+    legacy(&input, modes, 0)
+}
+
+fn kitty(input: &KeyInput, flags: KeyboardFlags) -> Option<String> {
+    encode(input, TermMode::default(), 0, flags).map(|b| String::from_utf8(b).unwrap())
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 1. Cursor and editing keys
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn arrows_follow_decckm() {
+    assert_eq!(named(NamedKey::ArrowUp, NONE).unwrap(), "\x1b[A");
+    let app = TermMode::default() | TermMode::CURSOR_KEYS;
+    assert_eq!(named_in(NamedKey::ArrowUp, NONE, app).unwrap(), "\x1bOA");
+    assert_eq!(named_in(NamedKey::End, NONE, app).unwrap(), "\x1bOF");
+}
+
+#[test]
+fn modified_arrows_use_csi_1_m() {
+    assert_eq!(named(NamedKey::ArrowLeft, CTRL).unwrap(), "\x1b[1;5D");
+    assert_eq!(named(NamedKey::Home, SHIFT).unwrap(), "\x1b[1;2H");
+    // Modifiers win over DECCKM
+    let app = TermMode::default() | TermMode::CURSOR_KEYS;
+    assert_eq!(named_in(NamedKey::ArrowUp, ALT, app).unwrap(), "\x1b[1;3A");
+}
+
+#[test]
+fn tilde_keys() {
+    assert_eq!(named(NamedKey::Delete, NONE).unwrap(), "\x1b[3~");
+    assert_eq!(named(NamedKey::PageDown, NONE).unwrap(), "\x1b[6~");
+    assert_eq!(named(NamedKey::PageUp, CTRL | SHIFT).unwrap(), "\x1b[5;6~");
+}
+
+#[test]
+fn function_keys() {
+    assert_eq!(named(NamedKey::F1, NONE).unwrap(), "\x1bOP");
+    assert_eq!(named(NamedKey::F4, SHIFT).unwrap(), "\x1b[1;2S");
+    assert_eq!(named(NamedKey::F5, NONE).unwrap(), "\x1b[15~");
+    assert_eq!(named(NamedKey::F12, CTRL).unwrap(), "\x1b[24;5~");
+}
+
+#[test]
+fn enter_tab_backspace_escape() {
+    assert_eq!(named(NamedKey::Enter, NONE).unwrap(), "\r");
+    assert_eq!(named(NamedKey::Enter, ALT).unwrap(), "\x1b\r");
+    let lnm = TermMode::default() | TermMode::LINE_FEED_NEWLINE;
+    assert_eq!(named_in(NamedKey::Enter, NONE, lnm).unwrap(), "\r\n");
+    assert_eq!(named(NamedKey::Tab, NONE).unwrap(), "\t");
+    assert_eq!(named(NamedKey::Tab, SHIFT).unwrap(), "\x1b[Z");
+    assert_eq!(named(NamedKey::Backspace, NONE).unwrap(), "\x7f");
+    assert_eq!(named(NamedKey::Backspace, CTRL).unwrap(), "\x08");
+    assert_eq!(named(NamedKey::Backspace, ALT).unwrap(), "\x1b\x7f");
+    assert_eq!(named(NamedKey::Escape, NONE).unwrap(), "\x1b");
+    assert_eq!(named(NamedKey::Space, CTRL).unwrap(), "\x00");
+}
+
+#[test]
+fn modifier_keys_send_nothing() {
+    assert_eq!(named(NamedKey::Shift, SHIFT), None);
+    assert_eq!(named(NamedKey::Control, CTRL), None);
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 2. Characters
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn plain_and_shifted_text() {
+    assert_eq!(character("a", NONE, 0).unwrap(), "a");
+    assert_eq!(character("A", SHIFT, 0).unwrap(), "A");
+    assert_eq!(character("é", NONE, 0).unwrap(), "é");
+}
+
+#[test]
+fn ctrl_letters_and_symbols() {
+    assert_eq!(character("c", CTRL, 0).unwrap(), "\x03");
+    assert_eq!(character("C", CTRL | SHIFT, 0).unwrap(), "\x03");
+    assert_eq!(character("[", CTRL, 0).unwrap(), "\x1b");
+    assert_eq!(character("2", CTRL, 0).unwrap(), "\x00");
+    assert_eq!(character("/", CTRL, 0).unwrap(), "\x1f");
+}
+
+#[test]
+fn alt_is_meta() {
+    assert_eq!(character("f", ALT, 0).unwrap(), "\x1bf");
+    assert_eq!(character("x", CTRL | ALT, 0).unwrap(), "\x1b\x18");
+}
+
+#[test]
+fn super_is_left_alone() {
+    assert_eq!(character("c", ModifiersState::SUPER, 0), None);
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 3. modifyOtherKeys
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn level_one_only_reports_keys_without_legacy_encoding() {
+    assert_eq!(character("c", CTRL, 1).unwrap(), "\x03");
+    assert_eq!(character(";", CTRL, 1).unwrap(), "\x1b[27;5;59~");
+    assert_eq!(character("1", CTRL, 1).unwrap(), "\x1b[27;5;49~");
+}
+
+#[test]
+fn level_two_reports_every_modified_key() {
+    assert_eq!(character("c", CTRL, 2).unwrap(), "\x1b[27;5;99~");
+    assert_eq!(character("f", ALT, 2).unwrap(), "\x1b[27;3;102~");
+    // Shift alone still types the character
+    assert_eq!(character("A", SHIFT, 2).unwrap(), "A");
+    let key = Key::Named(NamedKey::Enter);
+    let input = press(&key, None, CTRL);
+    assert_eq!(
+        legacy(&input, TermMode::default(), 2).unwrap(),
+        "\x1b[27;5;13~"
+    );
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 4. Keypad
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn keypad_follows_deckpam() {
+    assert_eq!(keypad("5", TermMode::default()).unwrap(), "5");
+    let app = TermMode::default() | TermMode::KEYPAD_APPLICATION;
+    assert_eq!(keypad("5", app).unwrap(), "\x1bOu");
+    assert_eq!(keypad("+", app).unwrap(), "\x1bOk");
+    assert_eq!(keypad("/", app).unwrap(), "\x1bOo");
+}
+
+#[test]
+fn legacy_ignores_releases() {
+    let key = Key::Character("a".into());
+    let input = KeyInput {
+        state: ElementState::Released,
+        ..press(&key, Some("a"), NONE)
+    };
+    assert_eq!(legacy(&input, TermMode::default(), 0), None);
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 5. Kitty keyboard protocol
+// ─────────────────────────────────────────────────────────────────────────
+
+const DISAMBIGUATE: KeyboardFlags = KeyboardFlags::DISAMBIGUATE;
+
+#[test]
+fn disambiguate_keeps_plain_text_and_shell_keys() {
+    let a = Key::Character("a".into());
+    assert_eq!(
+        kitty(&press(&a, Some("a"), NONE), DISAMBIGUATE).unwrap(),
+        "a"
+    );
+    let upper = Key::Character("A".into());
+    assert_eq!(
+        kitty(&press(&upper, Some("A"), SHIFT), DISAMBIGUATE).unwrap(),
+        "A"
+    );
+    let enter = Key::Named(NamedKey::Enter);
+    assert_eq!(
+        kitty(&press(&enter, None, NONE), DISAMBIGUATE).unwrap(),
+        "\r"
+    );
+}
+
+#[test]
+fn disambiguate_reports_modified_keys_as_csi_u() {
+    let c = Key::Character("c".into());
+    assert_eq!(
+        kitty(&press(&c, Some("c"), CTRL), DISAMBIGUATE).unwrap(),
+        "\x1b[99;5u"
+    );
+    let i = Key::Character("i".into());
+    assert_eq!(
+        kitty(&press(&i, Some("i"), CTRL), DISAMBIGUATE).unwrap(),
+        "\x1b[105;5u"
+    );
+    let esc = Key::Named(NamedKey::Escape);
+    assert_eq!(
+        kitty(&press(&esc, None, NONE), DISAMBIGUATE).unwrap(),
+        "\x1b[27u"
+    );
+    let enter = Key::Named(NamedKey::Enter);
+    assert_eq!(
+        kitty(&press(&enter, None, SHIFT), DISAMBIGUATE).unwrap(),
+        "\x1b[13;2u"
+    );
+}
+
+#[test]
+fn kitty_functional_keys() {
+    let up = Key::Named(NamedKey::ArrowUp);
+    assert_eq!(
+        kitty(&press(&up, None, NONE), DISAMBIGUATE).unwrap(),
+        "\x1b[A"
+    );
+    assert_eq!(
+        kitty(&press(&up, None, CTRL), DISAMBIGUATE).unwrap(),
+        "\x1b[1;5A"
+    );
+    let f3 = Key::Named(NamedKey::F3);
+    assert_eq!(
+        kitty(&press(&f3, None, NONE), DISAMBIGUATE).unwrap(),
+        "\x1b[13~"
+    );
+    let shift = Key::Named(NamedKey::Shift);
+    assert_eq!(kitty(&press(&shift, None, SHIFT), DISAMBIGUATE), None);
+}
+
+#[test]
+fn kitty_keypad_keys_are_distinct() {
+    let five = Key::Character("5".into());
+    let input = KeyInput {
+        location: KeyLocation::Numpad,
+        ..press(&five, Some("5"), NONE)
+    };
+    assert_eq!(kitty(&input, DISAMBIGUATE).unwrap(), "\x1b[57404u");
+}
+
+#[test]
+fn kitty_event_types() {
+    let flags = DISAMBIGUATE | KeyboardFlags::REPORT_EVENT_TYPES;
+    let c = Key::Character("c".into());
+    let repeat = KeyInput {
+        repeat: true,
+        ..press(&c, Some("c"), CTRL)
+    };
+    assert_eq!(kitty(&repeat, flags).unwrap(), "\x1b[99;5:2u");
+    let up = Key::Named(NamedKey::ArrowUp);
+    let release = KeyInput {
+        state: ElementState::Released,
+        ..press(&up, None, NONE)
+    };
+    assert_eq!(kitty(&release, flags).unwrap(), "\x1b[1;1:3A");
+    // Releases are only reported when asked for
+    assert_eq!(kitty(&release, DISAMBIGUATE), None);
+    // and never for keys that still send text
+    let a = Key::Character("a".into());
+    let release = KeyInput {
+        state: ElementState::Released,
+        ..press(&a, Some("a"), NONE)
+    };
+    assert_eq!(kitty(&release, flags), None);
+}
+
+#[test]
+fn kitty_report_all_keys_with_alternates_and_text() {
+    let flags = KeyboardFlags::REPORT_ALL_KEYS
+        | KeyboardFlags::REPORT_ALTERNATES
+        | KeyboardFlags::REPORT_TEXT;
+    let a = Key::Character("a".into());
+    assert_eq!(
+        kitty(&press(&a, Some("a"), NONE), flags).unwrap(),
+        "\x1b[97;;97u"
+    );
+    let upper = Key::Character("A".into());
+    assert_eq!(
+        kitty(&press(&upper, Some("A"), SHIFT), flags).unwrap(),
+        "\x1b[97:65;2;65u"
+    );
+    let enter = Key::Named(NamedKey::Enter);
+    assert_eq!(
+        kitty(&press(&enter, Some("\r"), NONE), flags).unwrap(),
+        "\x1b[13u"
+    );
+    let shift = KeyInput {
+        location: KeyLocation::Right,
+        ..press(&Key::Named(NamedKey::Shift), None, SHIFT)
+    };
+    assert_eq!(kitty(&shift, flags).unwrap(), "\x1b[57447;2u");
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 6. Mouse reporting
+// ─────────────────────────────────────────────────────────────────────────
+
+fn mouse_at(action: MouseAction, col: usize, row: usize, mods: ModifiersState) -> MouseEvent {
+    MouseEvent {
+        action,
+        col,
+        row,
+        x: col as u32 * 10,
+        y: row as u32 * 20,
+        mods,
+    }
+}
+
+fn report(event: MouseEvent, modes: TermMode) -> Option<Vec<u8>> {
+    mouse::encode(&event, modes)
+}
+
+const LEFT_PRESS: MouseAction = MouseAction::Press(MouseButton::Left);
+const LEFT_RELEASE: MouseAction = MouseAction::Release(MouseButton::Left);
+
+#[test]
+fn mouse_untracked_by_default() {
+    let event = mouse_at(LEFT_PRESS, 0, 0, NONE);
+    assert_eq!(report(event, TermMode::default()), None);
+}
+
+#[test]
+fn mouse_legacy_encoding() {
+    let modes = TermMode::MOUSE_NORMAL;
+    let event = mouse_at(LEFT_PRESS, 2, 4, NONE);
+    assert_eq!(report(event, modes).unwrap(), b"\x1b[M\x20\x23\x25");
+    // Releases don't say which button
+    let event = mouse_at(LEFT_RELEASE, 2, 4, CTRL);
+    assert_eq!(report(event, modes).unwrap(), b"\x1b[M\x33\x23\x25");
+    // Too far for a single byte
+    let event = mouse_at(LEFT_PRESS, 300, 0, NONE);
+    assert_eq!(report(event, modes), None);
+}
+
+#[test]
+fn mouse_x10_reports_presses_only() {
+    let modes = TermMode::MOUSE_X10;
+    let event = mouse_at(LEFT_PRESS, 0, 0, SHIFT);
+    assert_eq!(report(event, modes).unwrap(), b"\x1b[M\x20\x21\x21");
+    assert_eq!(report(mouse_at(LEFT_RELEASE, 0, 0, NONE), modes), None);
+    let wheel = MouseAction::Press(MouseButton::WheelUp);
+    assert_eq!(report(mouse_at(wheel, 0, 0, NONE), modes), None);
+}
+
+#[test]
+fn mouse_motion_follows_tracking_mode() {
+    let drag = mouse_at(MouseAction::Motion(Some(MouseButton::Left)), 0, 0, NONE);
+    let hover = mouse_at(MouseAction::Motion(None), 0, 0, NONE);
+    assert_eq!(report(drag, TermMode::MOUSE_NORMAL), None);
+    let modes = TermMode::MOUSE_BUTTON_EVENT | TermMode::MOUSE_SGR;
+    assert_eq!(report(drag, modes).unwrap(), b"\x1b[<32;1;1M");
+    assert_eq!(report(hover, modes), None);
+    let modes = TermMode::MOUSE_ANY_EVENT | TermMode::MOUSE_SGR;
+    assert_eq!(report(hover, modes).unwrap(), b"\x1b[<35;1;1M");
+}
+
+#[test]
+fn mouse_sgr_encoding() {
+    let modes = TermMode::MOUSE_NORMAL | TermMode::MOUSE_SGR;
+    let event = mouse_at(MouseAction::Press(MouseButton::Right), 299, 9, ALT);
+    assert_eq!(report(event, modes).unwrap(), b"\x1b[<10;300;10M");
+    let event = mouse_at(MouseAction::Release(MouseButton::Right), 299, 9, NONE);
+    assert_eq!(report(event, modes).unwrap(), b"\x1b[<2;300;10m");
+    let wheel = mouse_at(MouseAction::Press(MouseButton::WheelDown), 0, 0, NONE);
+    assert_eq!(report(wheel, modes).unwrap(), b"\x1b[<65;1;1M");
+}
+
+#[test]
+fn mouse_sgr_pixel_encoding() {
+    let modes = TermMode::MOUSE_NORMAL | TermMode::MOUSE_SGR_PIXELS;
+    let event = mouse_at(LEFT_PRESS, 3, 2, NONE);
+    assert_eq!(report(event, modes).unwrap(), b"\x1b[<0;31;41M");
+}
+
+#[test]
+fn mouse_urxvt_and_utf8_encodings() {
+    let modes = TermMode::MOUSE_NORMAL | TermMode::MOUSE_URXVT;
+    let event = mouse_at(LEFT_PRESS, 299, 0, NONE);
+    assert_eq!(report(event, modes).unwrap(), b"\x1b[32;300;1M");
+    let modes = TermMode::MOUSE_NORMAL | TermMode::MOUSE_UTF8;
+    // Column 300 + 32 as a two byte character
+    assert_eq!(
+        report(event, modes).unwrap(),
+        "\x1b[M\u{20}\u{14c}\u{21}".as_bytes()
+    );
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 7. Paste
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn paste_sends_line_breaks_as_enter() {
+    assert_eq!(paste::encode("ls\r\ncd /\n", false), b"ls\rcd /\r");
+    assert!(paste::is_multiline("a\nb"));
+    assert!(!paste::is_multiline("echo hi"));
+}
+
+#[test]
+fn bracketed_paste_wraps_text_as_is() {
+    assert_eq!(paste::encode("a\nb", true), b"\x1b[200~a\nb\x1b[201~");
+}
+
+#[test]
+fn bracketed_paste_cannot_end_early() {
+    let text = "safe\x1b[201~rm -rf ~\n\x1b[A";
+    assert_eq!(
+        paste::encode(text, true),
+        b"\x1b[200~saferm -rf ~\n[A\x1b[201~"
+    );
+}
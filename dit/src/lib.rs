@@ -1,7 +1,9 @@
 pub mod ansii;
 pub mod app;
+pub mod clipboard;
 pub mod dsa;
 pub mod font_manager;
+pub mod input;
 pub mod renderer;
 pub mod screen;
 pub mod shell;
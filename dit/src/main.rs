@@ -12,9 +12,10 @@ use dit::{
 };
 use winit::event_loop::{ControlFlow, EventLoop};
 fn main() {
-    let event_loop = EventLoop::new().unwrap();
-    event_loop.set_control_flow(ControlFlow::Poll);
-    let mut app = Application::new();
+    let event_loop = EventLoop::with_user_event().build().unwrap();
+    // Redraws are requested whenever something changes, the pty thread wakes us for output
+    event_loop.set_control_flow(ControlFlow::Wait);
+    let mut app = Application::new(event_loop.create_proxy());
     event_loop.run_app(&mut app).unwrap();
     // let file_finder = FileFinder::new();
     // //Input must be sanitized according to the same file sanitazation algo
@@ -375,7 +375,7 @@ impl DynamicBuffer {
 
     pub fn destroy(&self, device: &Device) {
         unsafe {
-            device.unmap_memory(self.device.memory);
+            device.unmap_memory(self.staging.memory);
             device.destroy_buffer(self.device.buffer, None);
             device.destroy_buffer(self.staging.buffer, None);
             device.free_memory(self.staging.memory, None);
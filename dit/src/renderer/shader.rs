@@ -18,6 +18,8 @@ pub fn create_shader_module(device: &Device, code: &[u32]) -> vk::ShaderModule {
 pub struct Vertex {
     pub pos: [f32; 2],
     pub uv: [f32; 2],
+    /// 1.0 for cells inside the selection, which are drawn inverted
+    pub selected: f32,
 }
 impl Vertex {
     pub fn get_binding_description() -> vk::VertexInputBindingDescription {
@@ -26,7 +28,7 @@ impl Vertex {
             .stride(size_of::<Vertex>() as _)
             .input_rate(vk::VertexInputRate::VERTEX)
     }
-    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
+    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
         let position_desc = vk::VertexInputAttributeDescription::default()
             .binding(0)
             .location(0)
@@ -37,6 +39,11 @@ impl Vertex {
             .location(1)
             .format(vk::Format::R32G32_SFLOAT)
             .offset(offset_of!(Vertex, uv) as _);
-        [position_desc, uv_desc]
+        let selected_desc = vk::VertexInputAttributeDescription::default()
+            .binding(0)
+            .location(2)
+            .format(vk::Format::R32_SFLOAT)
+            .offset(offset_of!(Vertex, selected) as _);
+        [position_desc, uv_desc, selected_desc]
     }
 }
@@ -15,10 +15,6 @@ pub const MAX_FRAMES_IN_FLIGHT: u32 = 2;
 
 pub struct VkApplication {
     pub resize_dimensions: [u32; 2],
-    pub is_left_clicked: bool,
-    pub cursor_position: [i32; 2],
-    pub cursor_delta: Option<[i32; 2]>,
-    pub wheel_delta: Option<f32>,
     pub dirty_swapchain: bool,
     pub vk_context: VkContext,
     pub queue_families_indices: QueueFamiliesIndices,
@@ -174,10 +170,6 @@ impl VkApplication {
 
         Self {
             resize_dimensions: [WIDTH, HEIGHT],
-            is_left_clicked: false,
-            cursor_position: [0, 0],
-            cursor_delta: None,
-            wheel_delta: None,
             dirty_swapchain: false,
             vk_context,
             queue_families_indices,
@@ -283,6 +275,38 @@ impl VkApplication {
         }
         false
     }
+    /// Uploads a mesh whose size changed, eg after the grid was resized. The command buffers
+    /// keep drawing the old one until the swapchain is recreated.
+    pub fn replace_mesh(&mut self, mesh: &Mesh) {
+        self.wait_gpu_idle();
+        let vertex_size = byte_size(&mesh.vertices) as vk::DeviceSize;
+        if vertex_size > self.vertex_buffer.staging.memory_size {
+            self.vertex_buffer.destroy(self.vk_context.device());
+            self.vertex_buffer = DynamicBuffer::new(
+                vertex_size * 4,
+                &self.vk_context,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+            );
+        }
+        self.vertex_buffer.full_copy::<u32, Vertex>(
+            &self.vk_context,
+            self.command_pool,
+            self.graphics_queue,
+            &mesh.vertices,
+        );
+        unsafe {
+            let device = self.vk_context.device();
+            device.free_memory(self.index_buffer_memory, None);
+            device.destroy_buffer(self.index_buffer, None);
+        }
+        (self.index_buffer, self.index_buffer_memory) = create_index_buffer(
+            &self.vk_context,
+            self.transient_command_pool,
+            self.graphics_queue,
+            &mesh.indices,
+        );
+        self.model_index_count = mesh.indices.len();
+    }
     pub fn write_to_device(&mut self, regions: &[vk::BufferCopy]) {
         self.vertex_buffer.transfer_to_device(
             self.vk_context.device(),
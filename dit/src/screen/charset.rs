@@ -0,0 +1,57 @@
+//! G0–G3 character sets and the shifts picking which one printable ASCII is drawn from
+use crate::ansii::details::{Charset, CharsetSlot};
+
+/// DEC Special Graphics from `_` to `~`
+pub const DEC_SPECIAL_GRAPHICS: [char; 32] = [
+    ' ', '◆', '▒', '␉', '␌', '␍', '␊', '°', '±', '␤', '␋', '┘', '┐', '┌', '└', '┼', '⎺', '⎻', '─',
+    '⎼', '⎽', '├', '┤', '┴', '┬', '│', '≤', '≥', 'π', '≠', '£', '·',
+];
+
+/// `ch` as it reads in `charset`. Only printable ASCII is ever translated.
+pub fn translate(charset: Charset, ch: char) -> char {
+    if !(' '..='~').contains(&ch) {
+        return ch;
+    }
+    match charset {
+        Charset::Ascii => ch,
+        Charset::DecSpecialGraphics if ch >= '_' => DEC_SPECIAL_GRAPHICS[ch as usize - 0x5f],
+        Charset::DecSpecialGraphics => ch,
+        Charset::Uk if ch == '#' => '£',
+        Charset::Uk => ch,
+        Charset::DecSupplemental => match ch {
+            ' ' => ' ',
+            // Where DEC's set differs from Latin-1, reserved positions are left as Latin-1
+            '(' => '¤',
+            'W' => 'Œ',
+            ']' => 'Ÿ',
+            'w' => 'œ',
+            '}' => 'ÿ',
+            _ => char::from_u32(ch as u32 + 0x80).unwrap_or(ch),
+        },
+    }
+}
+/// The designated sets and the shift state, saved along with the cursor
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Charsets {
+    slots: [Charset; 4],
+    /// Slot shifted into GL by SI, SO, LS2 or LS3
+    gl: usize,
+    /// Slot of a pending SS2 or SS3, for the next character only
+    single_shift: Option<usize>,
+}
+impl Charsets {
+    pub fn designate(&mut self, slot: CharsetSlot, charset: Charset) {
+        self.slots[slot as usize] = charset;
+    }
+    pub fn locking_shift(&mut self, slot: CharsetSlot) {
+        self.gl = slot as usize;
+    }
+    pub fn single_shift(&mut self, slot: CharsetSlot) {
+        self.single_shift = Some(slot as usize);
+    }
+    /// Translates a printed character through the set in GL, or the single shifted one
+    pub fn translate(&mut self, ch: char) -> char {
+        let slot = self.single_shift.take().unwrap_or(self.gl);
+        translate(self.slots[slot], ch)
+    }
+}
@@ -0,0 +1,69 @@
+//! Grapheme clusters held by cells and the number of columns characters take
+use std::fmt;
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// A base character along with the combining marks, variation selectors and joined characters
+/// attached to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grapheme {
+    base: char,
+    /// Everything after the base, most cells have nothing
+    rest: Option<Box<str>>,
+}
+impl Grapheme {
+    /// The first character, the one drawn
+    pub fn base(&self) -> char {
+        self.base
+    }
+    pub fn push(&mut self, ch: char) {
+        let mut rest = self.rest.take().map(String::from).unwrap_or_default();
+        rest.push(ch);
+        self.rest = Some(rest.into_boxed_str());
+    }
+    /// Bytes taken in UTF-8
+    pub fn len_utf8(&self) -> usize {
+        self.base.len_utf8() + self.rest.as_deref().map_or(0, str::len)
+    }
+    /// Whether `ch` extends the cluster by UAX #29 rather than starting the next one
+    pub fn continues_with(&self, ch: char) -> bool {
+        // Printable ASCII never joins with more ASCII, which spares most text the segmenting
+        if ch.is_ascii() && self.base.is_ascii() && self.rest.is_none() {
+            return false;
+        }
+        let mut text = self.to_string();
+        text.push(ch);
+        text.graphemes(true).nth(1).is_none()
+    }
+    /// Columns taken by the cluster as a whole, emoji sequences and variation selector 16 make
+    /// it wide
+    pub fn width(&self) -> usize {
+        self.to_string().width().clamp(1, 2)
+    }
+}
+impl From<char> for Grapheme {
+    fn from(base: char) -> Self {
+        Self { base, rest: None }
+    }
+}
+impl PartialEq<char> for Grapheme {
+    fn eq(&self, ch: &char) -> bool {
+        self.base == *ch && self.rest.is_none()
+    }
+}
+impl fmt::Display for Grapheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            self.base,
+            self.rest.as_deref().unwrap_or_default()
+        )
+    }
+}
+/// Columns `ch` takes on its own by East Asian Width, zero for combining marks, joiners and
+/// variation selectors
+pub fn char_width(ch: char) -> usize {
+    ch.width().unwrap_or(0)
+}
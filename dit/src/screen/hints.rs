@@ -0,0 +1,152 @@
+//! Text worth acting on in output without OSC 8 links, found by regex: URLs, paths with a line
+//! and column, git hashes and IP addresses. Hints mode labels every match on screen with a few
+//! letters, typing a label picks its match.
+use regex::Regex;
+
+use super::selection::Point;
+
+/// Letters labels are made of, the easiest to reach first
+pub const DEFAULT_HINT_ALPHABET: &str = "asdfghjklqwertyuiopzxcvbnm";
+
+/// What a match is, which decides how it's opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HintKind {
+    /// Handed to the link opener as is
+    Url,
+    /// A file, opened relative to the shell's working directory without any `:line:col`
+    Path,
+    /// Nothing to open, only copied or pasted
+    Text,
+}
+#[derive(Debug, Clone)]
+pub struct HintRule {
+    pub regex: Regex,
+    pub kind: HintKind,
+}
+impl HintRule {
+    /// Panics on an invalid pattern, meant for rules written in code
+    pub fn new(pattern: &str, kind: HintKind) -> Self {
+        Self {
+            regex: Regex::new(pattern).unwrap(),
+            kind,
+        }
+    }
+}
+/// URLs, paths, IPs then git hashes. Earlier rules win where matches overlap.
+pub fn default_rules() -> Vec<HintRule> {
+    vec![
+        HintRule::new(
+            r#"\b(?:https?|ftp|file)://[^\s<>"'`]*[^\s<>"'`.,:;!?)\]]"#,
+            HintKind::Url,
+        ),
+        // Either with a directory or with a line number, `main.rs` alone is too often a word
+        HintRule::new(
+            r"(?:~|\.{1,2})?/?(?:[\w.@+-]+/)+[\w.@+-]+(?::\d+){0,2}|[\w.@+-]+\.[A-Za-z]\w*(?::\d+){1,2}",
+            HintKind::Path,
+        ),
+        // IPv4, and IPv6 written out in full
+        HintRule::new(
+            r"\b(?:\d{1,3}\.){3}\d{1,3}(?::\d+)?\b|\b(?:[0-9a-fA-F]{1,4}:){7}[0-9a-fA-F]{1,4}\b",
+            HintKind::Text,
+        ),
+        HintRule::new(r"\b[0-9a-f]{7,40}\b", HintKind::Text),
+    ]
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HintMatch {
+    pub text: String,
+    pub kind: HintKind,
+    pub start: Point,
+    /// Inclusive
+    pub end: Point,
+    pub label: String,
+}
+/// The matches of hints mode and what has been typed of a label so far
+#[derive(Debug, Clone)]
+pub struct Hints {
+    pub matches: Vec<HintMatch>,
+    typed: String,
+}
+impl Hints {
+    pub fn new(matches: Vec<HintMatch>) -> Self {
+        Self {
+            matches,
+            typed: String::new(),
+        }
+    }
+    /// Matches whose label starts with what was typed
+    pub fn visible(&self) -> impl Iterator<Item = &HintMatch> {
+        self.matches
+            .iter()
+            .filter(|hint| hint.label.starts_with(&self.typed))
+    }
+    /// Adds a letter to the label being typed, returns the match once its whole label has been.
+    /// A letter no label continues with is ignored.
+    pub fn push(&mut self, ch: char) -> Option<&HintMatch> {
+        self.typed.push(ch);
+        if self.visible().next().is_none() {
+            self.typed.pop();
+            return None;
+        }
+        self.matches.iter().find(|hint| hint.label == self.typed)
+    }
+    pub fn pop(&mut self) {
+        self.typed.pop();
+    }
+    /// Whether any match is on lines `top..=bottom`
+    pub fn intersects_lines(&self, top: usize, bottom: usize) -> bool {
+        self.matches
+            .iter()
+            .any(|hint| top <= hint.end.line && bottom >= hint.start.line)
+    }
+}
+/// `count` labels, all the same length so none is the start of another
+pub fn labels(count: usize, alphabet: &str) -> Vec<String> {
+    let alphabet: Vec<char> = alphabet.chars().collect();
+    if alphabet.len() < 2 {
+        return Vec::new();
+    }
+    let mut len = 1;
+    while alphabet.len().pow(len) < count {
+        len += 1;
+    }
+    (0..count)
+        .map(|mut n| {
+            let mut label = vec![' '; len as usize];
+            for slot in label.iter_mut().rev() {
+                *slot = alphabet[n % alphabet.len()];
+                n /= alphabet.len();
+            }
+            label.into_iter().collect()
+        })
+        .collect()
+}
+/// Runs `rules` over a line of text, `points` holding the cell of each byte. Matches overlapping
+/// one of an earlier rule are dropped. Labels are left empty.
+pub fn find(text: &str, points: &[Point], rules: &[HintRule]) -> Vec<HintMatch> {
+    let mut matches: Vec<(usize, usize, HintKind)> = Vec::new();
+    for rule in rules {
+        for found in rule.regex.find_iter(text) {
+            let (start, end) = (found.start(), found.end());
+            if start == end
+                || matches
+                    .iter()
+                    .any(|&(taken_start, taken_end, _)| start < taken_end && taken_start < end)
+            {
+                continue;
+            }
+            matches.push((start, end, rule.kind));
+        }
+    }
+    matches.sort_by_key(|&(start, _, _)| start);
+    matches
+        .into_iter()
+        .map(|(start, end, kind)| HintMatch {
+            text: text[start..end].to_string(),
+            kind,
+            start: points[start],
+            end: points[end - 1],
+            label: String::new(),
+        })
+        .collect()
+}
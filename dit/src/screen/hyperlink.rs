@@ -0,0 +1,80 @@
+//! OSC 8 hyperlinks. Cells only carry a small id, the URIs live in a table on the screen which
+//! is pruned of links no cell refers to anymore.
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroU32,
+};
+
+/// Links kept before the table is first pruned
+const MIN_PRUNE_AT: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LinkId(NonZeroU32);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hyperlink {
+    /// The `id=` parameter. Text written under separate OSC 8 sequences with the same id and
+    /// URI is one link, like a URL an editor draws across several lines.
+    pub id: Option<String>,
+    pub uri: String,
+}
+/// The scheme of `uri`, lowercased
+pub fn scheme(uri: &str) -> Option<String> {
+    let (scheme, _) = uri.split_once(':')?;
+    let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    valid.then(|| scheme.to_ascii_lowercase())
+}
+#[derive(Debug)]
+pub struct Hyperlinks {
+    links: HashMap<LinkId, Hyperlink>,
+    /// Links with an `id=`, by id and URI
+    named: HashMap<(String, String), LinkId>,
+    next: NonZeroU32,
+    /// Size of the table at which it should be pruned next
+    prune_at: usize,
+}
+impl Default for Hyperlinks {
+    fn default() -> Self {
+        Self {
+            links: HashMap::new(),
+            named: HashMap::new(),
+            next: NonZeroU32::MIN,
+            prune_at: MIN_PRUNE_AT,
+        }
+    }
+}
+impl Hyperlinks {
+    pub fn get(&self, id: LinkId) -> Option<&Hyperlink> {
+        self.links.get(&id)
+    }
+    /// Gives an opened link its id, the one already handed out when the link is named and was
+    /// opened before
+    pub fn insert(&mut self, link: Hyperlink) -> LinkId {
+        if let Some(name) = &link.id
+            && let Some(&id) = self.named.get(&(name.clone(), link.uri.clone()))
+        {
+            return id;
+        }
+        let id = LinkId(self.next);
+        // Wrapping around takes billions of links, by then the early ones are long gone
+        self.next = self.next.checked_add(1).unwrap_or(NonZeroU32::MIN);
+        if let Some(name) = &link.id {
+            self.named.insert((name.clone(), link.uri.clone()), id);
+        }
+        self.links.insert(id, link);
+        id
+    }
+    /// Whether the table grew enough since it was last pruned to be worth scanning the cells
+    pub fn needs_pruning(&self) -> bool {
+        self.links.len() >= self.prune_at
+    }
+    /// Drops every link not in `live`
+    pub fn retain(&mut self, live: &HashSet<LinkId>) {
+        self.links.retain(|id, _| live.contains(id));
+        self.named.retain(|_, id| live.contains(id));
+        self.prune_at = MIN_PRUNE_AT.max(self.links.len() * 2);
+    }
+}
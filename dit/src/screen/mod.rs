@@ -1,34 +1,133 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+pub mod charset;
+pub mod grapheme;
+pub mod hints;
+pub mod hyperlink;
+pub mod osc;
+mod reflow;
 pub mod ring_buf;
+pub mod selection;
+pub mod tabs;
+use charset::Charsets;
+use grapheme::Grapheme;
+use hints::{HintMatch, HintRule, Hints};
+use hyperlink::{Hyperlink, Hyperlinks, LinkId};
+use osc::{ClipboardRequest, SemanticPrompt, TITLE_STACK_DEPTH, WorkingDirectory};
+use reflow::{Position, reflow};
+use ring_buf::RingBuffer;
+use selection::{DEFAULT_WORD_SEPARATORS, Point, Selection, SelectionKind, SelectionRange};
+use tabs::TabStops;
+#[cfg(test)]
+mod tests;
 use atlas_gen::{allocator::ShelfAllocator, atlas::Atlas};
 use font_parser::{CellMetrics, TtfFont};
 use image::Rgb;
 
 use crate::{
-    ansii::{Handler, details::Attributes, utf_decoder::Utf8Decoder},
+    ansii::{
+        Handler, ParamGroup,
+        details::{
+            Attributes, Charset, CharsetSlot, DeviceAttributes, DeviceStatusReport, KeyboardFlags,
+            ModeStatus, TermMode, secondary_attributes,
+        },
+        utf_decoder::Utf8Decoder,
+    },
     renderer::{Mesh, shader::Vertex},
 };
 
+/// How the cursor is drawn, picked by DECSCUSR
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CursorShape {
+    /// The cell is drawn inverted
+    #[default]
+    Block,
+    Underline,
+    /// A bar along the left edge of the cell
+    Bar,
+}
 #[derive(Debug, Default, Clone)]
 pub struct Cursor {
     pub y: usize,
     pub col: usize,
+    /// In the shown half of a blink. A steady cursor is drawn either way, DECTCEM hides both.
     pub visible: bool,
     pub blinking: bool,
+    pub shape: CursorShape,
+    /// Set after printing into the last column with autowrap on, the wrap happens when the
+    /// next character arrives. Any cursor movement clears it.
+    pub pending_wrap: bool,
+}
+/// Cursor state stored by DECSC and modes 1048/1049
+#[derive(Debug, Default, Clone)]
+struct SavedCursor {
+    y: usize,
+    col: usize,
+    attrs: Attributes,
+    /// DECOM
+    origin: bool,
+    pending_wrap: bool,
+    charsets: Charsets,
+}
+/// How much of a double width character a cell holds
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CellWidth {
+    #[default]
+    Single,
+    /// The left half, holding the character
+    Wide,
+    /// The right half, or padding left at the end of a row a wide character didn't fit on
+    Spacer,
 }
 #[derive(Clone)]
 pub struct Cell {
-    pub ch: char,
+    pub ch: Grapheme,
+    pub width: CellWidth,
     pub cell_attr: Attributes,
+    /// The OSC 8 hyperlink the character was written under
+    pub link: Option<LinkId>,
 }
 impl Default for Cell {
     fn default() -> Self {
         Self {
-            ch: ' ',
+            ch: ' '.into(),
+            width: CellWidth::Single,
             cell_attr: Attributes::default(),
+            link: None,
         }
     }
 }
+bitflags::bitflags! {
+    /// Where shell integration said the parts of a command start, through OSC 133
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct Marks: u8 {
+        /// The prompt
+        const PROMPT = 1 << 0;
+        /// The command line typed at the prompt
+        const COMMAND = 1 << 1;
+        /// The command's output
+        const OUTPUT = 1 << 2;
+        /// Whatever comes once the command finished, its output ends on the line before
+        const FINISHED = 1 << 3;
+    }
+}
+/// Per-row state kept next to the cells, both on screen and in history
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LineInfo {
+    /// The row continues on the next one through autowrap rather than ending in a newline
+    pub wrapped: bool,
+    /// Shell integration marks of the logical line starting on this row
+    pub marks: Marks,
+    /// On a prompt's row, the exit code of the command run from it once it finished
+    pub exit_code: Option<i32>,
+}
+/// A line that has scrolled off the top of the primary screen
+#[derive(Clone, Default)]
+pub struct Row {
+    pub cells: Vec<Cell>,
+    pub info: LineInfo,
+}
+/// Lines of history kept unless configured otherwise
+pub const DEFAULT_SCROLLBACK_LINES: usize = 10_000;
 // defines a buffer write and sees if
 #[derive(Debug, Clone)]
 pub struct Range {
@@ -69,11 +168,79 @@ impl From<Range> for ash::vk::BufferCopy {
     }
 }
 pub struct Screen {
+    /// The grid being displayed and written to, the alternate screen while it is active
     pub cells: Vec<Cell>,
+    /// The grid that is not active: the alternate screen normally, the primary one while
+    /// the alternate screen is shown
+    inactive_cells: Vec<Cell>,
+    /// One entry per visible row of `cells`
+    pub lines: Vec<LineInfo>,
+    inactive_lines: Vec<LineInfo>,
+    /// Each screen saves its own cursor
+    saved_cursor: Option<SavedCursor>,
+    inactive_saved_cursor: Option<SavedCursor>,
+    /// Where the cursor was on the primary screen when the alternate one was entered, keeps
+    /// its line on screen when the primary one is rewrapped underneath
+    primary_cursor: Position,
     pub cursor: Cursor,
+    /// Grid cell and shape of the cursor as it is in the mesh, `None` when it isn't drawn
+    drawn_cursor: Option<(usize, CursorShape)>,
+    charsets: Charsets,
     pub y_size: usize,
     pub col_size: usize,
+    tab_stops: TabStops,
+    /// DECSTBM scrolling region, inclusive rows
+    top_margin: usize,
+    bottom_margin: usize,
+    /// DECSLRM margins, inclusive columns. They span the whole row unless DECLRMM is set
+    left_margin: usize,
+    right_margin: usize,
     pub accumulator: Utf8Decoder,
+    pub modes: TermMode,
+    /// modifyOtherKeys level set through XTMODKEYS, 0 to 2
+    pub modify_other_keys: u8,
+    /// Kitty keyboard protocol flags stack of the active screen, the top entry is in effect
+    keyboard_flags: Vec<KeyboardFlags>,
+    inactive_keyboard_flags: Vec<KeyboardFlags>,
+    /// Attributes applied to every character written, set through SGR
+    pub attrs: Attributes,
+    /// Hyperlink applied to every character written, set through OSC 8
+    link: Option<LinkId>,
+    /// Every link some cell may still refer to
+    hyperlinks: Hyperlinks,
+    /// Link under the pointer while Ctrl is held, drawn underlined
+    hovered_link: Option<LinkId>,
+    /// Replies to terminal queries (DA, DSR, DECRQM, …) waiting to be written to the pty.
+    /// Drained by the application every update.
+    pub outbound: Vec<u8>,
+    /// OSC 52 clipboard accesses waiting for the application, drained every update
+    pub clipboard_requests: Vec<ClipboardRequest>,
+    /// Window title and icon name set through OSC 0/1/2, `None` until the child sets one
+    title: Option<String>,
+    icon_name: Option<String>,
+    /// Icon names and titles saved by XTWINOPS 22, newest last
+    title_stack: Vec<(Option<String>, Option<String>)>,
+    /// Set when the title changes, until the application picks it up
+    title_changed: bool,
+    /// Where the shell last said it is, through OSC 7
+    pub working_directory: Option<WorkingDirectory>,
+    /// Absolute line of the last prompt, the exit code of the command run from it goes there
+    prompt_line: Option<usize>,
+    /// Between OSC 133 `C` and `D`
+    command_running: bool,
+    /// Rows scrolled off the top of the primary screen, oldest first
+    pub scrollback: RingBuffer<Row>,
+    /// How many rows the viewport is scrolled back into history, 0 follows the live screen
+    pub display_offset: usize,
+    /// Absolute line of the oldest row in history, it grows as rows are evicted or cleared
+    history_start: usize,
+    selection: Option<Selection>,
+    /// Matches labelled while hints mode is on
+    hints: Option<Hints>,
+    /// Characters that end a word for double-click selection, whitespace always does
+    pub word_separators: String,
+    // set when the whole viewport has to be re-meshed, eg after scrolling through history
+    viewport_dirty: bool,
     font: TtfFont,
     cell_metrics: CellMetrics,
     // theese sohuld be sorted
@@ -81,6 +248,22 @@ pub struct Screen {
     atlas: Atlas<char, Rgb<u8>, ShelfAllocator>,
     pub mesh: Mesh,
 }
+/// Version reported through DA2, major * 100 + minor * 10 + patch
+const TERMINAL_VERSION: u16 = 10;
+/// Entries kept on each kitty keyboard flags stack, pushing more evicts the oldest
+const KEYBOARD_FLAGS_DEPTH: usize = 16;
+/// A selection background quad, the glyph quad, then a decoration quad drawn over the glyph
+const VERTICES_PER_CELL: usize = 12;
+/// Drawn over a cell's glyph, like a selected blank cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decoration {
+    /// A line along the bottom
+    Underline,
+    /// A bar down the left edge, on the first cell of a failed command's prompt
+    FailedCommand,
+    /// A thinner one, for the cursor
+    Bar,
+}
 // An arbitrary character for monospace fonts
 #[inline(always)]
 fn calculate_dims(
@@ -106,11 +289,48 @@ impl Screen {
         let (y_size, col_size) = calculate_dims(logical_screen_size, &cell_metrics);
         let cells = vec![Cell::default(); y_size * col_size];
         Self {
+            inactive_cells: cells.clone(),
             cells,
+            lines: vec![LineInfo::default(); y_size],
+            inactive_lines: vec![LineInfo::default(); y_size],
+            saved_cursor: None,
+            inactive_saved_cursor: None,
+            primary_cursor: Position::default(),
+            drawn_cursor: None,
+            charsets: Charsets::default(),
             cursor: Cursor::default(),
             y_size,
             col_size,
+            tab_stops: TabStops::new(col_size),
+            top_margin: 0,
+            bottom_margin: y_size.saturating_sub(1),
+            left_margin: 0,
+            right_margin: col_size.saturating_sub(1),
             accumulator: Utf8Decoder::new(),
+            modes: TermMode::default(),
+            modify_other_keys: 0,
+            keyboard_flags: Vec::new(),
+            inactive_keyboard_flags: Vec::new(),
+            attrs: Attributes::default(),
+            link: None,
+            hyperlinks: Hyperlinks::default(),
+            hovered_link: None,
+            outbound: Vec::new(),
+            clipboard_requests: Vec::new(),
+            title: None,
+            icon_name: None,
+            title_stack: Vec::new(),
+            title_changed: false,
+            working_directory: None,
+            prompt_line: None,
+            command_running: false,
+            scrollback: RingBuffer::with_capacity(DEFAULT_SCROLLBACK_LINES),
+            display_offset: 0,
+            history_start: 0,
+            selection: None,
+            hints: None,
+            word_separators: DEFAULT_WORD_SEPARATORS.to_string(),
+            viewport_dirty: false,
             font,
             cell_metrics,
             dirty_cells: HashSet::new(),
@@ -123,115 +343,762 @@ impl Screen {
         new_font_size: f32,
         logical_screen_size: winit::dpi::LogicalSize<f32>,
     ) {
-        if new_font_size != self.cell_metrics.font_size {
+        let font_changed = new_font_size != self.cell_metrics.font_size;
+        if font_changed {
             // recalculate cell_metrics if font_size changes
             self.cell_metrics = CellMetrics::new(new_font_size, &self.font)
         }
         let (new_y_size, new_col_size) = calculate_dims(logical_screen_size, &self.cell_metrics);
+        // Nothing fits in a minimised window, keep the old grid until it comes back
+        if new_y_size == 0 || new_col_size == 0 {
+            return;
+        }
+        // Most resize events while dragging don't add or remove a whole cell
+        if (new_y_size, new_col_size) == (self.y_size, self.col_size) {
+            if font_changed {
+                self.construct_mesh();
+            }
+            return;
+        }
+        // The primary screen is rewrapped along with history, the alternate screen is only
+        // cropped or padded since whatever runs there redraws it anyway
+        if self.modes.contains(TermMode::ALT_SCREEN) {
+            let (cells, lines) = (
+                std::mem::take(&mut self.inactive_cells),
+                std::mem::take(&mut self.inactive_lines),
+            );
+            let old = self.primary_cursor;
+            let (cells, lines, cursor) =
+                self.reflow_primary(cells, lines, old, new_y_size, new_col_size);
+            (self.inactive_cells, self.inactive_lines) = (cells, lines);
+            self.primary_cursor = cursor;
+            // A position saved by 1049 is where the cursor was and moves along with it, one
+            // saved elsewhere before is only kept on the grid
+            if let Some(saved) = self.inactive_saved_cursor.as_mut() {
+                if (saved.y, saved.col) == (old.row, old.col) {
+                    (saved.y, saved.col) = (cursor.row, cursor.col);
+                } else {
+                    saved.y = saved.y.min(new_y_size - 1);
+                    saved.col = saved.col.min(new_col_size - 1);
+                }
+            }
+            self.cells = self.crop_grid(&self.cells, new_y_size, new_col_size);
+            self.lines.resize(new_y_size, LineInfo::default());
+            self.cursor.y = self.cursor.y.min(new_y_size - 1);
+            self.cursor.col = self.cursor.col.min(new_col_size - 1);
+            self.cursor.pending_wrap = false;
+        } else {
+            let (cells, lines) = (
+                std::mem::take(&mut self.cells),
+                std::mem::take(&mut self.lines),
+            );
+            let (cells, lines, cursor) = self.reflow_primary(
+                cells,
+                lines,
+                Position {
+                    row: self.cursor.y,
+                    col: self.cursor.col,
+                },
+                new_y_size,
+                new_col_size,
+            );
+            (self.cells, self.lines) = (cells, lines);
+            (self.cursor.y, self.cursor.col) = (cursor.row, cursor.col);
+            // The wrapped character may no longer be in the last column
+            if self.cursor.pending_wrap && self.cursor.col + 1 < new_col_size {
+                self.cursor.col += 1;
+                self.cursor.pending_wrap = false;
+            }
+            self.inactive_cells = self.crop_grid(&self.inactive_cells, new_y_size, new_col_size);
+            self.inactive_lines.resize(new_y_size, LineInfo::default());
+        }
         self.col_size = new_col_size;
         self.y_size = new_y_size;
-        self.cells
-            .resize_with(new_col_size * new_y_size, Default::default);
-        self.construct_mesh();
+        self.tab_stops = TabStops::new(new_col_size);
+        self.display_offset = 0;
+        // Rewrapping moved the selected text, the matches and the prompts around
+        self.selection = None;
+        self.hints = None;
+        self.prompt_line = self.find_mark(Marks::PROMPT, self.grid_line(self.y_size), true);
+        self.reset_margins();
         // must reconstruct the mesh from scratch
+        self.construct_mesh();
     }
-    pub fn construct_mesh(&mut self) {
-        let mut vertices = Vec::new();
-        let mut indices = Vec::new();
-        let mut index_offset = 0u32;
-        let mut y_cell = 0.0;
-        for y in 0..self.y_size {
-            let mut x_cell = 0.0;
-            let y_start = y * self.col_size;
-            let y_slice = &self.cells[y_start..y_start + self.col_size];
-            for cell in y_slice {
-                let baseline_x = x_cell;
-                let baseline_y = 1080.0 - (y_cell + self.cell_metrics.baseline);
-                let x0;
-                let y0;
-                let x1;
-                let y1;
-                let (u0, v0, u1, v1);
-                if cell.ch.is_whitespace() {
-                    // Use full cell bounds for whitespace quad
-                    x0 = baseline_x;
-                    y0 = y_cell;
-                    x1 = baseline_x + self.cell_metrics.width;
-                    y1 = y_cell + self.cell_metrics.height;
-                    // Zero UVs
-                    u0 = 0.0;
-                    v0 = 0.0;
-                    u1 = 0.0;
-                    v1 = 0.0;
-                } else {
-                    // Safe glyph lookup (fallback to empty quad if missing)
-                    if let Some(gid) = self.font.lookup(cell.ch as u32) {
-                        if let Ok(Some(glyph)) = self.font.parse_gid(gid as u16) {
-                            let header = glyph.get_header();
-
-                            x0 = baseline_x + header.x_min as f32 * self.cell_metrics.scale;
-                            y0 = baseline_y + header.y_max as f32 * self.cell_metrics.scale;
-                            x1 = baseline_x + header.x_max as f32 * self.cell_metrics.scale;
-                            y1 = baseline_y + header.y_min as f32 * self.cell_metrics.scale;
-
-                            ([u0, v0], [u1, v1]) = self.atlas.get_uv(cell.ch);
-                        } else {
-                            // Failed parse → fallback to empty quad
-                            x0 = baseline_x;
-                            y0 = y_cell;
-                            x1 = baseline_x + self.cell_metrics.width;
-                            y1 = y_cell + self.cell_metrics.height;
-
-                            u0 = 0.0;
-                            v0 = 0.0;
-                            u1 = 0.0;
-                            v1 = 0.0;
-                        }
-                    } else {
-                        // Missing glyph → fallback to empty quad
-                        x0 = baseline_x;
-                        y0 = y_cell;
-                        x1 = baseline_x + self.cell_metrics.width;
-                        y1 = y_cell + self.cell_metrics.height;
-
-                        u0 = 0.0;
-                        v0 = 0.0;
-                        u1 = 0.0;
-                        v1 = 0.0;
+    /// Rewraps the primary grid together with history to the new size. Returns the new grid, its
+    /// line info and the cursor's new position on it.
+    fn reflow_primary(
+        &mut self,
+        cells: Vec<Cell>,
+        lines: Vec<LineInfo>,
+        cursor: Position,
+        new_y_size: usize,
+        new_col_size: usize,
+    ) -> (Vec<Cell>, Vec<LineInfo>, Position) {
+        let mut rows = self.scrollback.drain();
+        let history = rows.len();
+        rows.extend(
+            cells
+                .chunks(self.col_size)
+                .zip(lines)
+                .map(|(cells, info)| Row {
+                    cells: cells.to_vec(),
+                    info,
+                }),
+        );
+        let cursor = Position {
+            row: history + cursor.row,
+            col: cursor.col,
+        };
+        let (mut rows, cursor) = reflow(rows, new_col_size, cursor);
+        // Blank rows below the cursor are dropped rather than pushing content into history
+        while rows.len() > cursor.row + 1 && rows.last().is_some_and(|row| row.cells.is_empty()) {
+            rows.pop();
+        }
+        // Whatever doesn't fit goes to history. Only text below the cursor can push the
+        // cursor's own line there, it then stays on the top row.
+        let history = rows.len().saturating_sub(new_y_size);
+        let grid = rows.split_off(history);
+        for row in rows {
+            self.scrollback.push(row);
+        }
+        let mut cells = Vec::with_capacity(new_y_size * new_col_size);
+        let mut lines = Vec::with_capacity(new_y_size);
+        for mut row in grid {
+            row.cells.resize_with(new_col_size, Cell::default);
+            cells.extend(row.cells);
+            lines.push(row.info);
+        }
+        cells.resize_with(new_y_size * new_col_size, Cell::default);
+        lines.resize(new_y_size, LineInfo::default());
+        let cursor = Position {
+            row: cursor.row.saturating_sub(history),
+            col: cursor.col,
+        };
+        (cells, lines, cursor)
+    }
+    /// Copies `grid` into a `new_y_size` x `new_col_size` grid, keeping the top left corner.
+    fn crop_grid(&self, grid: &[Cell], new_y_size: usize, new_col_size: usize) -> Vec<Cell> {
+        let mut cells = vec![Cell::default(); new_y_size * new_col_size];
+        let cols = self.col_size.min(new_col_size);
+        for (y, row) in grid.chunks(self.col_size).take(new_y_size).enumerate() {
+            cells[y * new_col_size..y * new_col_size + cols].clone_from_slice(&row[..cols]);
+        }
+        cells
+    }
+    pub fn cell_metrics(&self) -> &CellMetrics {
+        &self.cell_metrics
+    }
+    /// The cell under a point in logical pixels, clamped to the grid
+    pub fn cell_at(&self, x: f64, y: f64) -> (usize, usize) {
+        let col = (x.max(0.0) / self.cell_metrics.width as f64) as usize;
+        let row = (y.max(0.0) / self.cell_metrics.height as f64) as usize;
+        (col.min(self.col_size - 1), row.min(self.y_size - 1))
+    }
+    /// Width and height of the text area in pixels
+    pub fn pixel_size(&self) -> (u32, u32) {
+        (
+            (self.col_size as f32 * self.cell_metrics.width) as u32,
+            (self.y_size as f32 * self.cell_metrics.height) as u32,
+        )
+    }
+    /// Changes how many lines of history are kept, dropping the oldest ones if needed.
+    pub fn set_scrollback_limit(&mut self, lines: usize) {
+        let mut scrollback = RingBuffer::with_capacity(lines);
+        let kept = self.scrollback.len().saturating_sub(lines);
+        for row in self.scrollback.iter().skip(kept) {
+            scrollback.push(row.clone());
+        }
+        self.scrollback = scrollback;
+        self.history_start += kept;
+        self.forget_evicted_selection();
+        self.display_offset = self.display_offset.min(self.scrollback.len());
+        self.viewport_dirty = true;
+    }
+    /// Scrolls the viewport through history. Positive values move towards older lines.
+    /// History isn't reachable from the alternate screen.
+    pub fn scroll_viewport(&mut self, lines: isize) {
+        if self.modes.contains(TermMode::ALT_SCREEN) {
+            return;
+        }
+        let offset = self
+            .display_offset
+            .saturating_add_signed(lines)
+            .min(self.scrollback.len());
+        if offset != self.display_offset {
+            self.display_offset = offset;
+            self.viewport_dirty = true;
+        }
+    }
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll_viewport(-(self.display_offset as isize));
+    }
+    /// Absolute line shown at viewport row `y`
+    fn viewport_line(&self, y: usize) -> usize {
+        self.history_start + self.scrollback.len() - self.display_offset + y
+    }
+    /// Absolute line of grid row `y`
+    fn grid_line(&self, y: usize) -> usize {
+        self.history_start + self.scrollback.len() + y
+    }
+    /// Cells and line info of an absolute line, `None` once it has left history or past the
+    /// bottom of the grid
+    fn line(&self, line: usize) -> Option<(&[Cell], LineInfo)> {
+        let index = line.checked_sub(self.history_start)?;
+        let history = self.scrollback.len();
+        if index < history {
+            let row = &self.scrollback[index];
+            return Some((&row.cells, row.info));
+        }
+        let y = index - history;
+        let start = y * self.col_size;
+        Some((
+            self.cells.get(start..start + self.col_size)?,
+            *self.lines.get(y)?,
+        ))
+    }
+    fn line_info_mut(&mut self, line: usize) -> Option<&mut LineInfo> {
+        let index = line.checked_sub(self.history_start)?;
+        let history = self.scrollback.len();
+        if index < history {
+            return Some(&mut self.scrollback.get_mut(index)?.info);
+        }
+        self.lines.get_mut(index - history)
+    }
+    /// The nearest absolute line carrying `mark`, above `from` when `up` and below it otherwise
+    fn find_mark(&self, mark: Marks, from: usize, up: bool) -> Option<usize> {
+        let has_mark = |line: &usize| {
+            self.line(*line)
+                .is_some_and(|(_, info)| info.marks.contains(mark))
+        };
+        if up {
+            (self.history_start..from).rev().find(has_mark)
+        } else {
+            (from + 1..self.grid_line(self.y_size)).find(has_mark)
+        }
+    }
+    /// Moves the viewport so the previous or next prompt is at its top, as far as history goes
+    pub fn scroll_to_prompt(&mut self, up: bool) {
+        if let Some(line) = self.find_mark(Marks::PROMPT, self.viewport_line(0), up) {
+            let offset = self.grid_line(0).saturating_sub(line);
+            self.scroll_viewport(offset as isize - self.display_offset as isize);
+        }
+    }
+    /// Selects the output of the last command that finished, returns whether it printed any
+    pub fn select_last_output(&mut self) -> bool {
+        let Some(end) = self.find_mark(Marks::FINISHED, self.grid_line(self.y_size), true) else {
+            return false;
+        };
+        // The output mark may share the finished command's line when it printed nothing
+        let Some(start) = self.find_mark(Marks::OUTPUT, end + 1, true) else {
+            return false;
+        };
+        if start == end {
+            return false;
+        }
+        self.selection = Some(Selection {
+            kind: SelectionKind::Line,
+            anchor: Point {
+                line: start,
+                col: 0,
+            },
+            extent: Point {
+                line: end - 1,
+                col: 0,
+            },
+        });
+        self.viewport_dirty = true;
+        true
+    }
+    /// The character at `point`, the right half of a wide character reads as the whole
+    fn char_at(&self, point: Point) -> char {
+        self.line(point.line)
+            .and_then(|(cells, _)| {
+                let col = match cells.get(point.col)?.width {
+                    CellWidth::Spacer
+                        if point.col > 0 && cells[point.col - 1].width == CellWidth::Wide =>
+                    {
+                        point.col - 1
+                    }
+                    _ => point.col,
+                };
+                cells.get(col)
+            })
+            .map_or(' ', |cell| cell.ch.base())
+    }
+    fn is_separator(&self, ch: char) -> bool {
+        ch.is_whitespace() || self.word_separators.contains(ch)
+    }
+    /// The cell before `point`, following a soft wrap back onto the previous line
+    fn prev_in_line(&self, point: Point) -> Option<Point> {
+        if point.col > 0 {
+            return Some(Point {
+                col: point.col - 1,
+                ..point
+            });
+        }
+        let line = point.line.checked_sub(1)?;
+        self.line(line)
+            .filter(|(_, info)| info.wrapped)
+            .map(|_| Point {
+                line,
+                col: self.col_size - 1,
+            })
+    }
+    /// The cell after `point`, following a soft wrap onto the next line
+    fn next_in_line(&self, point: Point) -> Option<Point> {
+        if point.col + 1 < self.col_size {
+            return Some(Point {
+                col: point.col + 1,
+                ..point
+            });
+        }
+        let line = point.line + 1;
+        (self.line(point.line).is_some_and(|(_, info)| info.wrapped) && self.line(line).is_some())
+            .then_some(Point { line, col: 0 })
+    }
+    /// First cell of the word under `point`, a separator is a word of its own
+    fn word_start(&self, mut point: Point) -> Point {
+        if self.is_separator(self.char_at(point)) {
+            return point;
+        }
+        while let Some(prev) = self.prev_in_line(point)
+            && !self.is_separator(self.char_at(prev))
+        {
+            point = prev;
+        }
+        point
+    }
+    fn word_end(&self, mut point: Point) -> Point {
+        if self.is_separator(self.char_at(point)) {
+            return point;
+        }
+        while let Some(next) = self.next_in_line(point)
+            && !self.is_separator(self.char_at(next))
+        {
+            point = next;
+        }
+        point
+    }
+    /// First line of the soft wrapped line `line` is part of
+    fn line_start(&self, mut line: usize) -> usize {
+        while line > 0 && self.line(line - 1).is_some_and(|(_, info)| info.wrapped) {
+            line -= 1;
+        }
+        line
+    }
+    fn line_end(&self, mut line: usize) -> usize {
+        while self.line(line).is_some_and(|(_, info)| info.wrapped) && self.line(line + 1).is_some()
+        {
+            line += 1;
+        }
+        line
+    }
+    /// The cells the selection covers, `None` without one or while a click hasn't been dragged
+    pub fn selection_range(&self) -> Option<SelectionRange> {
+        let selection = self.selection.as_ref()?;
+        let (start, end) = selection.ordered();
+        let (start, end) = match selection.kind {
+            SelectionKind::Simple | SelectionKind::Block if start == end => return None,
+            SelectionKind::Simple => (start, end),
+            SelectionKind::Word => (self.word_start(start), self.word_end(end)),
+            SelectionKind::Line => (
+                Point {
+                    line: self.line_start(start.line),
+                    col: 0,
+                },
+                Point {
+                    line: self.line_end(end.line),
+                    col: self.col_size - 1,
+                },
+            ),
+            SelectionKind::Block => {
+                let (anchor, extent) = (selection.anchor.col, selection.extent.col);
+                return Some(SelectionRange {
+                    start: Point {
+                        line: start.line,
+                        col: anchor.min(extent),
+                    },
+                    end: Point {
+                        line: end.line,
+                        col: anchor.max(extent),
+                    },
+                    block: true,
+                });
+            }
+        };
+        Some(SelectionRange {
+            start,
+            end,
+            block: false,
+        })
+    }
+    /// Starts a selection at viewport cell (`col`, `y`), replacing the previous one
+    pub fn start_selection(&mut self, kind: SelectionKind, col: usize, y: usize) {
+        let point = Point {
+            line: self.viewport_line(y),
+            col,
+        };
+        self.selection = Some(Selection::new(kind, point));
+        self.viewport_dirty = true;
+    }
+    /// Moves the end of the selection to viewport cell (`col`, `y`)
+    pub fn update_selection(&mut self, col: usize, y: usize) {
+        let point = Point {
+            line: self.viewport_line(y),
+            col,
+        };
+        if let Some(selection) = self.selection.as_mut()
+            && selection.extent != point
+        {
+            selection.extent = point;
+            self.viewport_dirty = true;
+        }
+    }
+    pub fn clear_selection(&mut self) {
+        if self.selection.take().is_some() {
+            self.viewport_dirty = true;
+        }
+    }
+    /// Starts hints mode, labelling the matches of `rules` on screen. Lines soft wrapped into
+    /// view are searched whole, from history or below the viewport. Returns how many matches
+    /// were found, hints mode stays off without any.
+    pub fn start_hints(&mut self, rules: &[HintRule], alphabet: &str) -> usize {
+        let (top, bottom) = (self.viewport_line(0), self.viewport_line(self.y_size - 1));
+        let mut line = top;
+        while line > self.history_start && self.line(line - 1).is_some_and(|(_, info)| info.wrapped)
+        {
+            line -= 1;
+        }
+        let mut matches: Vec<HintMatch> = Vec::new();
+        while line <= bottom {
+            let (mut text, mut points) = (String::new(), Vec::new());
+            while let Some((cells, info)) = self.line(line) {
+                for (col, cell) in cells.iter().enumerate() {
+                    if cell.width == CellWidth::Spacer {
+                        continue;
                     }
+                    text.push_str(&cell.ch.to_string());
+                    points.extend(std::iter::repeat_n(Point { line, col }, cell.ch.len_utf8()));
+                }
+                line += 1;
+                if !info.wrapped {
+                    break;
                 }
-                vertices.push(Vertex {
+            }
+            if text.is_empty() {
+                break;
+            }
+            let found = hints::find(&text, &points, rules);
+            matches.extend(
+                found
+                    .into_iter()
+                    .filter(|hint| hint.end.line >= top && hint.start.line <= bottom),
+            );
+        }
+        let labels = hints::labels(matches.len(), alphabet);
+        for (hint, label) in matches.iter_mut().zip(labels) {
+            hint.label = label;
+        }
+        let count = matches.len();
+        self.hints = (count > 0).then(|| Hints::new(matches));
+        self.viewport_dirty = true;
+        count
+    }
+    pub fn hints_active(&self) -> bool {
+        self.hints.is_some()
+    }
+    /// Types a letter of a label. Picking a match ends hints mode.
+    pub fn hint_input(&mut self, ch: char) -> Option<HintMatch> {
+        let picked = self.hints.as_mut()?.push(ch).cloned();
+        if picked.is_some() {
+            self.hints = None;
+        }
+        self.viewport_dirty = true;
+        picked
+    }
+    /// Takes back the last letter typed of a label
+    pub fn hint_backspace(&mut self) {
+        if let Some(hints) = self.hints.as_mut() {
+            hints.pop();
+            self.viewport_dirty = true;
+        }
+    }
+    pub fn cancel_hints(&mut self) {
+        if self.hints.take().is_some() {
+            self.viewport_dirty = true;
+        }
+    }
+    /// Cells of the matches hints mode shows, along with the label letters drawn over their first
+    /// cells
+    fn hint_cells(&self) -> HashMap<Point, Option<char>> {
+        let mut cells = HashMap::new();
+        let Some(hints) = &self.hints else {
+            return cells;
+        };
+        for hint in hints.visible() {
+            let mut point = Some(hint.start);
+            while let Some(current) = point {
+                cells.insert(current, None);
+                point = (current != hint.end)
+                    .then(|| self.next_in_line(current))
+                    .flatten();
+            }
+            for (i, ch) in hint.label.chars().enumerate() {
+                let col = hint.start.col + i;
+                if col < self.col_size {
+                    cells.insert(Point { col, ..hint.start }, Some(ch));
+                }
+            }
+        }
+        cells
+    }
+    /// The selected text. Rows are joined with newlines unless they were soft wrapped, and the
+    /// blanks trailing a row's text are left out.
+    pub fn selection_text(&self) -> Option<String> {
+        let range = self.selection_range()?;
+        let mut text = String::new();
+        for line in range.start.line..=range.end.line {
+            let (cells, info) = self.line(line)?;
+            let first = if range.block || line == range.start.line {
+                range.start.col
+            } else {
+                0
+            };
+            let last = if range.block || line == range.end.line {
+                range.end.col
+            } else {
+                self.col_size - 1
+            };
+            // Each row of a block ends its own line
+            let joined = info.wrapped && !range.block && last + 1 >= self.col_size;
+            // The right halves of wide characters add nothing to the text
+            let mut segment = String::new();
+            for col in first..=last {
+                match cells.get(col) {
+                    Some(cell) if cell.width == CellWidth::Spacer => {}
+                    Some(cell) => segment.push_str(&cell.ch.to_string()),
+                    None => segment.push(' '),
+                }
+            }
+            if !joined && cells.iter().skip(last + 1).all(|cell| cell.ch == ' ') {
+                segment.truncate(segment.trim_end_matches(' ').len());
+            }
+            text.push_str(&segment);
+            if line != range.end.line && !joined {
+                text.push('\n');
+            }
+        }
+        Some(text)
+    }
+    /// Drops the selection when it covers any of grid rows `top..=bottom`, whose text is about
+    /// to change
+    fn damage_rows(&mut self, top: usize, bottom: usize) {
+        let (top, bottom) = (self.grid_line(top), self.grid_line(bottom));
+        if let Some(range) = self.selection_range()
+            && range.intersects_lines(top, bottom)
+        {
+            self.clear_selection();
+        }
+        if let Some(hints) = &self.hints
+            && hints.intersects_lines(top, bottom)
+        {
+            self.cancel_hints();
+        }
+    }
+    fn damage_cell(&mut self, y: usize, col: usize) {
+        let point = Point {
+            line: self.grid_line(y),
+            col,
+        };
+        if self
+            .selection_range()
+            .is_some_and(|range| range.contains(point))
+        {
+            self.clear_selection();
+        }
+        if let Some(hints) = &self.hints
+            && hints.intersects_lines(point.line, point.line)
+        {
+            self.cancel_hints();
+        }
+    }
+    /// Drops the selection once part of it has been evicted from history
+    fn forget_evicted_selection(&mut self) {
+        if self
+            .selection
+            .as_ref()
+            .is_some_and(|selection| selection.ordered().0.line < self.history_start)
+        {
+            self.clear_selection();
+        }
+        if let Some(hints) = &self.hints
+            && hints.intersects_lines(0, self.history_start.saturating_sub(1))
+        {
+            self.cancel_hints();
+        }
+    }
+    /// The cell shown at viewport position (`y`, `col`), taking the display offset into account.
+    /// `None` past the end of history rows narrower than the screen.
+    fn viewport_cell(&self, y: usize, col: usize) -> Option<&Cell> {
+        let history = self.scrollback.len();
+        let line = history - self.display_offset + y;
+        if line < history {
+            self.scrollback[line].cells.get(col)
+        } else {
+            self.cells.get((line - history) * self.col_size + col)
+        }
+    }
+    fn cell_vertices(
+        &mut self,
+        ch: char,
+        y: usize,
+        col: usize,
+        selected: bool,
+        decoration: Option<Decoration>,
+    ) -> [Vertex; VERTICES_PER_CELL] {
+        let x_cell = col as f32 * self.cell_metrics.width;
+        let y_cell = y as f32 * self.cell_metrics.height;
+        let baseline_x = x_cell;
+        let baseline_y = 1080.0 - (y_cell + self.cell_metrics.baseline);
+        // y grows upwards, like in the glyph outlines
+        let cell_bounds = (
+            baseline_x,
+            1080.0 - y_cell,
+            baseline_x + self.cell_metrics.width,
+            1080.0 - (y_cell + self.cell_metrics.height),
+        );
+        // Use full cell bounds with zero UVs for whitespace and glyphs we can't draw
+        let mut bounds = cell_bounds;
+        let mut uv = ([0.0, 0.0], [0.0, 0.0]);
+        if !ch.is_whitespace()
+            && let Some(gid) = self.font.lookup(ch as u32)
+            && let Ok(Some(glyph)) = self.font.parse_gid(gid as u16)
+        {
+            let header = glyph.get_header();
+            bounds = (
+                baseline_x + header.x_min as f32 * self.cell_metrics.scale,
+                baseline_y + header.y_max as f32 * self.cell_metrics.scale,
+                baseline_x + header.x_max as f32 * self.cell_metrics.scale,
+                baseline_y + header.y_min as f32 * self.cell_metrics.scale,
+            );
+            uv = self.atlas.get_uv(ch);
+        }
+        let quad = |(x0, y0, x1, y1): (f32, f32, f32, f32),
+                    ([u0, v0], [u1, v1]): ([f32; 2], [f32; 2]),
+                    selected: bool| {
+            let selected = if selected { 1.0 } else { 0.0 };
+            [
+                Vertex {
                     pos: [x0, y0],
                     uv: [u0, v0],
-                });
-                vertices.push(Vertex {
+                    selected,
+                },
+                Vertex {
                     pos: [x0, y1],
                     uv: [u0, v1],
-                });
-                vertices.push(Vertex {
+                    selected,
+                },
+                Vertex {
                     pos: [x1, y1],
                     uv: [u1, v1],
-                });
-                vertices.push(Vertex {
+                    selected,
+                },
+                Vertex {
                     pos: [x1, y0],
                     uv: [u1, v0],
-                });
-                indices.extend_from_slice(&[
-                    index_offset,
-                    index_offset + 1,
-                    index_offset + 2,
-                    index_offset + 2,
-                    index_offset + 3,
-                    index_offset,
-                ]);
-                index_offset += 4;
-                x_cell += self.cell_metrics.width;
+                    selected,
+                },
+            ]
+        };
+        // The glyph quad only covers the outline, selected cells get a quad of their own so the
+        // whole cell is highlighted. It collapses to a point otherwise.
+        let none = (0.0, 0.0, 0.0, 0.0);
+        let background = if selected { cell_bounds } else { none };
+        let [b0, b1, b2, b3] = quad(background, ([0.0, 0.0], [0.0, 0.0]), selected);
+        let [g0, g1, g2, g3] = quad(bounds, uv, selected);
+        let thickness = (self.cell_metrics.height / 16.0).max(1.0);
+        let (left, top, right, bottom) = cell_bounds;
+        let decoration = match decoration {
+            // It would vanish against the highlight
+            _ if selected => none,
+            Some(Decoration::Underline) => (left, bottom + thickness, right, bottom),
+            Some(Decoration::FailedCommand) => (left, top, left + 2.0 * thickness, bottom),
+            Some(Decoration::Bar) => (left, top, left + thickness, bottom),
+            None => none,
+        };
+        let [d0, d1, d2, d3] = quad(decoration, ([0.0, 0.0], [0.0, 0.0]), true);
+        [b0, b1, b2, b3, g0, g1, g2, g3, d0, d1, d2, d3]
+    }
+    /// What viewport cell (`y`, `col`) shows: its character, whether it's highlighted and what
+    /// is drawn over it
+    fn cell_look(
+        &self,
+        y: usize,
+        col: usize,
+        selection: Option<SelectionRange>,
+        hints: &HashMap<Point, Option<char>>,
+    ) -> (char, bool, Option<Decoration>) {
+        let line = self.viewport_line(y);
+        let point = Point { line, col };
+        let (ch, link) = self
+            .viewport_cell(y, col)
+            .map_or((' ', None), |cell| (cell.ch.base(), cell.link));
+        let selected = selection.is_some_and(|range| range.contains(point));
+        let failed = || {
+            self.line(line)
+                .is_some_and(|(_, info)| info.exit_code.is_some_and(|code| code != 0))
+        };
+        // Labels are drawn highlighted over the text they stand for
+        let (ch, selected, decoration) = match hints.get(&point) {
+            Some(&Some(label)) => (label, true, Some(Decoration::Underline)),
+            Some(None) => (ch, selected, Some(Decoration::Underline)),
+            None if link.is_some() && link == self.hovered_link => {
+                (ch, selected, Some(Decoration::Underline))
+            }
+            None if col == 0 && failed() => (ch, selected, Some(Decoration::FailedCommand)),
+            None => (ch, selected, None),
+        };
+        let cursor = self
+            .drawn_cursor
+            .filter(|&(index, _)| {
+                y >= self.display_offset && index == (y - self.display_offset) * self.col_size + col
+            })
+            .map(|(_, shape)| shape);
+        match cursor {
+            Some(CursorShape::Block) => (ch, !selected, decoration),
+            Some(CursorShape::Underline) => (ch, selected, Some(Decoration::Underline)),
+            Some(CursorShape::Bar) => (ch, selected, Some(Decoration::Bar)),
+            None => (ch, selected, decoration),
+        }
+    }
+    pub fn construct_mesh(&mut self) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut index_offset = 0u32;
+        let selection = self.selection_range();
+        let hints = self.hint_cells();
+        self.drawn_cursor = self.cursor_look();
+        for y in 0..self.y_size {
+            for col in 0..self.col_size {
+                let (ch, selected, decoration) = self.cell_look(y, col, selection, &hints);
+                vertices.extend_from_slice(&self.cell_vertices(ch, y, col, selected, decoration));
+                for quad in [index_offset, index_offset + 4, index_offset + 8] {
+                    indices.extend_from_slice(&[
+                        quad,
+                        quad + 1,
+                        quad + 2,
+                        quad + 2,
+                        quad + 3,
+                        quad,
+                    ]);
+                }
+                index_offset += VERTICES_PER_CELL as u32;
             }
-
-            y_cell += self.cell_metrics.height;
         }
         self.mesh.indices = indices;
         self.mesh.vertices = vertices;
+        self.viewport_dirty = false;
+        self.dirty_cells.clear();
     }
     // This is called by the update method in the application
     // Returns a vec of ranges of memory to be updated
@@ -239,69 +1106,340 @@ impl Screen {
     // When the app calls resize it must then use the
     // mesh stored in screen instead of relying on diffs
     pub fn update_mesh(&mut self) -> Option<Vec<Range>> {
+        if self.viewport_dirty {
+            // The whole viewport moved, rewrite every vertex in one go
+            self.construct_mesh();
+            return Some(vec![Range {
+                start: 0,
+                end: self.mesh.vertices.len(),
+            }]);
+        }
+        // The cursor moved, changed shape or blinked since it was last drawn
+        let cursor = self.cursor_look();
+        if cursor != self.drawn_cursor {
+            self.dirty_cells.extend(
+                self.drawn_cursor
+                    .into_iter()
+                    .chain(cursor)
+                    .map(|(index, _)| index),
+            );
+            self.drawn_cursor = cursor;
+        }
         if self.dirty_cells.is_empty() {
             return None;
         }
         let mut ranges = Vec::new();
+        let selection = self.selection_range();
+        let hints = self.hint_cells();
 
         // Theoretically the index buffer shouldn't need updating unless its rezising at which point
         // Just remake the whole mesh
-        for index in &self.dirty_cells {
-            if let Some(cell) = self.cells.get(*index) {
-                // identify the position inthe ver
-                let gid = self.font.lookup(cell.ch as u32).unwrap_or_default();
-                let glyf = self
-                    .font
-                    .parse_gid(gid as u16)
-                    .unwrap()
-                    .clone()
-                    .unwrap()
-                    .get_header();
-                let col = index % self.col_size;
-                let y = index / self.col_size;
-                let x_cell = col as f32 * self.cell_metrics.width;
-                let y_cell = y as f32 * self.cell_metrics.height;
-                let baseline_x = x_cell;
-                let baseline_y = 1080.0 - (y_cell + self.cell_metrics.baseline);
-                let x0 = baseline_x + glyf.x_min as f32 * self.cell_metrics.scale;
-                let y0 = baseline_y + glyf.y_max as f32 * self.cell_metrics.scale;
-                let x1 = baseline_x + glyf.x_max as f32 * self.cell_metrics.scale;
-                let y1 = baseline_y + glyf.y_min as f32 * self.cell_metrics.scale;
-                let ([u0, v0], [u1, v1]) = if cell.ch == ' ' {
-                    ([0.0, 0.0], [0.0, 0.0])
-                } else {
-                    self.atlas.get_uv(cell.ch)
-                };
-                let init_index = index * 4;
-                let vx0 = Vertex {
-                    pos: [x0, y0],
-                    uv: [u0, v0],
-                };
-                let vx1 = Vertex {
-                    pos: [x0, y1],
-                    uv: [u0, v1],
-                };
-                let vx2 = Vertex {
-                    pos: [x1, y1],
-                    uv: [u1, v1],
-                };
-                let vx3 = Vertex {
-                    pos: [x1, y0],
-                    uv: [u1, v0],
-                };
-                self.mesh.vertices[init_index] = vx0;
-                self.mesh.vertices[init_index + 1] = vx1;
-                self.mesh.vertices[init_index + 2] = vx2;
-                self.mesh.vertices[init_index + 3] = vx3;
+        for index in std::mem::take(&mut self.dirty_cells) {
+            // Dirty cells are tracked in grid coordinates, the viewport may be showing them
+            // further down or not at all while scrolled back
+            let col = index % self.col_size;
+            let y = index / self.col_size + self.display_offset;
+            if y >= self.y_size {
+                continue;
+            }
+            if self.viewport_cell(y, col).is_some() {
+                let (ch, selected, decoration) = self.cell_look(y, col, selection, &hints);
+                let init_index = (y * self.col_size + col) * VERTICES_PER_CELL;
+                let quad = self.cell_vertices(ch, y, col, selected, decoration);
+                self.mesh.vertices[init_index..init_index + VERTICES_PER_CELL]
+                    .copy_from_slice(&quad);
                 ranges.push(Range {
                     start: init_index,
-                    end: init_index + 4,
+                    end: init_index + VERTICES_PER_CELL,
                 });
             }
         }
-        self.dirty_cells.drain();
         Some(ranges)
     }
+    fn save_cursor(&mut self) {
+        self.saved_cursor = Some(SavedCursor {
+            y: self.cursor.y,
+            col: self.cursor.col,
+            attrs: self.attrs,
+            origin: self.modes.contains(TermMode::ORIGIN),
+            pending_wrap: self.cursor.pending_wrap,
+            charsets: self.charsets,
+        });
+    }
+    /// Restores what the last [`Self::save_cursor`] on this screen saved, or the home position
+    /// with default attributes when nothing was
+    fn restore_cursor(&mut self) {
+        let saved = self.saved_cursor.clone().unwrap_or_default();
+        self.cursor.y = saved.y.min(self.y_size - 1);
+        self.cursor.col = saved.col.min(self.col_size - 1);
+        // A wrap pending in a column a resize cut off has nowhere to happen
+        self.cursor.pending_wrap = saved.pending_wrap && self.cursor.col == saved.col;
+        self.attrs = saved.attrs;
+        self.modes.set(TermMode::ORIGIN, saved.origin);
+        self.charsets = saved.charsets;
+    }
+    /// Where the cursor should be drawn: its grid cell and shape, `None` while it's hidden
+    fn cursor_look(&self) -> Option<(usize, CursorShape)> {
+        let shown = self.modes.contains(TermMode::SHOW_CURSOR)
+            && (self.cursor.visible || !self.cursor.blinking);
+        shown.then_some((
+            self.cursor.y * self.col_size + self.cursor.col,
+            self.cursor.shape,
+        ))
+    }
+    /// Whether the cursor is shown and blinking, the application has to keep redrawing it
+    pub fn cursor_blinks(&self) -> bool {
+        self.cursor.blinking && self.modes.contains(TermMode::SHOW_CURSOR)
+    }
+    fn reset_margins(&mut self) {
+        self.top_margin = 0;
+        self.bottom_margin = self.y_size.saturating_sub(1);
+        self.left_margin = 0;
+        self.right_margin = self.col_size.saturating_sub(1);
+    }
+    /// Moves the cursor `n` tab stops right without touching the cells it passes. Stops at the
+    /// right margin, or the last column when right of it.
+    fn tab_forward(&mut self, n: usize) {
+        self.cursor.pending_wrap = false;
+        let last = if self.cursor.col <= self.right_margin {
+            self.right_margin
+        } else {
+            self.col_size - 1
+        };
+        for _ in 0..n {
+            match self.tab_stops.next(self.cursor.col, last) {
+                Some(col) => self.cursor.col = col,
+                None => {
+                    self.cursor.col = last;
+                    break;
+                }
+            }
+        }
+    }
+    /// Moves the cursor `n` tab stops left, stopping at the left margin or, left of it, the
+    /// first column
+    fn tab_backward(&mut self, n: usize) {
+        self.cursor.pending_wrap = false;
+        let first = if self.cursor.col >= self.left_margin {
+            self.left_margin
+        } else {
+            0
+        };
+        for _ in 0..n {
+            match self.tab_stops.prev(self.cursor.col, first) {
+                Some(col) => self.cursor.col = col,
+                None => {
+                    self.cursor.col = first;
+                    break;
+                }
+            }
+        }
+    }
+    fn in_region(&self) -> bool {
+        (self.top_margin..=self.bottom_margin).contains(&self.cursor.y)
+            && (self.left_margin..=self.right_margin).contains(&self.cursor.col)
+    }
+    /// Moves the line info of rows `top..=bottom` along with a scroll of `n` rows. Rows only
+    /// partially scrolled between left/right margins lose their wrap flag instead.
+    fn shift_lines(&mut self, top: usize, bottom: usize, n: usize, up: bool) {
+        let lines = &mut self.lines[top..=bottom];
+        if self.left_margin != 0 || self.right_margin != self.col_size - 1 {
+            lines.iter_mut().for_each(|l| l.wrapped = false);
+            return;
+        }
+        if up {
+            lines.rotate_left(n);
+            let len = lines.len();
+            lines[len - n..].fill(LineInfo::default());
+        } else {
+            lines.rotate_right(n);
+            lines[..n].fill(LineInfo::default());
+        }
+    }
+    /// Moves the rows from `top` to the bottom margin up by `n` between the left and right
+    /// margins, blanking the rows uncovered at the bottom.
+    fn scroll_region_up(&mut self, top: usize, n: usize) {
+        let bottom = self.bottom_margin;
+        let n = n.min(bottom + 1 - top);
+        self.shift_lines(top, bottom, n, true);
+        for y in top..=bottom {
+            for col in self.left_margin..=self.right_margin {
+                let dst = y * self.col_size + col;
+                self.cells[dst] = if y + n <= bottom {
+                    self.cells[dst + n * self.col_size].clone()
+                } else {
+                    Cell::default()
+                };
+                self.dirty_cells.insert(dst);
+            }
+        }
+    }
+    /// Moves the rows from `top` to the bottom margin down by `n` between the left and right
+    /// margins, blanking the rows uncovered at `top`.
+    fn scroll_region_down(&mut self, top: usize, n: usize) {
+        let bottom = self.bottom_margin;
+        let n = n.min(bottom + 1 - top);
+        self.shift_lines(top, bottom, n, false);
+        for y in (top..=bottom).rev() {
+            for col in self.left_margin..=self.right_margin {
+                let dst = y * self.col_size + col;
+                self.cells[dst] = if y >= top + n {
+                    self.cells[dst - n * self.col_size].clone()
+                } else {
+                    Cell::default()
+                };
+                self.dirty_cells.insert(dst);
+            }
+        }
+    }
+    /// Swaps the active and inactive grids. The whole viewport is redrawn and
+    /// pending per-cell updates for the old grid are dropped along with it.
+    fn swap_screens(&mut self) {
+        std::mem::swap(&mut self.cells, &mut self.inactive_cells);
+        std::mem::swap(&mut self.lines, &mut self.inactive_lines);
+        // Each screen keeps its own keyboard flags
+        std::mem::swap(&mut self.keyboard_flags, &mut self.inactive_keyboard_flags);
+        std::mem::swap(&mut self.saved_cursor, &mut self.inactive_saved_cursor);
+        self.modes.toggle(TermMode::ALT_SCREEN);
+        self.display_offset = 0;
+        self.selection = None;
+        self.hints = None;
+        self.dirty_cells.clear();
+        self.viewport_dirty = true;
+    }
+    fn clear_grid(&mut self) {
+        self.damage_rows(0, self.y_size - 1);
+        self.cells.fill(Cell::default());
+        self.lines.fill(LineInfo::default());
+        self.dirty_cells.extend(0..self.cells.len());
+    }
+    /// Modes 47, 1047 and 1049
+    fn enter_alt_screen(&mut self, mode: u16) {
+        if mode == 1049 {
+            self.save_cursor();
+        }
+        if self.modes.contains(TermMode::ALT_SCREEN) {
+            return;
+        }
+        self.primary_cursor = Position {
+            row: self.cursor.y,
+            col: self.cursor.col,
+        };
+        self.swap_screens();
+        if mode == 1049 {
+            self.clear_grid();
+        }
+    }
+    fn leave_alt_screen(&mut self, mode: u16) {
+        if self.modes.contains(TermMode::ALT_SCREEN) {
+            if mode == 1047 {
+                self.clear_grid();
+            }
+            self.swap_screens();
+        }
+        if mode == 1049 {
+            self.restore_cursor();
+        }
+    }
+    /// Kitty keyboard protocol enhancements currently requested by the child
+    pub fn keyboard_flags(&self) -> KeyboardFlags {
+        self.keyboard_flags.last().copied().unwrap_or_default()
+    }
+    /// Window title the child set, `None` for the application's own
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+    pub fn icon_name(&self) -> Option<&str> {
+        self.icon_name.as_deref()
+    }
+    /// Hyperlink of the viewport cell at `col`, `y`
+    pub fn link_at(&self, col: usize, y: usize) -> Option<LinkId> {
+        self.viewport_cell(y, col)?.link
+    }
+    pub fn hyperlink(&self, id: LinkId) -> Option<&Hyperlink> {
+        self.hyperlinks.get(id)
+    }
+    /// Underlines every cell of `link`, returns whether that changed anything
+    pub fn set_hovered_link(&mut self, link: Option<LinkId>) -> bool {
+        if self.hovered_link == link {
+            return false;
+        }
+        self.hovered_link = link;
+        self.viewport_dirty = true;
+        true
+    }
+    /// OSC 133, marks the cursor's row. Only the primary screen has a shell on it.
+    fn semantic_prompt(&mut self, mark: SemanticPrompt) {
+        if self.modes.contains(TermMode::ALT_SCREEN) {
+            return;
+        }
+        let y = self.cursor.y;
+        match mark {
+            SemanticPrompt::PromptStart => {
+                self.lines[y].marks |= Marks::PROMPT;
+                self.prompt_line = Some(self.grid_line(y));
+            }
+            SemanticPrompt::CommandStart => self.lines[y].marks |= Marks::COMMAND,
+            SemanticPrompt::OutputStart => {
+                self.lines[y].marks |= Marks::OUTPUT;
+                self.command_running = true;
+            }
+            SemanticPrompt::CommandFinished(code) => {
+                // Shells send one before every prompt, whether a command ran or not
+                if !std::mem::take(&mut self.command_running) {
+                    return;
+                }
+                self.lines[y].marks |= Marks::FINISHED;
+                // Unless the prompt was cleared away in the meantime
+                if let Some(line) = self.prompt_line
+                    && let Some(info) = self.line_info_mut(line)
+                    && info.marks.contains(Marks::PROMPT)
+                {
+                    info.exit_code = code;
+                    // A failed command's prompt gets a mark in the gutter
+                    self.viewport_dirty = true;
+                }
+            }
+        }
+    }
+    /// OSC 8, `None` ends the current link
+    fn open_link(&mut self, link: Option<Hyperlink>) {
+        self.link = None;
+        let Some(link) = link else {
+            return;
+        };
+        if self.hyperlinks.needs_pruning() {
+            self.prune_links();
+        }
+        self.link = Some(self.hyperlinks.insert(link));
+    }
+    /// Forgets links that no cell on either screen or in history refers to anymore
+    fn prune_links(&mut self) {
+        let history = self.scrollback.iter().flat_map(|row| row.cells.iter());
+        let live = self
+            .cells
+            .iter()
+            .chain(self.inactive_cells.iter())
+            .chain(history)
+            .filter_map(|cell| cell.link)
+            .chain(self.hovered_link)
+            .collect();
+        self.hyperlinks.retain(&live);
+    }
+    /// Whether the title changed since the last call
+    pub fn take_title_changed(&mut self) -> bool {
+        std::mem::take(&mut self.title_changed)
+    }
+    fn set_title(&mut self, title: Option<String>) {
+        if self.title != title {
+            self.title = title;
+            self.title_changed = true;
+        }
+    }
+    /// Queues bytes to be sent back to the child process.
+    pub fn reply(&mut self, bytes: &[u8]) {
+        self.outbound.extend_from_slice(bytes);
+    }
     pub fn write_char(&mut self, ch: char) {
         match ch {
             '\n' => {
@@ -314,31 +1452,160 @@ impl Screen {
                 self.back_cursor(1);
             }
             c if !c.is_control() => {
-                let index = self.cursor.y * self.col_size + self.cursor.col;
-
-                if index < self.cells.len() {
-                    self.cells[index].ch = c;
-                    self.dirty_cells.insert(index);
-                    self.advance_cursor(1);
+                let c = self.charsets.translate(c);
+                if self.attach(c) {
+                    return;
+                }
+                let width = grapheme::char_width(c);
+                // A combining mark with nothing before it to combine with
+                if width == 0 {
+                    return;
+                }
+                if self.cursor.pending_wrap {
+                    self.wrap_line();
+                }
+                // A wide character never straddles two rows, it goes on the next one and the
+                // column it didn't fit in is left as padding
+                if width == 2 && self.cursor.col == self.last_print_col() {
+                    if !self.modes.contains(TermMode::AUTOWRAP) {
+                        return;
+                    }
+                    self.split_wide(self.cursor.y, self.cursor.col);
+                    self.put_cell(self.cursor.col, ' '.into(), CellWidth::Spacer);
+                    self.wrap_line();
+                    if self.cursor.col == self.last_print_col() {
+                        return;
+                    }
+                }
+                // IRM pushes what's under the cursor right instead of writing over it
+                if self.modes.contains(TermMode::INSERT) {
+                    self.insert_blank_chars(width as u16);
                 }
+                let col = self.cursor.col;
+                self.split_wide(self.cursor.y, col);
+                if width == 2 {
+                    self.split_wide(self.cursor.y, col + 1);
+                    self.put_cell(col, c.into(), CellWidth::Wide);
+                    self.put_cell(col + 1, ' '.into(), CellWidth::Spacer);
+                } else {
+                    self.put_cell(col, c.into(), CellWidth::Single);
+                }
+                self.advance_cursor(width);
             }
 
             _ => {}
         }
     }
+    /// Adds `ch` to the grapheme printed last when it belongs there: any zero width character,
+    /// or with mode 2027 whatever continues the cluster by UAX #29. Returns whether it did.
+    fn attach(&mut self, ch: char) -> bool {
+        let clustering = self.modes.contains(TermMode::GRAPHEME_CLUSTERS);
+        if !clustering && grapheme::char_width(ch) != 0 {
+            return false;
+        }
+        let Some(col) = self.previous_col() else {
+            return false;
+        };
+        let index = self.cursor.y * self.col_size + col;
+        if clustering && !self.cells[index].ch.continues_with(ch) {
+            return false;
+        }
+        self.damage_cell(self.cursor.y, col);
+        self.cells[index].ch.push(ch);
+        self.dirty_cells.insert(index);
+        // An emoji modifier or VS16 can make the cluster wide, it takes the column the cursor is
+        // on when there is one left on the row
+        if clustering
+            && self.cells[index].width == CellWidth::Single
+            && self.cells[index].ch.width() == 2
+            && !self.cursor.pending_wrap
+        {
+            self.cells[index].width = CellWidth::Wide;
+            self.split_wide(self.cursor.y, self.cursor.col);
+            self.put_cell(self.cursor.col, ' '.into(), CellWidth::Spacer);
+            self.advance_cursor(1);
+        }
+        true
+    }
+    /// Column of the grapheme printed last on the cursor's row: under the cursor while a wrap
+    /// is pending, left of it otherwise
+    fn previous_col(&self) -> Option<usize> {
+        let col = if self.cursor.pending_wrap {
+            self.cursor.col
+        } else {
+            self.cursor.col.checked_sub(1)?
+        };
+        let row = self.cursor.y * self.col_size;
+        match self.cells[row + col].width {
+            // Padding before a wrapped wide character holds nothing
+            CellWidth::Spacer => col
+                .checked_sub(1)
+                .filter(|&col| self.cells[row + col].width == CellWidth::Wide),
+            _ => Some(col),
+        }
+    }
+    /// Writes a grapheme into column `col` of the cursor's row with the current attributes
+    fn put_cell(&mut self, col: usize, ch: Grapheme, width: CellWidth) {
+        let index = self.cursor.y * self.col_size + col;
+        self.damage_cell(self.cursor.y, col);
+        if index < self.cells.len() {
+            self.cells[index] = Cell {
+                ch,
+                width,
+                cell_attr: self.attrs,
+                link: self.link,
+            };
+            self.dirty_cells.insert(index);
+        }
+    }
+    /// Blanks the other half of a wide character about to lose the half at (`y`, `col`)
+    fn split_wide(&mut self, y: usize, col: usize) {
+        let row = y * self.col_size;
+        let other = match self.cells.get(row + col).map(|cell| cell.width) {
+            Some(CellWidth::Wide) if col + 1 < self.col_size => col + 1,
+            Some(CellWidth::Spacer)
+                if col > 0 && self.cells[row + col - 1].width == CellWidth::Wide =>
+            {
+                col - 1
+            }
+            _ => return,
+        };
+        self.damage_cell(y, other);
+        let cell = &mut self.cells[row + other];
+        cell.ch = ' '.into();
+        cell.width = CellWidth::Single;
+        self.dirty_cells.insert(row + other);
+    }
+    /// The column printing stops at: the right margin, or the last column when right of it
+    fn last_print_col(&self) -> usize {
+        if self.cursor.col <= self.right_margin {
+            self.right_margin
+        } else {
+            self.col_size - 1
+        }
+    }
+    /// Moves the cursor right after printing. Reaching the right margin, or the last column when
+    /// right of it, arms a pending wrap instead when autowrap is on and stays put otherwise.
     pub fn advance_cursor(&mut self, n: usize) {
+        let last_col = self.last_print_col();
         for _ in 0..n {
-            self.cursor.col += 1;
-            if self.cursor.col >= self.col_size {
-                self.cursor.col = 0;
-                self.cursor.y += 1;
-            }
-            if self.cursor.y * self.col_size >= self.cells.len() {
-                break;
+            if self.cursor.col < last_col {
+                self.cursor.col += 1;
+            } else if self.modes.contains(TermMode::AUTOWRAP) {
+                self.cursor.pending_wrap = true;
+                return;
             }
         }
     }
+    /// Performs a pending wrap, continuing on the next line at the left margin.
+    fn wrap_line(&mut self) {
+        self.lines[self.cursor.y].wrapped = true;
+        // scrolls when wrapping off the bottom of the region
+        self.index();
+        self.cursor.col = self.left_margin;
+    }
     pub fn back_cursor(&mut self, n: i32) {
+        self.cursor.pending_wrap = false;
         for _ in 0..n {
             if self.cursor.col == 0 {
                 if self.cursor.y == 0 {
@@ -351,28 +1618,54 @@ impl Screen {
             }
 
             let index = self.cursor.y * self.col_size + self.cursor.col;
+            self.damage_cell(self.cursor.y, self.cursor.col);
             if index < self.cells.len() {
-                self.cells[index].ch = ' ';
+                self.split_wide(self.cursor.y, self.cursor.col);
+                self.cells[index].ch = ' '.into();
+                self.cells[index].width = CellWidth::Single;
                 self.dirty_cells.insert(index);
             }
         }
     }
 }
 impl Handler for Screen {
+    // Relative movement stops at a margin when the cursor starts inside of it
     fn cursor_up(&mut self, n: u16) {
-        self.cursor.y = self.cursor.y.saturating_sub(n as usize);
+        self.cursor.pending_wrap = false;
+        let min_y = if self.cursor.y >= self.top_margin {
+            self.top_margin
+        } else {
+            0
+        };
+        self.cursor.y = self.cursor.y.saturating_sub(n as usize).max(min_y);
     }
     fn cursor_down(&mut self, n: u16) {
-        let max_y = self.y_size - 1;
+        self.cursor.pending_wrap = false;
+        let max_y = if self.cursor.y <= self.bottom_margin {
+            self.bottom_margin
+        } else {
+            self.y_size - 1
+        };
         self.cursor.y = (self.cursor.y + n as usize).min(max_y);
     }
     fn cursor_right(&mut self, n: u16) {
-        let max_col = self.col_size - 1;
+        self.cursor.pending_wrap = false;
+        let max_col = if self.cursor.col <= self.right_margin {
+            self.right_margin
+        } else {
+            self.col_size - 1
+        };
         self.cursor.col = self.cursor.col.saturating_add(n as usize).min(max_col);
     }
 
     fn cursor_left(&mut self, n: u16) {
-        self.cursor.col = self.cursor.col.saturating_sub(n as usize);
+        self.cursor.pending_wrap = false;
+        let min_col = if self.cursor.col >= self.left_margin {
+            self.left_margin
+        } else {
+            0
+        };
+        self.cursor.col = self.cursor.col.saturating_sub(n as usize).max(min_col);
     }
     fn accumluate_utf8(&mut self, byte: u8) {
         if let Some(ch) = self.accumulator.decode(byte) {
@@ -380,19 +1673,56 @@ impl Handler for Screen {
         }
     }
     fn next_line(&mut self) {}
-    fn handle_osc(&mut self, osc: &Vec<u8>) {}
+    fn handle_osc(&mut self, command: u16, payload: &[u8]) {
+        match command {
+            // An empty title goes back to the default
+            0..=2 => {
+                let text = Some(osc::title(payload)).filter(|text| !text.is_empty());
+                if command != 2 {
+                    self.icon_name = text.clone();
+                }
+                if command != 1 {
+                    self.set_title(text);
+                }
+            }
+            7 => self.working_directory = osc::working_directory(payload),
+            8 => self.open_link(osc::hyperlink(payload)),
+            133 => {
+                if let Some(mark) = osc::semantic_prompt(payload) {
+                    self.semantic_prompt(mark);
+                }
+            }
+            52 => {
+                if let Some(request) = osc::clipboard(payload) {
+                    self.clipboard_requests.push(request);
+                }
+            }
+            _ => {}
+        }
+    }
     fn previous_line(&mut self) {}
     fn bell(&mut self) {}
     fn execute(&mut self, ctl_seq: u8) {
+        self.cursor.pending_wrap = false;
         match ctl_seq {
             0x08 => {
                 self.back_cursor(1);
             }
-            0x0A => {
-                self.cursor.y += 1; // move DOWN
+            0x09 => self.tab_forward(1),
+            // SO and SI
+            0x0E => self.charsets.locking_shift(CharsetSlot::G1),
+            0x0F => self.charsets.locking_shift(CharsetSlot::G0),
+            0x0A..=0x0C => {
+                // LF, VT and FF move DOWN, scrolling at the bottom
+                self.index();
             }
             0x0D => {
-                self.cursor.col = 0; // move to column 0
+                // move to the left margin, or column 0 when left of it
+                if self.cursor.col >= self.left_margin {
+                    self.cursor.col = self.left_margin;
+                } else {
+                    self.cursor.col = 0;
+                }
             }
             _ => {}
         }
@@ -402,58 +1732,106 @@ impl Handler for Screen {
         )
     }
     fn cursor_position(&mut self, row: u16, col: u16) {
-        self.cursor.y = (row as usize).saturating_sub(1).min(self.y_size - 1);
-        self.cursor.col = (col as usize).saturating_sub(1).min(self.col_size - 1);
+        self.cursor_vertical_absolute(row);
+        self.cursor_horizontal_absolute(col);
     }
 
+    // In origin mode absolute positions are relative to the margins and cannot leave them
     fn cursor_horizontal_absolute(&mut self, col: u16) {
-        self.cursor.col = (col as usize).saturating_sub(1).min(self.col_size - 1);
+        self.cursor.pending_wrap = false;
+        let col = (col as usize).saturating_sub(1);
+        self.cursor.col = if self.modes.contains(TermMode::ORIGIN) {
+            (self.left_margin + col).min(self.right_margin)
+        } else {
+            col.min(self.col_size - 1)
+        };
     }
 
     fn cursor_vertical_absolute(&mut self, row: u16) {
-        self.cursor.y = (row as usize).saturating_sub(1).min(self.y_size - 1);
+        self.cursor.pending_wrap = false;
+        let row = (row as usize).saturating_sub(1);
+        self.cursor.y = if self.modes.contains(TermMode::ORIGIN) {
+            (self.top_margin + row).min(self.bottom_margin)
+        } else {
+            row.min(self.y_size - 1)
+        };
     }
 
     fn save_cursor_position(&mut self) {
-        // TODO: Implement cursor position saving (would need additional state)
-        // Example: could store in a (usize, usize) field in Screen struct
+        self.save_cursor();
     }
 
     fn restore_cursor_position(&mut self) {
-        // TODO: Implement cursor position restoration
+        self.restore_cursor();
+    }
+
+    fn designate_charset(&mut self, slot: CharsetSlot, charset: Charset) {
+        self.charsets.designate(slot, charset);
+    }
+
+    fn locking_shift(&mut self, slot: CharsetSlot) {
+        self.charsets.locking_shift(slot);
+    }
+
+    fn single_shift(&mut self, slot: CharsetSlot) {
+        self.charsets.single_shift(slot);
     }
 
     fn erase_display(&mut self, mode: u16) {
+        self.cursor.pending_wrap = false;
         match mode {
             0 => {
                 // Erase from cursor to end of display
+                self.damage_rows(self.cursor.y, self.y_size - 1);
                 let start_index = self.cursor.y * self.col_size + self.cursor.col;
                 for i in start_index..self.cells.len() {
                     self.cells[i] = Cell::default();
                     self.dirty_cells.insert(i);
                 }
+                // The cursor's row keeps its marks, shells clear below the prompt they just marked
+                self.lines[self.cursor.y].wrapped = false;
+                self.lines[self.cursor.y + 1..].fill(LineInfo::default());
             }
             1 => {
                 // Erase from start of display to cursor
+                self.damage_rows(0, self.cursor.y);
                 let end_index = self.cursor.y * self.col_size + self.cursor.col + 1;
                 for i in 0..end_index {
                     self.cells[i] = Cell::default();
                     self.dirty_cells.insert(i);
                 }
+                self.lines[..self.cursor.y].fill(LineInfo::default());
             }
-            2 | 3 => {
+            2 => {
                 // Erase entire display
+                self.damage_rows(0, self.y_size - 1);
                 for (i, cell) in self.cells.iter_mut().enumerate() {
                     *cell = Cell::default();
                     self.dirty_cells.insert(i);
                 }
+                self.lines.fill(LineInfo::default());
+            }
+            3 => {
+                // Erase saved lines
+                self.history_start += self.scrollback.len();
+                self.forget_evicted_selection();
+                self.scrollback.clear();
+                self.display_offset = 0;
+                self.viewport_dirty = true;
             }
             _ => {}
         }
     }
 
     fn erase_line(&mut self, mode: u16) {
+        self.cursor.pending_wrap = false;
         let y_start = self.cursor.y * self.col_size;
+        self.damage_rows(self.cursor.y, self.cursor.y);
+        // Erasing the end of a row breaks its continuation onto the next
+        if mode != 1 {
+            self.lines[self.cursor.y].wrapped = false;
+        }
+        self.split_wide(self.cursor.y, self.cursor.col);
         match mode {
             0 => {
                 // Erase from cursor to end of line
@@ -486,6 +1864,11 @@ impl Handler for Screen {
         let start_index = self.cursor.y * self.col_size + self.cursor.col;
         let line_end = ((self.cursor.y + 1) * self.col_size).min(self.cells.len());
         let erase_end = (start_index + n as usize).min(line_end);
+        self.damage_rows(self.cursor.y, self.cursor.y);
+        if erase_end > start_index {
+            self.split_wide(self.cursor.y, self.cursor.col);
+            self.split_wide(self.cursor.y, erase_end - 1 - self.cursor.y * self.col_size);
+        }
 
         for i in start_index..erase_end {
             self.cells[i] = Cell::default();
@@ -494,14 +1877,21 @@ impl Handler for Screen {
     }
 
     fn insert_blank_chars(&mut self, n: u16) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.col < self.left_margin || self.cursor.col > self.right_margin {
+            return;
+        }
         let start_index = self.cursor.y * self.col_size + self.cursor.col;
-        let line_end = ((self.cursor.y + 1) * self.col_size).min(self.cells.len());
+        let line_end = self.cursor.y * self.col_size + self.right_margin + 1;
         let insert_count = (n as usize).min(line_end - start_index);
+        self.damage_rows(self.cursor.y, self.cursor.y);
+        self.split_wide(self.cursor.y, self.cursor.col);
 
         // Shift characters to the right
         if start_index + insert_count < line_end {
             for i in (start_index..line_end - insert_count).rev() {
                 self.cells[i + insert_count] = self.cells[i].clone();
+                self.dirty_cells.insert(i + insert_count);
             }
         }
 
@@ -513,9 +1903,15 @@ impl Handler for Screen {
     }
 
     fn delete_chars(&mut self, n: u16) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.col < self.left_margin || self.cursor.col > self.right_margin {
+            return;
+        }
         let start_index = self.cursor.y * self.col_size + self.cursor.col;
-        let line_end = ((self.cursor.y + 1) * self.col_size).min(self.cells.len());
+        let line_end = self.cursor.y * self.col_size + self.right_margin + 1;
         let delete_count = (n as usize).min(line_end - start_index);
+        self.damage_rows(self.cursor.y, self.cursor.y);
+        self.split_wide(self.cursor.y, self.cursor.col);
 
         // Shift characters to the left
         for i in start_index..(line_end - delete_count) {
@@ -531,187 +1927,380 @@ impl Handler for Screen {
     }
 
     fn insert_lines(&mut self, n: u16) {
-        let insert_count = (n as usize).min(self.y_size - self.cursor.y);
-        let start_line = self.cursor.y * self.col_size;
-        let shift_start = start_line + insert_count * self.col_size;
-        let total_cells = self.cells.len();
-
-        // Shift lines down
-        if shift_start < total_cells {
-            for i in (start_line..(total_cells - insert_count * self.col_size)).rev() {
-                self.cells[i + insert_count * self.col_size] = self.cells[i].clone();
-            }
-        }
-
-        // Clear inserted lines
-        for i in start_line..shift_start {
-            self.cells[i] = Cell::default();
-            self.dirty_cells.insert(i);
+        // Ignored outside the scrolling region
+        if !self.in_region() {
+            return;
         }
+        self.damage_rows(self.cursor.y, self.bottom_margin);
+        self.scroll_region_down(self.cursor.y, n as usize);
+        self.cursor.col = self.left_margin;
+        self.cursor.pending_wrap = false;
     }
 
     fn delete_lines(&mut self, n: u16) {
-        let delete_count = (n as usize).min(self.y_size - self.cursor.y);
-        let start_line = self.cursor.y * self.col_size;
-        let shift_start = start_line + delete_count * self.col_size;
-        let total_cells = self.cells.len();
-
-        // Shift lines up
-        for i in start_line..(total_cells - delete_count * self.col_size) {
-            self.cells[i] = self.cells[i + delete_count * self.col_size].clone();
-            self.dirty_cells.insert(i);
-        }
-
-        // Clear vacated lines at bottom
-        for i in (total_cells - delete_count * self.col_size)..total_cells {
-            self.cells[i] = Cell::default();
-            self.dirty_cells.insert(i);
+        if !self.in_region() {
+            return;
         }
+        self.damage_rows(self.cursor.y, self.bottom_margin);
+        self.scroll_region_up(self.cursor.y, n as usize);
+        self.cursor.col = self.left_margin;
+        self.cursor.pending_wrap = false;
     }
 
     fn scroll_up(&mut self, n: u16) {
-        let scroll_count = (n as usize).min(self.y_size);
-        let scroll_cells = scroll_count * self.col_size;
+        let scroll_count = (n as usize).min(self.bottom_margin + 1 - self.top_margin);
 
-        // Shift all content up
-        for i in 0..(self.cells.len() - scroll_cells) {
-            self.cells[i] = self.cells[i + scroll_cells].clone();
-            self.dirty_cells.insert(i);
-        }
-
-        // Clear bottom lines
-        for i in (self.cells.len() - scroll_cells)..self.cells.len() {
-            self.cells[i] = Cell::default();
-            self.dirty_cells.insert(i);
+        // Rows leaving the top of the primary screen go into history. Rows leaving a region
+        // that doesn't start at the top or span the whole width are lost, and the alternate
+        // screen has none.
+        let full_width = self.left_margin == 0 && self.right_margin == self.col_size - 1;
+        if self.top_margin == 0 && full_width && !self.modes.contains(TermMode::ALT_SCREEN) {
+            let rows = self.cells[..scroll_count * self.col_size].chunks(self.col_size);
+            for (y, row) in rows.enumerate() {
+                // The oldest line makes room for the new one
+                if self.scrollback.len() == self.scrollback.capacity() {
+                    self.history_start += 1;
+                }
+                self.scrollback.push(Row {
+                    cells: row.to_vec(),
+                    info: self.lines[y],
+                });
+            }
+            if self.display_offset > 0 {
+                // Keep the viewport on the same lines while output keeps coming in
+                self.display_offset =
+                    (self.display_offset + scroll_count).min(self.scrollback.len());
+                self.viewport_dirty = true;
+            }
+            self.forget_evicted_selection();
+        } else {
+            // Lines moving within the region aren't lines moving through history
+            self.damage_rows(self.top_margin, self.bottom_margin);
         }
+        self.scroll_region_up(self.top_margin, scroll_count);
     }
 
     fn scroll_down(&mut self, n: u16) {
-        let scroll_count = (n as usize).min(self.y_size);
-        let scroll_cells = scroll_count * self.col_size;
-        let total_cells = self.cells.len();
+        self.damage_rows(self.top_margin, self.bottom_margin);
+        self.scroll_region_down(self.top_margin, n as usize);
+    }
 
-        // Shift all content down
-        for i in (scroll_cells..total_cells).rev() {
-            self.cells[i] = self.cells[i - scroll_cells].clone();
-            self.dirty_cells.insert(i);
+    fn set_scrolling_region(&mut self, top: u16, bottom: u16) {
+        let top = (top as usize).max(1) - 1;
+        let bottom = (bottom as usize).min(self.y_size).saturating_sub(1);
+        // A region needs at least two lines
+        if top >= bottom {
+            return;
         }
+        self.top_margin = top;
+        self.bottom_margin = bottom;
+        self.cursor_position(1, 1);
+    }
 
-        // Clear top lines
-        for i in 0..scroll_cells {
-            self.cells[i] = Cell::default();
-            self.dirty_cells.insert(i);
+    fn set_left_right_margins(&mut self, left: u16, right: u16) {
+        if !self.modes.contains(TermMode::LEFT_RIGHT_MARGIN) {
+            // SCOSC, `CSI s` saves the cursor while DECLRMM is reset
+            self.save_cursor_position();
+            return;
+        }
+        let left = (left as usize).max(1) - 1;
+        let right = (right as usize).min(self.col_size).saturating_sub(1);
+        if left >= right {
+            return;
         }
+        self.left_margin = left;
+        self.right_margin = right;
+        self.cursor_position(1, 1);
     }
 
-    fn set_scrolling_region(&mut self, _top: u16, _bottom: u16) {
-        // TODO: Implement scrolling region (would need additional state)
-        // When implemented, would store top/bottom margins and constrain scroll operations
+    fn char_attributes(&mut self, params: &[ParamGroup]) {
+        self.attrs.apply_sgr(params);
     }
 
-    fn char_attributes(&mut self, _params: &smallvec::SmallVec<[u16; 8]>) {
-        // TODO: Implement character attributes (colors, bold, italic, underline, etc.)
-        // Would update cell_attr field in current cells being written
+    fn set_tab_stop(&mut self) {
+        self.tab_stops.set(self.cursor.col);
     }
 
-    fn set_tab_stop(&mut self) {
-        // TODO: Implement tab stop tracking
-        // Could use a HashSet<usize> to store column positions with tab stops
+    fn clear_tab_stop(&mut self, mode: u16) {
+        match mode {
+            0 => self.tab_stops.clear(self.cursor.col),
+            3 => self.tab_stops.clear_all(),
+            _ => {}
+        }
     }
 
-    fn clear_tab_stop(&mut self, _mode: u16) {
-        // TODO: Implement tab stop clearing
-        // mode 0 = clear at current column, mode 3 = clear all
+    fn cursor_forward_tab(&mut self, n: u16) {
+        self.tab_forward(n.max(1) as usize);
     }
 
-    fn cursor_forward_tab(&mut self, _n: u16) {
-        // TODO: Implement forward tabulation
-        // Jump to next n tab stops (default to column 8, 16, 24, etc.)
+    fn cursor_backward_tab(&mut self, n: u16) {
+        self.tab_backward(n.max(1) as usize);
     }
 
-    fn cursor_backward_tab(&mut self, _n: u16) {
-        // TODO: Implement backward tabulation
-        // Jump back n tab stops
+    fn reset_tab_stops(&mut self) {
+        self.tab_stops = TabStops::new(self.col_size);
     }
 
-    fn set_mode(&mut self, _params: &smallvec::SmallVec<[u16; 8]>, _private: bool) {
-        // TODO: Implement mode setting
-        // Common modes: 4 (IRM - insert/replace), 25 (DECTCEM - cursor visible)
+    fn set_mode(&mut self, params: &smallvec::SmallVec<[u16; 8]>, private: bool) {
+        for &param in params {
+            match (param, private) {
+                (47 | 1047 | 1049, true) => self.enter_alt_screen(param),
+                (1048, true) => self.save_cursor(),
+                _ => {
+                    if let Some(mode) = TermMode::from_param(param, private) {
+                        // A new tracking mode or encoding replaces the previous one
+                        for group in [TermMode::MOUSE_TRACKING, TermMode::MOUSE_ENCODING] {
+                            if group.contains(mode) {
+                                self.modes.remove(group);
+                            }
+                        }
+                        self.modes.insert(mode);
+                        if mode == TermMode::ORIGIN {
+                            self.cursor_position(1, 1);
+                        }
+                    }
+                }
+            }
+        }
     }
 
-    fn reset_mode(&mut self, _params: &smallvec::SmallVec<[u16; 8]>, _private: bool) {
-        // TODO: Implement mode resetting
+    fn reset_mode(&mut self, params: &smallvec::SmallVec<[u16; 8]>, private: bool) {
+        for &param in params {
+            match (param, private) {
+                (47 | 1047 | 1049, true) => self.leave_alt_screen(param),
+                (1048, true) => self.restore_cursor(),
+                _ => {
+                    if let Some(mode) = TermMode::from_param(param, private) {
+                        self.modes.remove(mode);
+                        if mode == TermMode::ORIGIN {
+                            self.cursor_position(1, 1);
+                        } else if mode == TermMode::LEFT_RIGHT_MARGIN {
+                            self.left_margin = 0;
+                            self.right_margin = self.col_size - 1;
+                        }
+                    }
+                }
+            }
+        }
     }
 
     fn primary_device_attributes(&mut self) {
-        // TODO: Implement primary device attributes response
-        // Should send back a response indicating terminal type
+        // VT220 with ANSI color (22)
+        self.reply(&DeviceAttributes::VT220(22).encode());
     }
 
     fn secondary_device_attributes(&mut self) {
-        // TODO: Implement secondary device attributes response
+        self.reply(&secondary_attributes(TERMINAL_VERSION));
+    }
+
+    fn device_status_report(&mut self, param: u16) {
+        let report = match param {
+            5 => DeviceStatusReport::Ok,
+            6 => {
+                // Reported relative to the margins in origin mode
+                let (top, left) = if self.modes.contains(TermMode::ORIGIN) {
+                    (self.top_margin, self.left_margin)
+                } else {
+                    (0, 0)
+                };
+                DeviceStatusReport::CursorPosition(
+                    self.cursor.y.saturating_sub(top) as u16 + 1,
+                    self.cursor.col.saturating_sub(left) as u16 + 1,
+                )
+            }
+            _ => return,
+        };
+        self.reply(&report.encode());
+    }
+
+    fn request_mode(&mut self, mode: u16, private: bool) {
+        let status = match TermMode::from_param(mode, private) {
+            Some(flag) if self.modes.contains(flag) => ModeStatus::Set,
+            Some(_) => ModeStatus::Reset,
+            None => ModeStatus::NotRecognized,
+        };
+        let prefix = if private { "?" } else { "" };
+        let report = format!("\x1b[{}{};{}$y", prefix, mode, status as u8);
+        self.reply(report.as_bytes());
     }
 
-    fn device_status_report(&mut self, _param: u16) {
-        // TODO: Implement device status report
-        // param 5 = operational status, param 6 = cursor position
+    fn report_version(&mut self) {
+        let report = format!("\x1bP>|dit({})\x1b\\", env!("CARGO_PKG_VERSION"));
+        self.reply(report.as_bytes());
     }
 
     fn soft_reset(&mut self) {
+        // Only the state DECSTR lists, the screen, the buffer shown and tab stops stay
+        self.modes.remove(
+            TermMode::INSERT
+                | TermMode::ORIGIN
+                | TermMode::CURSOR_KEYS
+                | TermMode::KEYPAD_APPLICATION,
+        );
+        self.modes
+            .insert(TermMode::AUTOWRAP | TermMode::SHOW_CURSOR);
+        self.modify_other_keys = 0;
+        self.keyboard_flags.clear();
+        // The saved position goes back to the home position
+        self.saved_cursor = None;
+        self.reset_margins();
+        self.cursor.pending_wrap = false;
+        self.attrs = Attributes::default();
+        self.charsets = Charsets::default();
+        self.link = None;
+    }
+
+    fn full_reset(&mut self) {
+        // Back to the primary screen before clearing it
+        if self.modes.contains(TermMode::ALT_SCREEN) {
+            self.swap_screens();
+        }
+        self.modes = TermMode::default();
+        self.modify_other_keys = 0;
+        self.keyboard_flags.clear();
+        self.inactive_keyboard_flags.clear();
+        self.saved_cursor = None;
+        self.inactive_saved_cursor = None;
+        self.reset_margins();
+        self.tab_stops = TabStops::new(self.col_size);
         // Reset cursor and clear display
         self.cursor = Cursor::default();
+        self.attrs = Attributes::default();
+        self.charsets = Charsets::default();
+        self.link = None;
+        self.damage_rows(0, self.y_size - 1);
         for (i, cell) in self.cells.iter_mut().enumerate() {
             *cell = Cell::default();
             self.dirty_cells.insert(i);
         }
+        self.lines.fill(LineInfo::default());
     }
 
-    fn set_cursor_style(&mut self, _style: u16) {
-        // TODO: Implement cursor style setting
-        // Styles: 0/1=blinking block, 2=steady block, 3=blinking underline, etc.
-        // Would update cursor.blinking field
+    fn set_cursor_style(&mut self, style: u16) {
+        // Odd styles blink, 0 is the default steady block
+        let shape = match style {
+            0..=2 => CursorShape::Block,
+            3 | 4 => CursorShape::Underline,
+            5 | 6 => CursorShape::Bar,
+            _ => return,
+        };
+        self.cursor.shape = shape;
+        self.cursor.blinking = style % 2 == 1;
     }
 
-    fn window_ops(&mut self, _params: &smallvec::SmallVec<[u16; 8]>) {
-        // TODO: Implement window operations
-        // Could handle window resize, minimization, maximization requests
+    fn window_ops(&mut self, params: &smallvec::SmallVec<[u16; 8]>) {
+        let report = match params.first() {
+            // Text area size in pixels
+            Some(14) => {
+                let (width, height) = self.pixel_size();
+                format!("\x1b[4;{};{}t", height, width)
+            }
+            // Cell size in pixels
+            Some(16) => format!(
+                "\x1b[6;{};{}t",
+                self.cell_metrics.height as u32, self.cell_metrics.width as u32
+            ),
+            // Text area size in characters
+            Some(18) => format!("\x1b[8;{};{}t", self.y_size, self.col_size),
+            // Save the icon name and title. Both are kept whatever the second parameter, 23's
+            // picks what comes back: 1 the icon name, 2 the title, 0 both.
+            Some(22) => {
+                if self.title_stack.len() == TITLE_STACK_DEPTH {
+                    self.title_stack.remove(0);
+                }
+                self.title_stack
+                    .push((self.icon_name.clone(), self.title.clone()));
+                return;
+            }
+            Some(23) => {
+                if let Some((icon_name, title)) = self.title_stack.pop() {
+                    let which = params.get(1).copied().unwrap_or(0);
+                    if which != 2 {
+                        self.icon_name = icon_name;
+                    }
+                    if which != 1 {
+                        self.set_title(title);
+                    }
+                }
+                return;
+            }
+            _ => return,
+        };
+        self.reply(report.as_bytes());
     }
 
     fn index(&mut self) {
-        // IND – move cursor down, scroll up if at bottom
-        if self.cursor.y < self.y_size - 1 {
-            self.cursor.y += 1;
-        } else {
+        // IND – move cursor down, scroll the region up if at its bottom
+        self.cursor.pending_wrap = false;
+        if self.cursor.y == self.bottom_margin {
             self.scroll_up(1);
+        } else if self.cursor.y < self.y_size - 1 {
+            self.cursor.y += 1;
         }
     }
 
     fn reverse_index(&mut self) {
-        // RI – move cursor up, scroll down if at top
-        if self.cursor.y > 0 {
-            self.cursor.y -= 1;
-        } else {
+        self.cursor.pending_wrap = false;
+        // RI – move cursor up, scroll the region down if at its top
+        if self.cursor.y == self.top_margin {
             self.scroll_down(1);
+        } else if self.cursor.y > 0 {
+            self.cursor.y -= 1;
         }
     }
 
     fn next_line_esc(&mut self) {
-        // NEL – move to next line and column 0
-        if self.cursor.y < self.y_size - 1 {
-            self.cursor.y += 1;
-        } else {
-            self.scroll_up(1);
-        }
-        self.cursor.col = 0;
+        // NEL – move to next line and the left margin
+        self.index();
+        self.cursor.col = self.left_margin;
     }
 
     fn set_keypad_application_mode(&mut self) {
-        // TODO: Implement keypad application mode
-        // Would set a flag to indicate keypad sends application codes
+        self.modes.insert(TermMode::KEYPAD_APPLICATION);
     }
 
     fn unset_keypad_application_mode(&mut self) {
-        // TODO: Implement keypad normal mode
+        self.modes.remove(TermMode::KEYPAD_APPLICATION);
+    }
+
+    fn set_modify_keys(&mut self, resource: u16, value: Option<u16>) {
+        // Only modifyOtherKeys is supported, the other resources tweak legacy encodings
+        if resource == 4 {
+            self.modify_other_keys = value.unwrap_or(0).min(2) as u8;
+        }
+    }
+
+    fn push_keyboard_flags(&mut self, flags: u16) {
+        if self.keyboard_flags.len() == KEYBOARD_FLAGS_DEPTH {
+            self.keyboard_flags.remove(0);
+        }
+        self.keyboard_flags
+            .push(KeyboardFlags::from_bits_truncate(flags as u8));
+    }
+
+    fn pop_keyboard_flags(&mut self, n: u16) {
+        let len = self.keyboard_flags.len().saturating_sub(n as usize);
+        self.keyboard_flags.truncate(len);
+    }
+
+    fn set_keyboard_flags(&mut self, flags: u16, mode: u16) {
+        let flags = KeyboardFlags::from_bits_truncate(flags as u8);
+        let current = self.keyboard_flags();
+        let new = match mode {
+            1 => flags,
+            2 => current | flags,
+            3 => current - flags,
+            _ => return,
+        };
+        match self.keyboard_flags.last_mut() {
+            Some(top) => *top = new,
+            None => self.keyboard_flags.push(new),
+        }
+    }
+
+    fn report_keyboard_flags(&mut self) {
+        let report = format!("\x1b[?{}u", self.keyboard_flags().bits());
+        self.reply(report.as_bytes());
     }
 
     fn csi(&mut self) {
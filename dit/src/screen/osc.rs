@@ -0,0 +1,223 @@
+//! Operating system commands, `OSC Ps ; Pt ST`. The parser splits off the numeric `Ps`, the
+//! functions here turn each command's `Pt` into something the screen can keep.
+use std::{ffi::OsString, os::unix::ffi::OsStringExt, path::PathBuf};
+
+use super::hyperlink::Hyperlink;
+use crate::clipboard::ClipboardKind;
+
+/// Longest OSC 52 payload accepted, in base64 characters. Anything bigger is dropped whole.
+pub const MAX_CLIPBOARD_PAYLOAD: usize = 1 << 20;
+/// Longest window title or icon name kept, in characters. Longer ones are cut.
+pub const MAX_TITLE_LEN: usize = 1024;
+/// Titles XTWINOPS 22 saves, pushing more drops the oldest
+pub const TITLE_STACK_DEPTH: usize = 10;
+/// Longest OSC 7 or OSC 8 URL accepted, in bytes
+pub const MAX_URL_LEN: usize = 8192;
+/// Longest OSC 8 `id=` kept, a link with a longer one is left anonymous
+pub const MAX_LINK_ID_LEN: usize = 256;
+
+/// `OSC 0/1/2 ; Pt`, a window title or icon name. Control characters are dropped so the title
+/// can't carry escape sequences on to whatever displays it, invalid UTF-8 is replaced.
+pub fn title(payload: &[u8]) -> String {
+    String::from_utf8_lossy(payload)
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_TITLE_LEN)
+        .collect()
+}
+
+/// The shell's working directory, reported through OSC 7
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkingDirectory {
+    /// Machine the directory is on, empty when the shell left it out
+    pub host: String,
+    pub path: PathBuf,
+}
+impl WorkingDirectory {
+    /// Whether the directory is on this machine rather than one reached over ssh
+    pub fn is_local(&self) -> bool {
+        self.host.is_empty()
+            || self.host == "localhost"
+            || hostname().is_some_and(|name| name == self.host)
+    }
+}
+fn hostname() -> Option<String> {
+    let mut name = [0u8; 256];
+    if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } != 0 {
+        return None;
+    }
+    let len = name.iter().position(|&b| b == 0)?;
+    String::from_utf8(name[..len].to_vec()).ok()
+}
+/// `OSC 7 ; file://host/path`, the path percent-encoded. `None` for anything but an absolute
+/// `file` URL.
+pub fn working_directory(payload: &[u8]) -> Option<WorkingDirectory> {
+    if payload.len() > MAX_URL_LEN {
+        return None;
+    }
+    let url = payload.strip_prefix(b"file://")?;
+    let slash = url.iter().position(|&b| b == b'/')?;
+    let host = std::str::from_utf8(&url[..slash]).ok()?;
+    if host.chars().any(char::is_control) {
+        return None;
+    }
+    let path = percent_decode(&url[slash..])?;
+    Some(WorkingDirectory {
+        host: host.to_string(),
+        path: PathBuf::from(OsString::from_vec(path)),
+    })
+}
+fn percent_decode(text: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut bytes = text.iter();
+    while let Some(&b) = bytes.next() {
+        if b == b'%' {
+            let high = (*bytes.next()? as char).to_digit(16)?;
+            let low = (*bytes.next()? as char).to_digit(16)?;
+            out.push((high << 4 | low) as u8);
+        } else {
+            out.push(b);
+        }
+    }
+    Some(out)
+}
+
+/// Clipboard access the child asked for through OSC 52, carried out by the application which
+/// owns the clipboards
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardRequest {
+    Store(ClipboardKind, String),
+    /// Reply with the clipboard's content, as long as reads are allowed
+    Load(ClipboardKind),
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+/// Decodes standard base64, padding optional. `None` on anything that isn't base64.
+pub fn base64_decode(text: &[u8]) -> Option<Vec<u8>> {
+    let text = match text.iter().position(|&b| b == b'=') {
+        // Padding may only end the text
+        Some(pad) if text[pad..].iter().all(|&b| b == b'=') && text.len() - pad <= 2 => {
+            &text[..pad]
+        }
+        Some(_) => return None,
+        None => text,
+    };
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.chunks(4) {
+        // A single character can't carry a whole byte
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut group = 0u32;
+        for (i, &b) in chunk.iter().enumerate() {
+            let value = BASE64.iter().position(|&c| c == b)? as u32;
+            group |= value << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(out)
+}
+/// `OSC 52 ; Pc ; Pd`, sets the clipboard named in `Pc` to the base64 text `Pd` or asks for
+/// its content when `Pd` is `?`
+pub fn clipboard(payload: &[u8]) -> Option<ClipboardRequest> {
+    if payload.len() > MAX_CLIPBOARD_PAYLOAD {
+        return None;
+    }
+    let split = payload.iter().position(|&b| b == b';')?;
+    let (targets, data) = (&payload[..split], &payload[split + 1..]);
+    // The first target we have, cut buffers aren't. Most programs leave it empty meaning the
+    // clipboard.
+    let kind = if targets.is_empty() {
+        ClipboardKind::Clipboard
+    } else {
+        targets.iter().find_map(|target| match target {
+            b'c' => Some(ClipboardKind::Clipboard),
+            b'p' | b's' => Some(ClipboardKind::Primary),
+            _ => None,
+        })?
+    };
+    if data == b"?" {
+        return Some(ClipboardRequest::Load(kind));
+    }
+    let text = base64_decode(data)?;
+    Some(ClipboardRequest::Store(
+        kind,
+        String::from_utf8_lossy(&text).into_owned(),
+    ))
+}
+/// The reply to an OSC 52 read
+pub fn clipboard_reply(kind: ClipboardKind, text: &str) -> String {
+    let target = match kind {
+        ClipboardKind::Clipboard => 'c',
+        ClipboardKind::Primary => 'p',
+    };
+    format!(
+        "\x1b]52;{};{}\x1b\\",
+        target,
+        base64_encode(text.as_bytes())
+    )
+}
+/// `OSC 8 ; params ; URI`, the link that following text belongs to. `params` are `key=value`
+/// pairs separated by `:`, only `id` means anything. `None` ends the current link, as does an
+/// empty URI or one that is too long or not printable ASCII.
+pub fn hyperlink(payload: &[u8]) -> Option<Hyperlink> {
+    let split = payload.iter().position(|&b| b == b';')?;
+    let (params, uri) = (&payload[..split], &payload[split + 1..]);
+    if uri.is_empty() || uri.len() > MAX_URL_LEN || !uri.iter().all(|b| (0x20..0x7f).contains(b)) {
+        return None;
+    }
+    let id = params
+        .split(|&b| b == b':')
+        .find_map(|param| param.strip_prefix(b"id="))
+        .filter(|id| !id.is_empty() && id.len() <= MAX_LINK_ID_LEN)
+        .map(|id| String::from_utf8_lossy(id).into_owned());
+    Some(Hyperlink {
+        id,
+        uri: String::from_utf8_lossy(uri).into_owned(),
+    })
+}
+/// `OSC 133 ; Pt`, shell integration marking where the parts of a command start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SemanticPrompt {
+    /// `A`, a prompt is about to be drawn
+    PromptStart,
+    /// `B`, the prompt ends and the command line starts
+    CommandStart,
+    /// `C`, the command was entered and its output follows
+    OutputStart,
+    /// `D ; code`, the command finished, with its exit code when the shell gave one
+    CommandFinished(Option<i32>),
+}
+pub fn semantic_prompt(payload: &[u8]) -> Option<SemanticPrompt> {
+    let mut params = payload.split(|&b| b == b';');
+    Some(match params.next()? {
+        b"A" => SemanticPrompt::PromptStart,
+        b"B" => SemanticPrompt::CommandStart,
+        b"C" => SemanticPrompt::OutputStart,
+        b"D" => SemanticPrompt::CommandFinished(
+            params
+                .next()
+                .and_then(|code| std::str::from_utf8(code).ok()?.parse().ok()),
+        ),
+        _ => return None,
+    })
+}
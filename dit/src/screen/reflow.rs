@@ -0,0 +1,113 @@
+use super::{Cell, CellWidth, LineInfo, Row};
+
+/// A cursor position among the rows handed to [`reflow`], `row` counts from the oldest row.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub row: usize,
+    pub col: usize,
+}
+impl Cell {
+    fn is_blank(&self) -> bool {
+        self.ch == ' '
+            && self.width == CellWidth::Single
+            && self.cell_attr == Default::default()
+            && self.link.is_none()
+    }
+}
+/// Joins soft-wrapped rows back into logical lines and splits them again every `cols` cells.
+/// Trailing blanks of each line are dropped, except on the cursor's line where they are kept
+/// up to the cursor. Wide characters stay whole, padding left where one didn't fit at the end
+/// of a row is dropped and added back as needed. Shell integration marks of a logical line
+/// stay on its first row. Returns the new rows and where the cursor's character ended up.
+pub fn reflow(rows: Vec<Row>, cols: usize, cursor: Position) -> (Vec<Row>, Position) {
+    let mut out = Vec::with_capacity(rows.len());
+    let mut new_cursor = Position::default();
+    let mut line: Vec<Cell> = Vec::new();
+    let mut line_info = LineInfo::default();
+    let mut cursor_offset = None;
+    let count = rows.len();
+    for (i, row) in rows.into_iter().enumerate() {
+        if i == cursor.row {
+            cursor_offset = Some(line.len() + cursor.col);
+        }
+        let mut cells = row.cells;
+        if row.info.wrapped && i + 1 < count && ends_in_padding(&cells) {
+            cells.pop();
+        }
+        line.extend(cells);
+        line_info.marks |= row.info.marks;
+        line_info.exit_code = line_info.exit_code.or(row.info.exit_code);
+        // The last row is flushed even if it was wrapped, there is nothing left to join
+        if row.info.wrapped && i + 1 < count {
+            continue;
+        }
+        let info = std::mem::take(&mut line_info);
+        let line = std::mem::take(&mut line);
+        if let Some(cursor) = split_line(&mut out, line, info, cols, cursor_offset.take()) {
+            new_cursor = cursor;
+        }
+    }
+    (out, new_cursor)
+}
+/// Whether the row's last cell pads out a wide character that went on the next row
+fn ends_in_padding(cells: &[Cell]) -> bool {
+    match cells {
+        [.., before, last] => last.width == CellWidth::Spacer && before.width != CellWidth::Wide,
+        [last] => last.width == CellWidth::Spacer,
+        [] => false,
+    }
+}
+/// Splits a logical line into rows of `cols` cells, returning where the cell at `cursor`
+/// ended up
+fn split_line(
+    out: &mut Vec<Row>,
+    mut line: Vec<Cell>,
+    info: LineInfo,
+    cols: usize,
+    cursor: Option<usize>,
+) -> Option<Position> {
+    let first = out.len();
+    let len = line
+        .iter()
+        .rposition(|cell| !cell.is_blank())
+        .map_or(0, |i| i + 1)
+        .max(cursor.map_or(0, |offset| offset + 1));
+    line.resize_with(len, Cell::default);
+    let mut position = None;
+    let mut row = Vec::with_capacity(cols);
+    for (i, cell) in line.into_iter().enumerate() {
+        // A wide character that would be cut in two moves to the next row
+        if cell.width == CellWidth::Wide && row.len() + 1 == cols && cols > 1 {
+            row.push(Cell {
+                width: CellWidth::Spacer,
+                ..Default::default()
+            });
+        }
+        if row.len() == cols {
+            out.push(Row {
+                cells: std::mem::take(&mut row),
+                info: LineInfo {
+                    wrapped: true,
+                    ..Default::default()
+                },
+            });
+        }
+        if cursor == Some(i) {
+            position = Some(Position {
+                row: out.len(),
+                col: row.len(),
+            });
+        }
+        row.push(cell);
+    }
+    // The last row, or the only one of an empty line
+    if !row.is_empty() || out.len() == first {
+        out.push(Row {
+            cells: row,
+            info: LineInfo::default(),
+        });
+    }
+    out[first].info.marks = info.marks;
+    out[first].info.exit_code = info.exit_code;
+    position
+}
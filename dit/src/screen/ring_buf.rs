@@ -1,24 +1,36 @@
-use std::ops::{Index, Range};
+use std::ops::{Index, IndexMut};
 
-struct RingBuffer<T: Copy + Default> {
+pub struct RingBuffer<T: Clone + Default> {
     data: Vec<T>,
     //start is the write index.
     start: usize,
+    //one slot is always left empty so a full buffer can be told apart from an empty one.
     capacity: usize,
     //end is the read index.
     end: usize,
 }
-impl<T: Copy + Default> Index<usize> for RingBuffer<T> {
+impl<T: Clone + Default> Index<usize> for RingBuffer<T> {
     type Output = T;
     fn index(&self, index: usize) -> &Self::Output {
         let abs_index = (self.end + index) % self.capacity;
         &self.data[abs_index]
     }
 }
-impl<T: Copy + Default> RingBuffer<T> {
+impl<T: Clone + Default> IndexMut<usize> for RingBuffer<T> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        let abs_index = (self.end + index) % self.capacity;
+        &mut self.data[abs_index]
+    }
+}
+impl<T: Clone + Default> Default for RingBuffer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<T: Clone + Default> RingBuffer<T> {
     ///Do not use this. If you do this, will allocate a capacity of 1 to ensure operations acting
     ///on this can work without panicking.
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             data: vec![T::default()],
             start: 0,
@@ -26,66 +38,50 @@ impl<T: Copy + Default> RingBuffer<T> {
             end: 0,
         }
     }
-    fn with_capacity(capacity: usize) -> Self {
+    ///Creates a buffer holding at most `capacity` values before the oldest get overwritten.
+    pub fn with_capacity(capacity: usize) -> Self {
         Self {
-            data: vec![T::default(); capacity],
+            data: vec![T::default(); capacity + 1],
             start: 0,
-            capacity,
+            capacity: capacity + 1,
             end: 0,
         }
     }
-    fn expand(&mut self, exact: usize) {
-        if self.capacity >= exact {
-            return;
-        }
-        self.make_contiguous();
-        self.data.resize(exact, T::default());
-        self.capacity = exact;
-    }
-    ///Shrinks the Vec by removing values that are not within the specified range.
-    ///The range provided is not absolute into the Vec but rather relative.
-    fn shrink(&mut self, range: Range<usize>) {
-        if range.end > self.capacity {
-            panic!(
-                "Range has exceeded the possible Vec capacity. If you want it to wrap around, implement it yourself."
-            )
-        }
-        //check if we can just get a slice from the start and end slice. if yes we can just easily
-        //use that for the new vec and return without an expensive make_contiguous call.
-        if self.end + range.start < self.capacity && self.end + range.end <= self.capacity {
-            self.data = self.data[self.end + range.start..self.end + range.end].to_vec();
-            return;
-        }
-        self.make_contiguous();
-        self.data = self.data[range].to_vec();
+    ///Number of values that can be stored before the oldest get overwritten.
+    pub fn capacity(&self) -> usize {
+        self.capacity - 1
+    }
+    pub fn len(&self) -> usize {
+        (self.start + self.capacity - self.end) % self.capacity
     }
-    fn make_contiguous(&mut self) {
-        if self.start >= self.end || self.data.is_empty() {
-            return;
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len() {
+            return None;
         }
-        let mut new_data = Vec::with_capacity(self.capacity);
-        new_data.extend_from_slice(&self.data[self.end..self.start]);
-        if self.start < self.end {
-            new_data.extend_from_slice(&self.data[0..self.start]);
+        Some(&self[index])
+    }
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len() {
+            return None;
         }
-        self.data = new_data;
+        Some(&mut self[index])
+    }
+    ///Iterates from the oldest to the newest value.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        (0..self.len()).map(|i| &self[i])
+    }
+    ///Drops every value, releasing whatever memory they held.
+    pub fn clear(&mut self) {
+        self.data = vec![T::default(); self.capacity];
+        self.start = 0;
         self.end = 0;
-        self.start = self.data.len(); // next write goes after last element
     }
     pub fn batch_push(&mut self, input: &[T]) {
-        let cap = self.capacity;
-        let n = input.len();
-        let write = self.start;
-        let first = (cap - write).min(n);
-        self.data[write..write + first].copy_from_slice(&input[..first]);
-        if n > first {
-            let second = n - first;
-            self.data[0..second].copy_from_slice(&input[first..]);
-        }
-        self.start = (self.start + n) % cap;
-        let used = (self.start + cap - self.end) % cap;
-        if used >= cap {
-            self.end = (self.start + 1) % cap;
+        for value in input {
+            self.push(value.clone());
         }
     }
     pub fn push(&mut self, new_value: T) {
@@ -95,5 +91,21 @@ impl<T: Copy + Default> RingBuffer<T> {
             self.end = (self.end + 1) % self.capacity; // overwrite oldest
         }
     }
-    pub fn batch_pop() {}
+    ///Removes every value, returning them from the oldest to the newest.
+    pub fn drain(&mut self) -> Vec<T> {
+        let mut values = Vec::with_capacity(self.len());
+        while let Some(value) = self.pop() {
+            values.push(value);
+        }
+        values.reverse();
+        values
+    }
+    ///Removes and returns the newest value.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.start = (self.start + self.capacity - 1) % self.capacity;
+        Some(std::mem::take(&mut self.data[self.start]))
+    }
 }
@@ -0,0 +1,72 @@
+//! Text selection. Points are kept in absolute line coordinates, counted from the first line
+//! ever pushed into history, so a selection stays on its text while output scrolls the screen
+//! and the viewport moves through history.
+
+/// Characters ending a word for double-click selection, on top of whitespace
+pub const DEFAULT_WORD_SEPARATORS: &str = ",│`|:\"'()[]{}<>";
+
+/// A cell position, `line` being absolute rather than relative to the screen or viewport
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Point {
+    pub line: usize,
+    pub col: usize,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionKind {
+    /// Every cell between the two ends in reading order, from click-drag
+    Simple,
+    /// Expanded to whole words at both ends, from double-click
+    Word,
+    /// Expanded to whole lines, soft wraps included, from triple-click
+    Line,
+    /// The rectangle between the two ends, from Alt-drag
+    Block,
+}
+#[derive(Debug, Clone)]
+pub struct Selection {
+    pub kind: SelectionKind,
+    /// Where the selection started
+    pub anchor: Point,
+    /// Where the pointer is now, either side of the anchor
+    pub extent: Point,
+}
+impl Selection {
+    pub fn new(kind: SelectionKind, point: Point) -> Self {
+        Self {
+            kind,
+            anchor: point,
+            extent: point,
+        }
+    }
+    /// Anchor and extent in reading order
+    pub fn ordered(&self) -> (Point, Point) {
+        if self.anchor <= self.extent {
+            (self.anchor, self.extent)
+        } else {
+            (self.extent, self.anchor)
+        }
+    }
+}
+/// The cells a selection covers once words and lines have been expanded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectionRange {
+    pub start: Point,
+    /// Inclusive
+    pub end: Point,
+    /// Only columns between those of `start` and `end` are covered on every line
+    pub block: bool,
+}
+impl SelectionRange {
+    pub fn contains(&self, point: Point) -> bool {
+        if self.block {
+            (self.start.line..=self.end.line).contains(&point.line)
+                && (self.start.col..=self.end.col).contains(&point.col)
+        } else {
+            (self.start..=self.end).contains(&point)
+        }
+    }
+    /// Whether any cell of lines `top..=bottom` is covered
+    pub fn intersects_lines(&self, top: usize, bottom: usize) -> bool {
+        top <= self.end.line && bottom >= self.start.line
+    }
+}
@@ -0,0 +1,46 @@
+//! Tab stops, one bit per column
+/// Columns between the default stops
+const TAB_WIDTH: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TabStops {
+    bits: Vec<u64>,
+    cols: usize,
+}
+impl TabStops {
+    /// A stop every eight columns
+    pub fn new(cols: usize) -> Self {
+        let mut stops = Self {
+            bits: vec![0; cols.div_ceil(64)],
+            cols,
+        };
+        for col in (TAB_WIDTH..cols).step_by(TAB_WIDTH) {
+            stops.set(col);
+        }
+        stops
+    }
+    pub fn is_set(&self, col: usize) -> bool {
+        col < self.cols && self.bits[col / 64] & (1 << (col % 64)) != 0
+    }
+    pub fn set(&mut self, col: usize) {
+        if col < self.cols {
+            self.bits[col / 64] |= 1 << (col % 64);
+        }
+    }
+    pub fn clear(&mut self, col: usize) {
+        if col < self.cols {
+            self.bits[col / 64] &= !(1 << (col % 64));
+        }
+    }
+    pub fn clear_all(&mut self) {
+        self.bits.fill(0);
+    }
+    /// The first stop after `col` and before `limit`
+    pub fn next(&self, col: usize, limit: usize) -> Option<usize> {
+        (col + 1..limit.min(self.cols)).find(|&col| self.is_set(col))
+    }
+    /// The last stop before `col` and at or after `limit`
+    pub fn prev(&self, col: usize, limit: usize) -> Option<usize> {
+        (limit..col).rev().find(|&col| self.is_set(col))
+    }
+}
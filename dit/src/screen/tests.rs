@@ -0,0 +1,1662 @@
+use atlas_gen::{allocator::ShelfAllocator, atlas::Atlas};
+use font_parser::{CellMetrics, TtfFont};
+use winit::dpi::LogicalSize;
+
+use crate::{
+    ansii::{
+        Parser,
+        details::{Attributes, Color, Intensity, KeyboardFlags, Rgb, TermMode},
+    },
+    clipboard::ClipboardKind,
+    screen::{
+        Cell, CellWidth, CursorShape, Marks, Screen,
+        hints::{self, HintKind},
+        osc::{self, ClipboardRequest, WorkingDirectory},
+        selection::SelectionKind,
+    },
+};
+
+const FONT_PATH: &str = "../JetBrainsMonoNerdFontMono-Regular.ttf";
+const FONT_SIZE: f32 = 12.0;
+
+/// Builds a screen that is exactly `cols` x `rows` cells big.
+fn screen(cols: usize, rows: usize) -> Screen {
+    let font = TtfFont::new(FONT_PATH).unwrap();
+    let metrics = CellMetrics::new(FONT_SIZE, &font);
+    // Pad by half a cell so flooring in calculate_dims lands on the exact size
+    let size = LogicalSize::new(
+        (cols as f32 + 0.5) * metrics.width,
+        (rows as f32 + 0.5) * metrics.height,
+    );
+    let atlas = Atlas::new(64, 64, ShelfAllocator::new(64, 64), 0);
+    let screen = Screen::new(FONT_SIZE, font, atlas, size);
+    assert_eq!((screen.col_size, screen.y_size), (cols, rows));
+    screen
+}
+
+/// Resizes `screen` to exactly `cols` x `rows` cells.
+fn resize(screen: &mut Screen, cols: usize, rows: usize) {
+    let (width, height) = (screen.cell_metrics().width, screen.cell_metrics().height);
+    let size = LogicalSize::new((cols as f32 + 0.5) * width, (rows as f32 + 0.5) * height);
+    screen.resize(FONT_SIZE, size);
+    assert_eq!((screen.col_size, screen.y_size), (cols, rows));
+}
+
+fn feed(screen: &mut Screen, input: &[u8]) {
+    let mut parser = Parser::new();
+    for &b in input {
+        parser.consume(b, screen);
+    }
+}
+
+/// Text of a row with trailing blanks removed, wide characters read once.
+fn text(cells: &[Cell]) -> String {
+    let line: String = cells
+        .iter()
+        .filter(|c| c.width != CellWidth::Spacer)
+        .map(|c| c.ch.to_string())
+        .collect();
+    line.trim_end().to_string()
+}
+
+/// Text of visible row `y`.
+fn line(screen: &Screen, y: usize) -> String {
+    text(&screen.cells[y * screen.col_size..(y + 1) * screen.col_size])
+}
+
+/// Feeds `input` and returns everything the screen queued for the pty.
+fn replies(screen: &mut Screen, input: &[u8]) -> String {
+    feed(screen, input);
+    String::from_utf8(std::mem::take(&mut screen.outbound)).unwrap()
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 1. Terminal reports
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn primary_device_attributes_reply() {
+    let mut s = screen(80, 24);
+    assert_eq!(replies(&mut s, b"\x1b[c"), "\x1b[?62;22c");
+}
+
+#[test]
+fn secondary_device_attributes_reply() {
+    let mut s = screen(80, 24);
+    assert_eq!(replies(&mut s, b"\x1b[>c"), "\x1b[>1;10;0c");
+}
+
+#[test]
+fn device_status_ok() {
+    let mut s = screen(80, 24);
+    assert_eq!(replies(&mut s, b"\x1b[5n"), "\x1b[0n");
+}
+
+#[test]
+fn cursor_position_report_is_one_based() {
+    let mut s = screen(80, 24);
+    assert_eq!(replies(&mut s, b"\x1b[5;10H\x1b[6n"), "\x1b[5;10R");
+}
+
+#[test]
+fn request_mode_reports_set_and_reset() {
+    let mut s = screen(80, 24);
+    // Autowrap is on by default, bracketed paste is not
+    assert_eq!(replies(&mut s, b"\x1b[?7$p"), "\x1b[?7;1$y");
+    assert_eq!(replies(&mut s, b"\x1b[?2004$p"), "\x1b[?2004;2$y");
+    assert_eq!(
+        replies(&mut s, b"\x1b[?2004h\x1b[?2004$p"),
+        "\x1b[?2004;1$y"
+    );
+}
+
+#[test]
+fn request_mode_unknown() {
+    let mut s = screen(80, 24);
+    assert_eq!(replies(&mut s, b"\x1b[?31337$p"), "\x1b[?31337;0$y");
+}
+
+#[test]
+fn xtversion_reply() {
+    let mut s = screen(80, 24);
+    let reply = replies(&mut s, b"\x1b[>q");
+    assert!(reply.starts_with("\x1bP>|dit("));
+    assert!(reply.ends_with("\x1b\\"));
+}
+
+#[test]
+fn window_size_in_chars() {
+    let mut s = screen(80, 24);
+    assert_eq!(replies(&mut s, b"\x1b[18t"), "\x1b[8;24;80t");
+}
+
+#[test]
+fn window_size_in_pixels() {
+    let mut s = screen(80, 24);
+    let reply = replies(&mut s, b"\x1b[14t");
+    assert!(reply.starts_with("\x1b[4;") && reply.ends_with('t'));
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 2. Character attributes
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn written_cells_take_current_attributes() {
+    let mut s = screen(80, 24);
+    feed(&mut s, b"\x1b[1;38:2::1:2:3mA\x1b[0mB");
+    assert_eq!(s.cells[0].cell_attr.intensity, Intensity::Bold);
+    assert_eq!(s.cells[0].cell_attr.fg, Color::Rgb(Rgb::new(1, 2, 3)));
+    assert_eq!(s.cells[1].cell_attr, Attributes::default());
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 3. Scrollback
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn line_feed_at_bottom_scrolls_into_history() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"one\r\ntwo\r\nthree\r\nfour");
+    assert_eq!(s.scrollback.len(), 1);
+    assert_eq!(text(&s.scrollback[0].cells), "one");
+    assert_eq!(line(&s, 0), "two");
+    assert_eq!(line(&s, 2), "four");
+}
+
+#[test]
+fn scroll_up_pushes_rows_in_order() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"a\r\nb\r\nc\x1b[2S");
+    let history: Vec<String> = s.scrollback.iter().map(|r| text(&r.cells)).collect();
+    assert_eq!(history, vec!["a", "b"]);
+    assert_eq!(line(&s, 0), "c");
+}
+
+#[test]
+fn scrollback_limit_drops_oldest() {
+    let mut s = screen(10, 2);
+    s.set_scrollback_limit(3);
+    for i in 0..10 {
+        feed(&mut s, format!("{}\r\n", i).as_bytes());
+    }
+    let history: Vec<String> = s.scrollback.iter().map(|r| text(&r.cells)).collect();
+    assert_eq!(history, vec!["6", "7", "8"]);
+}
+
+#[test]
+fn erase_saved_lines_clears_history_only() {
+    let mut s = screen(10, 2);
+    feed(&mut s, b"a\r\nb\r\nc");
+    s.scroll_viewport(1);
+    feed(&mut s, b"\x1b[3J");
+    assert!(s.scrollback.is_empty());
+    assert_eq!(s.display_offset, 0);
+    assert_eq!(line(&s, 1), "c");
+}
+
+#[test]
+fn viewport_offset_is_clamped_to_history() {
+    let mut s = screen(10, 2);
+    feed(&mut s, b"a\r\nb\r\nc\r\nd");
+    s.scroll_viewport(100);
+    assert_eq!(s.display_offset, 2);
+    s.scroll_viewport(-1);
+    assert_eq!(s.display_offset, 1);
+    s.scroll_to_bottom();
+    assert_eq!(s.display_offset, 0);
+}
+
+#[test]
+fn viewport_stays_on_content_while_output_arrives() {
+    let mut s = screen(10, 2);
+    feed(&mut s, b"a\r\nb\r\nc");
+    s.scroll_viewport(1);
+    feed(&mut s, b"\r\nd\r\ne");
+    assert_eq!(s.display_offset, 3);
+}
+
+#[test]
+fn mesh_renders_viewport_rows() {
+    let mut s = screen(10, 2);
+    s.construct_mesh();
+    let live = s.mesh.vertices.clone();
+    feed(&mut s, b"a\r\nb\r\n");
+    s.update_mesh();
+    s.scroll_viewport(1);
+    let ranges = s.update_mesh().unwrap();
+    // Scrolling the viewport rewrites the whole mesh in one range
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(ranges[0].end, live.len());
+    assert_eq!(s.mesh.vertices.len(), live.len());
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 4. Alternate screen
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn mode_1049_saves_cursor_and_restores_primary_screen() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"main\x1b[2;3H");
+    feed(&mut s, b"\x1b[?1049h");
+    assert!(s.modes.contains(TermMode::ALT_SCREEN));
+    assert_eq!(line(&s, 0), "");
+    feed(&mut s, b"\x1b[Halt");
+    assert_eq!(line(&s, 0), "alt");
+    feed(&mut s, b"\x1b[?1049l");
+    assert!(!s.modes.contains(TermMode::ALT_SCREEN));
+    assert_eq!(line(&s, 0), "main");
+    assert_eq!((s.cursor.y, s.cursor.col), (1, 2));
+}
+
+#[test]
+fn mode_1049_clears_alternate_screen_on_entry() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b[?47hstale\x1b[?47l");
+    feed(&mut s, b"\x1b[?1049h");
+    assert_eq!(line(&s, 0), "");
+}
+
+#[test]
+fn mode_47_keeps_alternate_contents() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b[?47hkept\x1b[?47l\x1b[?47h");
+    assert_eq!(line(&s, 0), "kept");
+}
+
+#[test]
+fn mode_1047_clears_alternate_screen_on_exit() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b[?1047hgone\x1b[?1047l\x1b[?47h");
+    assert_eq!(line(&s, 0), "");
+}
+
+#[test]
+fn mode_1048_saves_and_restores_cursor() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b[3;4H\x1b[?1048h\x1b[H\x1b[?1048l");
+    assert_eq!((s.cursor.y, s.cursor.col), (2, 3));
+}
+
+#[test]
+fn alternate_screen_has_no_scrollback() {
+    let mut s = screen(10, 2);
+    feed(&mut s, b"\x1b[?1049ha\r\nb\r\nc\r\nd");
+    assert!(s.scrollback.is_empty());
+    s.scroll_viewport(5);
+    assert_eq!(s.display_offset, 0);
+}
+
+#[test]
+fn switching_screens_redraws_whole_viewport() {
+    let mut s = screen(10, 2);
+    s.construct_mesh();
+    feed(&mut s, b"x\x1b[?1049h");
+    let ranges = s.update_mesh().unwrap();
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(ranges[0].end, s.mesh.vertices.len());
+}
+
+#[test]
+fn full_reset_leaves_alternate_screen() {
+    let mut s = screen(10, 2);
+    feed(&mut s, b"\x1b[?1049h\x1bc");
+    assert!(!s.modes.contains(TermMode::ALT_SCREEN));
+}
+
+#[test]
+fn soft_reset_keeps_the_screen_and_buffer() {
+    let mut s = screen(10, 2);
+    feed(&mut s, b"\x1b[?1049hab\x1b[3g\x1b[4h\x1b[1m\x1b[!p");
+    assert!(s.modes.contains(TermMode::ALT_SCREEN));
+    assert_eq!(line(&s, 0), "ab");
+    assert_eq!((s.cursor.y, s.cursor.col), (0, 2));
+    assert!(!s.modes.contains(TermMode::INSERT));
+    assert_eq!(s.attrs, Attributes::default());
+    // Tab stops stay cleared
+    feed(&mut s, b"\r\t");
+    assert_eq!(s.cursor.col, 9);
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 5. Scrolling regions and margins
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn line_feed_scrolls_only_the_region() {
+    let mut s = screen(10, 4);
+    feed(&mut s, b"top\r\na\r\nb\r\nbottom");
+    feed(&mut s, b"\x1b[2;3r\x1b[3;1H\nc");
+    assert_eq!(line(&s, 0), "top");
+    assert_eq!(line(&s, 1), "b");
+    assert_eq!(line(&s, 2), "c");
+    assert_eq!(line(&s, 3), "bottom");
+}
+
+#[test]
+fn region_below_top_does_not_feed_history() {
+    let mut s = screen(10, 4);
+    feed(&mut s, b"a\r\nb\x1b[2;4r\x1b[4;1H\n\n");
+    assert!(s.scrollback.is_empty());
+    assert_eq!(line(&s, 0), "a");
+}
+
+#[test]
+fn region_at_top_feeds_history() {
+    let mut s = screen(10, 4);
+    feed(&mut s, b"a\r\nb\r\nc\r\nfooter\x1b[1;3r\x1b[3;1H\n");
+    assert_eq!(text(&s.scrollback[0].cells), "a");
+    assert_eq!(line(&s, 3), "footer");
+}
+
+#[test]
+fn reverse_index_at_top_margin_scrolls_region_down() {
+    let mut s = screen(10, 4);
+    feed(&mut s, b"a\r\nb\r\nc\r\nd\x1b[2;3r\x1b[2;1H\x1bM");
+    assert_eq!(line(&s, 1), "");
+    assert_eq!(line(&s, 2), "b");
+    assert_eq!(line(&s, 3), "d");
+}
+
+#[test]
+fn insert_and_delete_lines_stay_in_region() {
+    let mut s = screen(10, 4);
+    feed(&mut s, b"a\r\nb\r\nc\r\nd\x1b[1;3r\x1b[2;1H\x1b[L");
+    assert_eq!(line(&s, 2), "b");
+    assert_eq!(line(&s, 3), "d");
+    feed(&mut s, b"\x1b[2M");
+    assert_eq!(line(&s, 1), "");
+    assert_eq!(line(&s, 3), "d");
+}
+
+#[test]
+fn insert_lines_outside_region_is_ignored() {
+    let mut s = screen(10, 4);
+    feed(&mut s, b"a\r\nb\r\nc\r\nd\x1b[1;2r\x1b[4;1H\x1b[L");
+    assert_eq!(line(&s, 3), "d");
+}
+
+#[test]
+fn invalid_region_is_ignored() {
+    let mut s = screen(10, 4);
+    feed(&mut s, b"\x1b[3;3r");
+    assert_eq!((s.top_margin, s.bottom_margin), (0, 3));
+}
+
+#[test]
+fn origin_mode_positions_relative_to_region() {
+    let mut s = screen(10, 6);
+    feed(&mut s, b"\x1b[3;5r\x1b[?6h");
+    assert_eq!((s.cursor.y, s.cursor.col), (2, 0));
+    feed(&mut s, b"\x1b[2;4H");
+    assert_eq!((s.cursor.y, s.cursor.col), (3, 3));
+    assert_eq!(replies(&mut s, b"\x1b[6n"), "\x1b[2;4R");
+    // Clamped to the bottom margin
+    feed(&mut s, b"\x1b[20H");
+    assert_eq!(s.cursor.y, 4);
+}
+
+#[test]
+fn cursor_up_stops_at_top_margin() {
+    let mut s = screen(10, 6);
+    feed(&mut s, b"\x1b[3;5r\x1b[4;1H\x1b[10A");
+    assert_eq!(s.cursor.y, 2);
+}
+
+#[test]
+fn left_right_margins_need_declrmm() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b[3;6s");
+    assert_eq!((s.left_margin, s.right_margin), (0, 9));
+    feed(&mut s, b"\x1b[?69h\x1b[3;6s");
+    assert_eq!((s.left_margin, s.right_margin), (2, 5));
+    feed(&mut s, b"\x1b[?69l");
+    assert_eq!((s.left_margin, s.right_margin), (0, 9));
+}
+
+#[test]
+fn csi_s_without_params_resets_margins_under_declrmm() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b[?69h\x1b[3;6s\x1b[s");
+    assert_eq!((s.left_margin, s.right_margin), (0, 9));
+    // Saves the cursor otherwise
+    feed(&mut s, b"\x1b[?69l\x1b[2;4H\x1b[s\x1b[H\x1b[u");
+    assert_eq!((s.cursor.y, s.cursor.col), (1, 3));
+}
+
+#[test]
+fn scroll_up_respects_left_right_margins() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"abcdefghij\x1b[2;1Hklmnopqrst");
+    feed(&mut s, b"\x1b[?69h\x1b[3;6s\x1b[S");
+    assert_eq!(line(&s, 0), "abmnopghij");
+    assert_eq!(line(&s, 1), "kl    qrst");
+    assert!(s.scrollback.is_empty());
+}
+
+#[test]
+fn soft_reset_clears_margins() {
+    let mut s = screen(10, 4);
+    feed(&mut s, b"\x1b[2;3r\x1b[!p");
+    assert_eq!((s.top_margin, s.bottom_margin), (0, 3));
+    feed(&mut s, b"\x1b[2;3r\x1bc");
+    assert_eq!((s.top_margin, s.bottom_margin), (0, 3));
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 6. Autowrap
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn last_column_defers_wrap() {
+    let mut s = screen(5, 3);
+    feed(&mut s, b"abcde");
+    assert_eq!((s.cursor.y, s.cursor.col), (0, 4));
+    assert!(s.cursor.pending_wrap);
+    feed(&mut s, b"f");
+    assert_eq!(line(&s, 1), "f");
+    assert_eq!((s.cursor.y, s.cursor.col), (1, 1));
+}
+
+#[test]
+fn newline_after_full_line_does_not_add_blank_line() {
+    let mut s = screen(5, 3);
+    feed(&mut s, b"abcde\r\nx");
+    assert_eq!(line(&s, 1), "x");
+    assert!(!s.lines[0].wrapped);
+}
+
+#[test]
+fn cursor_movement_cancels_pending_wrap() {
+    let mut s = screen(5, 3);
+    feed(&mut s, b"abcde\x1b[Dx");
+    assert_eq!(line(&s, 0), "abcxe");
+    assert_eq!(line(&s, 1), "");
+}
+
+#[test]
+fn autowrap_off_overwrites_last_column() {
+    let mut s = screen(5, 3);
+    feed(&mut s, b"\x1b[?7labcdefg");
+    assert_eq!(line(&s, 0), "abcdg");
+    assert_eq!(line(&s, 1), "");
+    assert!(!s.cursor.pending_wrap);
+}
+
+#[test]
+fn wrapping_marks_row_as_soft_wrapped() {
+    let mut s = screen(5, 3);
+    feed(&mut s, b"abcdefg\r\nh");
+    assert!(s.lines[0].wrapped);
+    assert!(!s.lines[1].wrapped);
+}
+
+#[test]
+fn soft_wrap_flag_follows_row_into_history() {
+    let mut s = screen(5, 2);
+    feed(&mut s, b"abcdefg\r\nh\r\n");
+    assert!(s.scrollback[0].info.wrapped);
+    assert!(!s.scrollback[1].info.wrapped);
+}
+
+#[test]
+fn erase_line_clears_soft_wrap() {
+    let mut s = screen(5, 3);
+    feed(&mut s, b"abcdefg\x1b[1;3H\x1b[K");
+    assert!(!s.lines[0].wrapped);
+}
+
+#[test]
+fn wrap_respects_right_margin() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b[?69h\x1b[3;6s\x1b[1;3Habcdef");
+    assert_eq!(line(&s, 0), "  abcd");
+    assert_eq!(line(&s, 1), "  ef");
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 7. Resize reflow
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn narrowing_rewraps_long_lines() {
+    let mut s = screen(10, 4);
+    feed(&mut s, b"abcdefgh\r\nxy");
+    resize(&mut s, 4, 4);
+    assert_eq!(line(&s, 0), "abcd");
+    assert!(s.lines[0].wrapped);
+    assert_eq!(line(&s, 1), "efgh");
+    assert!(!s.lines[1].wrapped);
+    assert_eq!(line(&s, 2), "xy");
+    assert_eq!((s.cursor.y, s.cursor.col), (2, 2));
+}
+
+#[test]
+fn widening_joins_soft_wrapped_rows() {
+    let mut s = screen(4, 4);
+    feed(&mut s, b"abcdefgh");
+    assert!(s.cursor.pending_wrap);
+    resize(&mut s, 10, 4);
+    assert_eq!(line(&s, 0), "abcdefgh");
+    assert_eq!(line(&s, 1), "");
+    assert!(!s.lines[0].wrapped);
+    // The cursor follows the last character and the wrap is no longer pending
+    assert_eq!((s.cursor.y, s.cursor.col), (0, 8));
+    assert!(!s.cursor.pending_wrap);
+}
+
+#[test]
+fn hard_newlines_are_not_joined() {
+    let mut s = screen(4, 4);
+    feed(&mut s, b"ab\r\ncd");
+    resize(&mut s, 10, 4);
+    assert_eq!(line(&s, 0), "ab");
+    assert_eq!(line(&s, 1), "cd");
+}
+
+#[test]
+fn reflow_includes_scrollback() {
+    let mut s = screen(4, 2);
+    feed(&mut s, b"abcdefgh\r\nij\r\nkl");
+    assert_eq!(s.scrollback.len(), 2);
+    resize(&mut s, 8, 2);
+    assert_eq!(text(&s.scrollback[0].cells), "abcdefgh");
+    assert_eq!(s.scrollback.len(), 1);
+    assert_eq!(line(&s, 0), "ij");
+    assert_eq!(line(&s, 1), "kl");
+}
+
+#[test]
+fn shrinking_height_keeps_cursor_on_screen() {
+    let mut s = screen(10, 4);
+    feed(&mut s, b"a\r\nb\r\nc\r\nd");
+    resize(&mut s, 10, 2);
+    let history: Vec<String> = s.scrollback.iter().map(|r| text(&r.cells)).collect();
+    assert_eq!(history, vec!["a", "b"]);
+    assert_eq!(line(&s, 1), "d");
+    assert_eq!(s.cursor.y, 1);
+    resize(&mut s, 10, 4);
+    assert!(s.scrollback.is_empty());
+    assert_eq!(line(&s, 0), "a");
+    assert_eq!(s.cursor.y, 3);
+}
+
+#[test]
+fn shrinking_height_drops_blank_rows_below_cursor() {
+    let mut s = screen(10, 4);
+    feed(&mut s, b"a\r\nb");
+    resize(&mut s, 10, 2);
+    assert!(s.scrollback.is_empty());
+    assert_eq!(line(&s, 0), "a");
+    assert_eq!(s.cursor.y, 1);
+}
+
+#[test]
+fn alternate_screen_is_cropped_not_reflowed() {
+    let mut s = screen(8, 3);
+    feed(&mut s, b"abcdefghij\x1b[?1049h\x1b[Hxyzxyzxy");
+    resize(&mut s, 4, 3);
+    assert_eq!(line(&s, 0), "xyzx");
+    assert_eq!(line(&s, 1), "");
+    feed(&mut s, b"\x1b[?1049l");
+    // The primary screen was rewrapped underneath
+    assert_eq!(line(&s, 0), "abcd");
+    assert_eq!(line(&s, 1), "efgh");
+    assert_eq!(line(&s, 2), "ij");
+}
+
+#[test]
+fn insert_mode_pushes_text_right() {
+    let mut s = screen(6, 3);
+    feed(&mut s, "abcd\x1b[1;2H\x1b[4hxy中".as_bytes());
+    // Pushed off the right edge
+    assert_eq!(line(&s, 0), "axy中b");
+    assert_eq!(replies(&mut s, b"\x1b[4$p"), "\x1b[4;1$y");
+    feed(&mut s, b"\x1b[4l\x1b[1;1Hz");
+    assert_eq!(line(&s, 0), "zxy中b");
+}
+
+#[test]
+fn shrinking_on_the_alternate_screen_keeps_the_primary_rows() {
+    // Neither 47 nor 1047 saves the cursor, the primary one is still known
+    for mode in ["47", "1047"] {
+        let mut s = screen(10, 6);
+        feed(&mut s, b"a\r\nb\r\nc\r\nd\r\ne\r\nf");
+        feed(&mut s, format!("\x1b[?{mode}h").as_bytes());
+        resize(&mut s, 10, 3);
+        feed(&mut s, format!("\x1b[?{mode}l").as_bytes());
+        let history: Vec<String> = s.scrollback.iter().map(|r| text(&r.cells)).collect();
+        assert_eq!(history, vec!["a", "b", "c"]);
+        assert_eq!(line(&s, 0), "d");
+        assert_eq!(line(&s, 2), "f");
+    }
+}
+
+#[test]
+fn shrinking_below_the_cursor_sends_rows_to_history() {
+    let mut s = screen(10, 4);
+    feed(&mut s, b"a\r\nb\r\nc\r\nd\x1b[2;1H");
+    resize(&mut s, 10, 2);
+    let history: Vec<String> = s.scrollback.iter().map(|r| text(&r.cells)).collect();
+    assert_eq!(history, vec!["a", "b"]);
+    assert_eq!(line(&s, 1), "d");
+    assert_eq!(s.cursor.y, 0);
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 8. Keyboard modes
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn deckpam_and_deckpnm_toggle_keypad_mode() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b=");
+    assert!(s.modes.contains(TermMode::KEYPAD_APPLICATION));
+    feed(&mut s, b"\x1b>");
+    assert!(!s.modes.contains(TermMode::KEYPAD_APPLICATION));
+    feed(&mut s, b"\x1b[?66h");
+    assert!(s.modes.contains(TermMode::KEYPAD_APPLICATION));
+}
+
+#[test]
+fn xtmodkeys_sets_modify_other_keys() {
+    let mut s = screen(10, 3);
+    // `4;2` must not be taken for SGR underline and faint
+    feed(&mut s, b"\x1b[>4;2mA");
+    assert_eq!(s.modify_other_keys, 2);
+    assert_eq!(s.cells[0].cell_attr, Attributes::default());
+    feed(&mut s, b"\x1b[>4m");
+    assert_eq!(s.modify_other_keys, 0);
+    feed(&mut s, b"\x1b[>4;1m\x1b[!p");
+    assert_eq!(s.modify_other_keys, 0);
+}
+
+#[test]
+fn kitty_keyboard_flags_stack() {
+    let mut s = screen(10, 3);
+    assert_eq!(replies(&mut s, b"\x1b[?u"), "\x1b[?0u");
+    feed(&mut s, b"\x1b[>1u\x1b[>11u");
+    assert_eq!(replies(&mut s, b"\x1b[?u"), "\x1b[?11u");
+    feed(&mut s, b"\x1b[<u");
+    assert_eq!(s.keyboard_flags(), KeyboardFlags::DISAMBIGUATE);
+    // Popping past the bottom leaves no enhancements
+    feed(&mut s, b"\x1b[<5u");
+    assert_eq!(s.keyboard_flags(), KeyboardFlags::empty());
+}
+
+#[test]
+fn kitty_keyboard_set_modes() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b[=3u");
+    assert_eq!(replies(&mut s, b"\x1b[?u"), "\x1b[?3u");
+    feed(&mut s, b"\x1b[=8;2u");
+    assert_eq!(replies(&mut s, b"\x1b[?u"), "\x1b[?11u");
+    feed(&mut s, b"\x1b[=1;3u");
+    assert_eq!(replies(&mut s, b"\x1b[?u"), "\x1b[?10u");
+}
+
+#[test]
+fn kitty_keyboard_flags_are_per_screen() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b[>1u\x1b[?1049h");
+    assert_eq!(s.keyboard_flags(), KeyboardFlags::empty());
+    feed(&mut s, b"\x1b[>8u\x1b[?1049l");
+    assert_eq!(s.keyboard_flags(), KeyboardFlags::DISAMBIGUATE);
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 9. Mouse
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn mouse_modes_replace_each_other() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b[?1000h\x1b[?1006h\x1b[?1003h\x1b[?1016h");
+    assert!(
+        s.modes
+            .contains(TermMode::MOUSE_ANY_EVENT | TermMode::MOUSE_SGR_PIXELS)
+    );
+    assert!(
+        !s.modes
+            .intersects(TermMode::MOUSE_NORMAL | TermMode::MOUSE_SGR)
+    );
+    assert_eq!(replies(&mut s, b"\x1b[?1002$p"), "\x1b[?1002;2$y");
+    feed(&mut s, b"\x1b[?1003l");
+    assert!(!s.modes.intersects(TermMode::MOUSE_TRACKING));
+}
+
+#[test]
+fn cell_at_maps_and_clamps_pixels() {
+    let s = screen(10, 3);
+    let metrics = s.cell_metrics();
+    let (width, height) = (metrics.width as f64, metrics.height as f64);
+    assert_eq!(s.cell_at(0.0, 0.0), (0, 0));
+    assert_eq!(s.cell_at(width * 2.5, height * 1.5), (2, 1));
+    assert_eq!(s.cell_at(-5.0, height * 10.0), (0, 2));
+    assert_eq!(s.cell_at(width * 50.0, 0.0), (9, 0));
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 10. Selection
+// ─────────────────────────────────────────────────────────────────────────
+
+/// Selects from viewport cell `from` to `to`, both (col, row).
+fn select(s: &mut Screen, kind: SelectionKind, from: (usize, usize), to: (usize, usize)) {
+    s.start_selection(kind, from.0, from.1);
+    s.update_selection(to.0, to.1);
+}
+
+#[test]
+fn simple_selection_joins_rows_and_drops_trailing_blanks() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"hello\r\nworld");
+    select(&mut s, SelectionKind::Simple, (1, 0), (2, 1));
+    assert_eq!(s.selection_text().as_deref(), Some("ello\nwor"));
+    // Dragging backwards selects the same cells
+    select(&mut s, SelectionKind::Simple, (9, 1), (0, 0));
+    assert_eq!(s.selection_text().as_deref(), Some("hello\nworld"));
+}
+
+#[test]
+fn click_without_drag_selects_nothing() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"hello");
+    s.start_selection(SelectionKind::Simple, 1, 0);
+    assert_eq!(s.selection_text(), None);
+}
+
+#[test]
+fn soft_wrapped_rows_are_joined_without_newline() {
+    let mut s = screen(5, 3);
+    feed(&mut s, b"abcdefgh");
+    select(&mut s, SelectionKind::Simple, (0, 0), (4, 1));
+    assert_eq!(s.selection_text().as_deref(), Some("abcdefgh"));
+}
+
+#[test]
+fn word_selection_follows_soft_wraps() {
+    let mut s = screen(5, 3);
+    feed(&mut s, b"ab cdefg hi");
+    s.start_selection(SelectionKind::Word, 3, 0);
+    assert_eq!(s.selection_text().as_deref(), Some("cdefg"));
+    // Dragging extends to whole words
+    s.update_selection(1, 2);
+    assert_eq!(s.selection_text().as_deref(), Some("cdefg hi"));
+}
+
+#[test]
+fn word_separators_are_configurable() {
+    let mut s = screen(20, 3);
+    feed(&mut s, b"src/main.rs:12:5");
+    s.start_selection(SelectionKind::Word, 0, 0);
+    assert_eq!(s.selection_text().as_deref(), Some("src/main.rs"));
+    s.word_separators = String::new();
+    s.start_selection(SelectionKind::Word, 0, 0);
+    assert_eq!(s.selection_text().as_deref(), Some("src/main.rs:12:5"));
+}
+
+#[test]
+fn line_selection_covers_the_whole_wrapped_line() {
+    let mut s = screen(5, 4);
+    feed(&mut s, b"first\r\nabcdefgh\r\nlast");
+    s.start_selection(SelectionKind::Line, 0, 2);
+    assert_eq!(s.selection_text().as_deref(), Some("abcdefgh"));
+}
+
+#[test]
+fn block_selection_takes_the_same_columns_of_each_row() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"abcdef\r\nghijkl\r\nmnopqr");
+    select(&mut s, SelectionKind::Block, (3, 0), (1, 2));
+    assert_eq!(s.selection_text().as_deref(), Some("bcd\nhij\nnop"));
+}
+
+#[test]
+fn selection_stays_on_its_text_while_output_scrolls() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"one\r\ntwo\r\nthree");
+    select(&mut s, SelectionKind::Simple, (0, 1), (2, 1));
+    feed(&mut s, b"\r\nfour\r\nfive");
+    assert_eq!(s.selection_text().as_deref(), Some("two"));
+    // Selecting while scrolled back picks lines from history
+    s.scroll_viewport(2);
+    select(&mut s, SelectionKind::Simple, (0, 0), (4, 0));
+    assert_eq!(s.selection_text().as_deref(), Some("one"));
+}
+
+#[test]
+fn overwriting_selected_text_clears_the_selection() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"one\r\ntwo");
+    select(&mut s, SelectionKind::Simple, (0, 1), (2, 1));
+    // Writing elsewhere leaves it alone
+    feed(&mut s, b"\x1b[1;5Hx");
+    assert_eq!(s.selection_text().as_deref(), Some("two"));
+    feed(&mut s, b"\x1b[2;2HX");
+    assert_eq!(s.selection_text(), None);
+
+    select(&mut s, SelectionKind::Simple, (0, 0), (2, 0));
+    feed(&mut s, b"\x1b[1;1H\x1b[K");
+    assert_eq!(s.selection_text(), None);
+}
+
+#[test]
+fn selection_is_dropped_when_evicted_from_history() {
+    let mut s = screen(10, 2);
+    s.set_scrollback_limit(1);
+    feed(&mut s, b"one\r\ntwo");
+    select(&mut s, SelectionKind::Simple, (0, 0), (2, 0));
+    feed(&mut s, b"\r\nthree");
+    assert_eq!(s.selection_text().as_deref(), Some("one"));
+    feed(&mut s, b"\r\nfour");
+    assert_eq!(s.selection_text(), None);
+}
+
+#[test]
+fn mesh_marks_selected_cells() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"hello");
+    select(&mut s, SelectionKind::Simple, (1, 0), (2, 0));
+    s.update_mesh();
+    let selected: Vec<bool> = (0..4)
+        // The glyph quad follows the selection background quad of each cell
+        .map(|col| s.mesh.vertices[col * 12 + 4].selected == 1.0)
+        .collect();
+    assert_eq!(selected, [false, true, true, false]);
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 11. Clipboard
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn base64_round_trips() {
+    for text in ["", "a", "ab", "abc", "hello, world", "ünïcödé"] {
+        let encoded = osc::base64_encode(text.as_bytes());
+        assert_eq!(
+            osc::base64_decode(encoded.as_bytes()).unwrap(),
+            text.as_bytes()
+        );
+    }
+    assert_eq!(osc::base64_encode(b"hi"), "aGk=");
+    // Padding is optional, but only at the end
+    assert_eq!(osc::base64_decode(b"aGk").unwrap(), b"hi");
+    assert_eq!(osc::base64_decode(b"aG=k"), None);
+    assert_eq!(osc::base64_decode(b"a!k="), None);
+}
+
+#[test]
+fn osc_52_sets_the_clipboard() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b]52;c;aGVsbG8=\x07\x1b]52;p;d29ybGQ=\x1b\\");
+    assert_eq!(
+        s.clipboard_requests,
+        [
+            ClipboardRequest::Store(ClipboardKind::Clipboard, "hello".into()),
+            ClipboardRequest::Store(ClipboardKind::Primary, "world".into()),
+        ]
+    );
+}
+
+#[test]
+fn osc_52_queries_and_defaults_to_the_clipboard() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b]52;;?\x07");
+    assert_eq!(
+        s.clipboard_requests,
+        [ClipboardRequest::Load(ClipboardKind::Clipboard)]
+    );
+    assert_eq!(
+        osc::clipboard_reply(ClipboardKind::Clipboard, "hi"),
+        "\x1b]52;c;aGk=\x1b\\"
+    );
+}
+
+#[test]
+fn osc_52_ignores_bad_and_oversized_payloads() {
+    let mut s = screen(10, 3);
+    // Not base64, a cut buffer we don't have, and no data at all
+    feed(&mut s, b"\x1b]52;c;*\x07\x1b]52;0;aGk=\x07\x1b]52;c\x07");
+    let mut big = b"\x1b]52;c;".to_vec();
+    big.resize(big.len() + osc::MAX_CLIPBOARD_PAYLOAD + 4, b'A');
+    big.push(0x07);
+    feed(&mut s, &big);
+    assert!(s.clipboard_requests.is_empty());
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 12. Titles and working directory
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn osc_sets_title_and_icon_name() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b]2;vim\x07\x1b]1;v\x07");
+    assert_eq!((s.title(), s.icon_name()), (Some("vim"), Some("v")));
+    assert!(s.take_title_changed());
+    assert!(!s.take_title_changed());
+    feed(&mut s, b"\x1b]0;both; here\x1b\\");
+    assert_eq!(
+        (s.title(), s.icon_name()),
+        (Some("both; here"), Some("both; here"))
+    );
+    // Empty goes back to the application's title
+    feed(&mut s, b"\x1b]2;\x07");
+    assert_eq!(s.title(), None);
+}
+
+#[test]
+fn title_drops_control_characters_and_is_capped() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b]2;a\tb\xc2\x85c\x07");
+    assert_eq!(s.title(), Some("abc"));
+    let mut long = b"\x1b]2;".to_vec();
+    long.resize(long.len() + osc::MAX_TITLE_LEN + 10, b'x');
+    long.push(0x07);
+    feed(&mut s, &long);
+    assert_eq!(s.title().unwrap().len(), osc::MAX_TITLE_LEN);
+}
+
+#[test]
+fn title_stack_pushes_and_pops() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b]0;one\x07\x1b[22;0t\x1b]0;two\x07");
+    feed(&mut s, b"\x1b[23;2t");
+    assert_eq!((s.title(), s.icon_name()), (Some("one"), Some("two")));
+    // Popping an empty stack changes nothing
+    feed(&mut s, b"\x1b[23;0t");
+    assert_eq!(s.title(), Some("one"));
+}
+
+#[test]
+fn title_stack_is_bounded() {
+    let mut s = screen(10, 3);
+    for i in 0..osc::TITLE_STACK_DEPTH + 5 {
+        feed(&mut s, format!("\x1b]2;{}\x07\x1b[22t", i).as_bytes());
+    }
+    for _ in 0..osc::TITLE_STACK_DEPTH + 5 {
+        feed(&mut s, b"\x1b[23t");
+    }
+    // The oldest entries were dropped
+    assert_eq!(s.title(), Some("5"));
+}
+
+#[test]
+fn osc_7_tracks_the_working_directory() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b]7;file://box/home/me/my%20dir\x07");
+    assert_eq!(
+        s.working_directory,
+        Some(WorkingDirectory {
+            host: "box".into(),
+            path: "/home/me/my dir".into(),
+        })
+    );
+    assert!(osc::working_directory(b"file:///tmp").unwrap().is_local());
+    // Other schemes and broken escapes are dropped
+    feed(&mut s, b"\x1b]7;http://box/tmp\x07");
+    assert_eq!(s.working_directory, None);
+    assert_eq!(osc::working_directory(b"file:///a%2"), None);
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 13. Hyperlinks
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn osc_8_links_the_text_written_under_it() {
+    let mut s = screen(10, 3);
+    feed(
+        &mut s,
+        b"a\x1b]8;;https://example.com\x1b\\bc\x1b]8;;\x1b\\d",
+    );
+    let links: Vec<_> = (0..4).map(|col| s.link_at(col, 0)).collect();
+    assert_eq!(links[0], None);
+    assert!(links[1].is_some() && links[1] == links[2]);
+    assert_eq!(links[3], None);
+    assert_eq!(
+        s.hyperlink(links[1].unwrap()).unwrap().uri,
+        "https://example.com"
+    );
+}
+
+#[test]
+fn osc_8_groups_links_by_id() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b]8;id=1;u\x07a\x1b]8;;\x07 \x1b]8;id=1;u\x07b");
+    feed(&mut s, b"\x1b]8;;u\x07c\x1b]8;;u\x07d\x1b]8;id=1;v\x07e");
+    let links: Vec<_> = (0..6).map(|col| s.link_at(col, 0)).collect();
+    // Same id and URI, even across separate sequences
+    assert_eq!(links[0], links[2]);
+    // Anonymous links are each their own, and an id only groups links to the same URI
+    assert_ne!(links[3], links[4]);
+    assert_ne!(links[2], links[5]);
+}
+
+#[test]
+fn osc_8_ignores_bad_uris() {
+    let mut s = screen(10, 3);
+    feed(
+        &mut s,
+        b"\x1b]8;;\x07a\x1b]8;;caf\xc3\xa9\x07b\x1b]8;no-uri\x07c",
+    );
+    assert!((0..3).all(|col| s.link_at(col, 0).is_none()));
+}
+
+#[test]
+fn links_survive_scrollback_and_reflow() {
+    let mut s = screen(4, 2);
+    feed(&mut s, b"xx\x1b]8;;u\x07abcd\x1b]8;;\x07");
+    let link = s.cells[2].link;
+    assert!(link.is_some());
+    assert_eq!(s.cells[4].link, link);
+    // Unwrapped onto one row
+    resize(&mut s, 8, 2);
+    assert_eq!(line(&s, 0), "xxabcd");
+    assert!((2..6).all(|col| s.cells[col].link == link));
+    // Then pushed into history and scrolled back to
+    feed(&mut s, b"\r\n\r\n");
+    s.scroll_viewport(1);
+    assert_eq!(s.link_at(5, 0), link);
+    assert_eq!(s.hyperlink(link.unwrap()).unwrap().uri, "u");
+}
+
+#[test]
+fn unreferenced_links_are_pruned() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b]8;;gone\x07a\x1b]8;;kept\x07b\x1b]8;;\x07");
+    let (gone, kept) = (s.link_at(0, 0).unwrap(), s.link_at(1, 0).unwrap());
+    // Overwritten, nothing refers to the first link anymore
+    feed(&mut s, b"\rc");
+    for _ in 0..5000 {
+        feed(&mut s, b"\x1b]8;;u\x07");
+    }
+    assert!(s.hyperlink(gone).is_none());
+    assert_eq!(s.hyperlink(kept).unwrap().uri, "kept");
+}
+
+#[test]
+fn hovered_link_is_underlined() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"a\x1b]8;;u\x07bc\x1b]8;;\x07d");
+    s.construct_mesh();
+    assert!(s.set_hovered_link(s.link_at(1, 0)));
+    assert!(!s.set_hovered_link(s.link_at(2, 0)));
+    s.update_mesh();
+    let underlined: Vec<bool> = (0..4)
+        // The underline quad is the last of each cell, collapsed to a point when not drawn
+        .map(|col| s.mesh.vertices[col * 12 + 8].pos != s.mesh.vertices[col * 12 + 10].pos)
+        .collect();
+    assert_eq!(underlined, [false, true, true, false]);
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 14. Hints
+// ─────────────────────────────────────────────────────────────────────────
+
+/// Starts hints mode with the default rules and picks every match in label order
+fn hint_texts(s: &mut Screen) -> Vec<(String, HintKind)> {
+    let count = s.start_hints(&hints::default_rules(), hints::DEFAULT_HINT_ALPHABET);
+    let labels = hints::labels(count, hints::DEFAULT_HINT_ALPHABET);
+    let found = labels
+        .iter()
+        .map(|label| {
+            s.start_hints(&hints::default_rules(), hints::DEFAULT_HINT_ALPHABET);
+            let mut picked = None;
+            for ch in label.chars() {
+                picked = s.hint_input(ch);
+            }
+            let picked = picked.unwrap();
+            (picked.text, picked.kind)
+        })
+        .collect();
+    assert!(!s.hints_active());
+    found
+}
+
+#[test]
+fn default_rules_find_urls_paths_ips_and_hashes() {
+    let mut s = screen(80, 3);
+    feed(
+        &mut s,
+        b"see https://example.com/a/b. at src/main.rs:12:5 from 10.0.0.1 in 3e1f2a9",
+    );
+    assert_eq!(
+        hint_texts(&mut s),
+        [
+            ("https://example.com/a/b".to_string(), HintKind::Url),
+            ("src/main.rs:12:5".to_string(), HintKind::Path),
+            ("10.0.0.1".to_string(), HintKind::Text),
+            ("3e1f2a9".to_string(), HintKind::Text),
+        ]
+    );
+}
+
+#[test]
+fn hints_follow_soft_wraps_into_history() {
+    let mut s = screen(10, 2);
+    feed(&mut s, b"go https://example.com/path");
+    // The URL starts on a row that scrolled into history
+    assert_eq!(s.scrollback.len(), 1);
+    assert_eq!(
+        hint_texts(&mut s),
+        [("https://example.com/path".to_string(), HintKind::Url)]
+    );
+}
+
+#[test]
+fn hints_search_history_scrolled_into_view() {
+    let mut s = screen(20, 2);
+    feed(&mut s, b"at lib.rs:3\r\n\r\n\r\n");
+    assert_eq!(s.start_hints(&hints::default_rules(), "ab"), 0);
+    s.scroll_viewport(2);
+    assert_eq!(
+        hint_texts(&mut s),
+        [("lib.rs:3".to_string(), HintKind::Path)]
+    );
+}
+
+#[test]
+fn hint_labels_are_prefix_free() {
+    assert_eq!(hints::labels(3, "asd"), ["a", "s", "d"]);
+    let labels = hints::labels(5, "ab");
+    assert_eq!(labels, ["aaa", "aab", "aba", "abb", "baa"]);
+}
+
+#[test]
+fn hint_input_ignores_letters_no_label_has() {
+    let mut s = screen(40, 3);
+    feed(&mut s, b"a.rs:1 b.rs:2 c.rs:3");
+    assert_eq!(s.start_hints(&hints::default_rules(), "ab"), 3);
+    assert_eq!(s.hint_input('x'), None);
+    assert_eq!(s.hint_input('b'), None);
+    // The labels are `aa`, `ab` and `ba`, there is no `bb`
+    assert_eq!(s.hint_input('b'), None);
+    assert!(s.hints_active());
+    s.hint_backspace();
+    assert_eq!(s.hint_input('a'), None);
+    assert_eq!(s.hint_input('b').unwrap().text, "b.rs:2");
+}
+
+#[test]
+fn output_over_a_match_cancels_hints() {
+    let mut s = screen(20, 3);
+    feed(&mut s, b"x.rs:1\r\n");
+    s.start_hints(&hints::default_rules(), "ab");
+    // Output on another line leaves them
+    feed(&mut s, b"hello");
+    assert!(s.hints_active());
+    feed(&mut s, b"\x1b[1;1Hy");
+    assert!(!s.hints_active());
+}
+
+#[test]
+fn mesh_draws_hint_labels() {
+    let mut s = screen(20, 3);
+    feed(&mut s, b"at x.rs:1");
+    s.construct_mesh();
+    s.start_hints(&hints::default_rules(), "ab");
+    s.update_mesh();
+    let glyph_selected = |col: usize| s.mesh.vertices[col * 12 + 4].selected == 1.0;
+    // The one letter label covers the first cell of the match
+    assert!(!glyph_selected(2) && glyph_selected(3) && !glyph_selected(4));
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 15. Shell integration
+// ─────────────────────────────────────────────────────────────────────────
+
+/// Goes through a prompt, `command` and its `output` the way an integrated shell reports them.
+fn run_command(screen: &mut Screen, command: &str, output: &str, code: i32) {
+    let marked = format!(
+        "\x1b]133;A\x07$ \x1b]133;B\x07{command}\r\n\x1b]133;C\x07{output}\x1b]133;D;{code}\x07"
+    );
+    feed(screen, marked.as_bytes());
+}
+
+#[test]
+fn semantic_prompt_parses_marks_and_exit_codes() {
+    assert_eq!(
+        osc::semantic_prompt(b"A"),
+        Some(osc::SemanticPrompt::PromptStart)
+    );
+    assert_eq!(
+        osc::semantic_prompt(b"D;127"),
+        Some(osc::SemanticPrompt::CommandFinished(Some(127)))
+    );
+    assert_eq!(
+        osc::semantic_prompt(b"D"),
+        Some(osc::SemanticPrompt::CommandFinished(None))
+    );
+    assert_eq!(osc::semantic_prompt(b"Z"), None);
+}
+
+#[test]
+fn rows_carry_marks_and_prompts_the_exit_code() {
+    let mut s = screen(20, 6);
+    run_command(&mut s, "ls", "a\r\nb\r\n", 0);
+    run_command(&mut s, "false", "", 1);
+    assert_eq!(s.lines[0].marks, Marks::PROMPT | Marks::COMMAND);
+    assert_eq!(s.lines[1].marks, Marks::OUTPUT);
+    assert_eq!(s.lines[2].marks, Marks::empty());
+    assert_eq!(
+        s.lines[3].marks,
+        Marks::FINISHED | Marks::PROMPT | Marks::COMMAND
+    );
+    // Nothing printed, the output ends where it starts
+    assert_eq!(s.lines[4].marks, Marks::OUTPUT | Marks::FINISHED);
+    assert_eq!(s.lines[0].exit_code, Some(0));
+    assert_eq!(s.lines[3].exit_code, Some(1));
+}
+
+#[test]
+fn finish_without_a_command_is_ignored() {
+    let mut s = screen(20, 3);
+    feed(&mut s, b"\x1b]133;A\x07$ \x1b]133;D;1\x07");
+    assert_eq!(s.lines[0].marks, Marks::PROMPT);
+    assert_eq!(s.lines[0].exit_code, None);
+}
+
+#[test]
+fn erase_below_keeps_the_cursor_rows_marks() {
+    let mut s = screen(20, 4);
+    run_command(&mut s, "false", "", 1);
+    // Shells clear what's below the prompt they just marked
+    feed(&mut s, b"\x1b]133;A\x07$ \x1b[J");
+    assert!(s.lines[1].marks.contains(Marks::PROMPT));
+    // Rows below the cursor lose theirs
+    feed(&mut s, b"\x1b[1;5H\x1b[J");
+    assert_eq!(s.lines[0].marks, Marks::PROMPT | Marks::COMMAND);
+    assert_eq!(s.lines[0].exit_code, Some(1));
+    assert_eq!(s.lines[1].marks, Marks::empty());
+}
+
+#[test]
+fn alternate_screen_is_not_marked() {
+    let mut s = screen(20, 3);
+    feed(&mut s, b"\x1b[?1049h\x1b]133;A\x07");
+    assert_eq!(s.lines[0].marks, Marks::empty());
+}
+
+#[test]
+fn select_last_output_spans_the_output_rows() {
+    let mut s = screen(20, 6);
+    run_command(&mut s, "ls", "a\r\nb\r\n", 0);
+    assert!(s.select_last_output());
+    assert_eq!(s.selection_text().unwrap(), "a\nb");
+    // A command that printed nothing leaves nothing to select
+    run_command(&mut s, "true", "", 0);
+    assert!(!s.select_last_output());
+}
+
+#[test]
+fn scroll_to_prompt_moves_between_prompts_in_history() {
+    let mut s = screen(20, 3);
+    run_command(&mut s, "one", "1\r\n1\r\n", 0);
+    run_command(&mut s, "two", "2\r\n2\r\n", 0);
+    run_command(&mut s, "three", "3\r\n", 0);
+    assert_eq!(line(&s, 0), "$ three");
+    // Each prompt is three rows above the next
+    s.scroll_to_prompt(true);
+    assert_eq!(s.display_offset, 3);
+    s.scroll_to_prompt(true);
+    assert_eq!(s.display_offset, 6);
+    // Nothing above the first one
+    s.scroll_to_prompt(true);
+    assert_eq!(s.display_offset, 6);
+    s.scroll_to_prompt(false);
+    assert_eq!(s.display_offset, 3);
+    s.scroll_to_prompt(false);
+    assert_eq!(s.display_offset, 0);
+}
+
+#[test]
+fn reflow_keeps_marks_on_the_first_row() {
+    let mut s = screen(10, 6);
+    run_command(&mut s, "ls", "abcdefgh\r\n", 2);
+    resize(&mut s, 5, 6);
+    assert_eq!(line(&s, 1), "abcde");
+    assert_eq!(s.lines[1].marks, Marks::OUTPUT);
+    assert_eq!(s.lines[2].marks, Marks::empty());
+    resize(&mut s, 10, 6);
+    assert_eq!(s.lines[0].exit_code, Some(2));
+    assert_eq!(s.lines[1].marks, Marks::OUTPUT);
+    assert_eq!(s.lines[2].marks, Marks::FINISHED);
+}
+
+#[test]
+fn failed_prompt_gets_a_gutter_mark() {
+    let mut s = screen(10, 4);
+    run_command(&mut s, "ok", "", 0);
+    run_command(&mut s, "bad", "", 1);
+    s.construct_mesh();
+    let marked = |y: usize, col: usize| {
+        let cell = (y * 10 + col) * 12;
+        s.mesh.vertices[cell + 8].pos != s.mesh.vertices[cell + 10].pos
+    };
+    // The second prompt follows the first command's finish on its row
+    assert!(!marked(0, 0));
+    assert!(marked(1, 0) && !marked(1, 1));
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 16. Tab stops
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn tab_moves_to_the_next_stop_without_erasing() {
+    let mut s = screen(20, 3);
+    feed(&mut s, b"abcdefghijk\r\t");
+    assert_eq!(s.cursor.col, 8);
+    assert_eq!(line(&s, 0), "abcdefghijk");
+    feed(&mut s, b"\tX");
+    assert_eq!(line(&s, 0), "abcdefghijk     X");
+}
+
+#[test]
+fn tab_stops_at_the_last_column() {
+    let mut s = screen(20, 3);
+    feed(&mut s, b"\t\t\t\t");
+    assert_eq!(s.cursor.col, 19);
+}
+
+#[test]
+fn tab_stays_inside_the_margins() {
+    let mut s = screen(20, 3);
+    feed(&mut s, b"\x1b[?69h\x1b[3;10s\x1b[1;4H\t\t");
+    assert_eq!(s.cursor.col, 9);
+    feed(&mut s, b"\x1b[3Z");
+    assert_eq!(s.cursor.col, 2);
+}
+
+#[test]
+fn set_and_clear_tab_stops() {
+    let mut s = screen(20, 3);
+    feed(&mut s, b"\x1b[3g\x1b[4G\x1bH\r\t");
+    assert_eq!(s.cursor.col, 3);
+    feed(&mut s, b"\x1b[0g\r\t");
+    assert_eq!(s.cursor.col, 19);
+}
+
+#[test]
+fn forward_and_backward_tabulation_count_stops() {
+    let mut s = screen(40, 3);
+    feed(&mut s, b"\x1b[2I");
+    assert_eq!(s.cursor.col, 16);
+    feed(&mut s, b"\x1b[Z");
+    assert_eq!(s.cursor.col, 8);
+    feed(&mut s, b"\x1b[5Z");
+    assert_eq!(s.cursor.col, 0);
+}
+
+#[test]
+fn tab_stops_are_reset_by_decst8c_ris_and_resize() {
+    let mut s = screen(20, 3);
+    feed(&mut s, b"\x1b[3g\x1b[?5W\t");
+    assert_eq!(s.cursor.col, 8);
+    feed(&mut s, b"\x1b[3g\x1bc\t");
+    assert_eq!(s.cursor.col, 8);
+    feed(&mut s, b"\x1b[3g");
+    resize(&mut s, 30, 3);
+    feed(&mut s, b"\r\t\t\t");
+    assert_eq!(s.cursor.col, 24);
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 17. Cursor save/restore and style
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn decsc_restores_position_and_attributes() {
+    let mut s = screen(20, 5);
+    feed(&mut s, b"\x1b[1m\x1b[2;3H\x1b7\x1b[0m\x1b[5;5H\x1b8X");
+    assert_eq!(line(&s, 1), "  X");
+    assert_eq!(s.cells[20 + 2].cell_attr.intensity, Intensity::Bold);
+    // SCOSC and SCORC do the same
+    feed(&mut s, b"\x1b[4;1H\x1b[s\x1b[H\x1b[uY");
+    assert_eq!(line(&s, 3), "Y");
+}
+
+#[test]
+fn decsc_restores_origin_mode_and_pending_wrap() {
+    let mut s = screen(10, 5);
+    feed(&mut s, b"\x1b[2;4r\x1b[?6h\x1b7\x1b[?6l\x1b8");
+    assert!(s.modes.contains(TermMode::ORIGIN));
+    let mut s = screen(10, 5);
+    feed(&mut s, b"0123456789\x1b7\x1b[3;3H\x1b8X");
+    assert_eq!(line(&s, 0), "0123456789");
+    assert_eq!(line(&s, 1), "X");
+}
+
+#[test]
+fn restore_without_save_goes_home() {
+    let mut s = screen(10, 5);
+    feed(&mut s, b"\x1b[1m\x1b[3;3H\x1b8X");
+    assert_eq!(line(&s, 0), "X");
+    assert_eq!(s.cells[0].cell_attr, Attributes::default());
+}
+
+#[test]
+fn each_screen_has_its_own_saved_cursor() {
+    let mut s = screen(10, 5);
+    feed(
+        &mut s,
+        b"\x1b[2;2H\x1b7\x1b[?47h\x1b[4;4H\x1b7\x1b[H\x1b[?47l\x1b8",
+    );
+    assert_eq!((s.cursor.y, s.cursor.col), (1, 1));
+    feed(&mut s, b"\x1b[?47h\x1b8");
+    assert_eq!((s.cursor.y, s.cursor.col), (3, 3));
+}
+
+#[test]
+fn decscusr_sets_shape_and_blinking() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b[4 q");
+    assert_eq!(
+        (s.cursor.shape, s.cursor.blinking),
+        (CursorShape::Underline, false)
+    );
+    feed(&mut s, b"\x1b[5 q");
+    assert_eq!(
+        (s.cursor.shape, s.cursor.blinking),
+        (CursorShape::Bar, true)
+    );
+    // Unknown styles are ignored
+    feed(&mut s, b"\x1b[9 q");
+    assert_eq!(s.cursor.shape, CursorShape::Bar);
+    feed(&mut s, b"\x1b[ q");
+    assert_eq!(
+        (s.cursor.shape, s.cursor.blinking),
+        (CursorShape::Block, false)
+    );
+}
+
+#[test]
+fn mesh_draws_the_cursor_in_its_shape() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"ab");
+    s.construct_mesh();
+    let inverted = |s: &Screen, col: usize| s.mesh.vertices[col * 12 + 4].selected == 1.0;
+    let decorated = |s: &Screen, col: usize| {
+        s.mesh.vertices[col * 12 + 8].pos != s.mesh.vertices[col * 12 + 10].pos
+    };
+    assert!(inverted(&s, 2) && !inverted(&s, 1));
+    // DECTCEM hides it
+    feed(&mut s, b"\x1b[?25l");
+    s.update_mesh();
+    assert!(!inverted(&s, 2));
+    feed(&mut s, b"\x1b[?25h\x1b[4 q");
+    s.update_mesh();
+    assert!(!inverted(&s, 2) && decorated(&s, 2));
+    // The old cell is redrawn once the cursor moves on
+    feed(&mut s, b"c");
+    s.update_mesh();
+    assert!(!decorated(&s, 2) && decorated(&s, 3));
+}
+
+#[test]
+fn blinking_cursor_is_drawn_in_the_shown_half() {
+    let mut s = screen(10, 3);
+    feed(&mut s, b"\x1b[1 q");
+    assert!(s.cursor_blinks());
+    s.cursor.visible = true;
+    s.construct_mesh();
+    assert_eq!(s.mesh.vertices[4].selected, 1.0);
+    s.cursor.visible = false;
+    s.update_mesh();
+    assert_eq!(s.mesh.vertices[4].selected, 0.0);
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 18. Character sets
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn dec_special_graphics_draws_lines() {
+    let mut s = screen(20, 3);
+    feed(&mut s, b"\x1b(0lqqk\x1b(B lqk");
+    assert_eq!(line(&s, 0), "┌──┐ lqk");
+}
+
+#[test]
+fn shift_out_and_in_switch_between_g0_and_g1() {
+    let mut s = screen(20, 3);
+    feed(&mut s, b"\x1b)0x\x0ex\x0fx");
+    assert_eq!(line(&s, 0), "x│x");
+}
+
+#[test]
+fn single_shift_applies_to_one_character() {
+    let mut s = screen(20, 3);
+    feed(&mut s, b"\x1b*A\x1b+<\x1bN##\x1bO!!");
+    assert_eq!(line(&s, 0), "£#¡!");
+    // Locking shifts stay
+    feed(&mut s, b"\x1bn##");
+    assert_eq!(line(&s, 0), "£#¡!££");
+}
+
+#[test]
+fn dec_supplemental_differs_from_latin_1() {
+    let mut s = screen(20, 3);
+    feed(&mut s, b"\x1b(%5AW]w\x1b(B");
+    assert_eq!(line(&s, 0), "ÁŒŸœ");
+}
+
+#[test]
+fn charsets_are_saved_with_the_cursor_and_reset() {
+    let mut s = screen(20, 3);
+    feed(&mut s, b"\x1b(0\x1b7\x1b(B\x1b8q");
+    assert_eq!(line(&s, 0), "─");
+    feed(&mut s, b"\x1b[!pq");
+    assert_eq!(line(&s, 0), "─q");
+}
+
+// ─────────────────────────────────────────────────────────────────────────
+// 19. Wide characters and grapheme clusters
+// ─────────────────────────────────────────────────────────────────────────
+
+#[test]
+fn wide_characters_take_two_columns() {
+    let mut s = screen(10, 3);
+    feed(&mut s, "中文x".as_bytes());
+    assert_eq!(line(&s, 0), "中文x");
+    assert_eq!(s.cells[0].width, CellWidth::Wide);
+    assert_eq!(s.cells[1].width, CellWidth::Spacer);
+    assert_eq!(s.cells[4].ch, 'x');
+    assert_eq!(s.cursor.col, 5);
+}
+
+#[test]
+fn combining_marks_attach_to_the_previous_cell() {
+    let mut s = screen(10, 3);
+    feed(&mut s, "e\u{301}x".as_bytes());
+    assert_eq!(s.cells[0].ch.to_string(), "e\u{301}");
+    assert_eq!(s.cells[1].ch, 'x');
+    assert_eq!(s.cursor.col, 2);
+    // Onto the last column too, while the wrap is pending
+    feed(&mut s, "\x1b[1;10Ha\u{308}".as_bytes());
+    assert_eq!(s.cells[9].ch.to_string(), "a\u{308}");
+    assert!(s.cursor.pending_wrap);
+    // Nothing to attach to at the start of a row
+    feed(&mut s, "\x1b[2;1H\u{301}".as_bytes());
+    assert_eq!(line(&s, 1), "");
+    assert_eq!(s.cursor.col, 0);
+}
+
+#[test]
+fn wide_character_at_the_last_column_wraps_first() {
+    let mut s = screen(5, 3);
+    feed(&mut s, "abcd中".as_bytes());
+    assert_eq!(line(&s, 0), "abcd");
+    assert_eq!(s.cells[4].width, CellWidth::Spacer);
+    assert!(s.lines[0].wrapped);
+    assert_eq!(line(&s, 1), "中");
+    assert_eq!((s.cursor.y, s.cursor.col), (1, 2));
+    // Dropped without autowrap
+    feed(&mut s, "\x1b[?7l\x1b[3;5H中".as_bytes());
+    assert_eq!(line(&s, 2), "");
+}
+
+#[test]
+fn overwriting_half_of_a_wide_character_blanks_the_other() {
+    let mut s = screen(10, 3);
+    feed(&mut s, "中文\x1b[1;2Hx".as_bytes());
+    assert_eq!(line(&s, 0), " x文");
+    assert_eq!(s.cells[0].width, CellWidth::Single);
+    feed(&mut s, "\x1b[1;3Hy".as_bytes());
+    assert_eq!(line(&s, 0), " xy");
+}
+
+#[test]
+fn clusters_split_by_width_without_mode_2027() {
+    let mut s = screen(10, 3);
+    // The heart stays narrow and the joined emoji get cells of their own
+    feed(&mut s, "❤\u{fe0f}👨\u{200d}👩".as_bytes());
+    assert_eq!(s.cells[0].ch.to_string(), "❤\u{fe0f}");
+    assert_eq!(s.cells[0].width, CellWidth::Single);
+    assert_eq!(s.cells[1].ch.to_string(), "👨\u{200d}");
+    assert_eq!(s.cells[3].ch, '👩');
+    assert_eq!(s.cursor.col, 5);
+}
+
+#[test]
+fn mode_2027_clusters_by_grapheme() {
+    let mut s = screen(10, 3);
+    assert_eq!(replies(&mut s, b"\x1b[?2027$p"), "\x1b[?2027;2$y");
+    feed(&mut s, b"\x1b[?2027h");
+    assert_eq!(replies(&mut s, b"\x1b[?2027$p"), "\x1b[?2027;1$y");
+    feed(&mut s, "❤\u{fe0f}👨\u{200d}👩🇺🇸x".as_bytes());
+    assert_eq!(s.cells[0].ch.to_string(), "❤\u{fe0f}");
+    assert_eq!(s.cells[0].width, CellWidth::Wide);
+    assert_eq!(s.cells[2].ch.to_string(), "👨\u{200d}👩");
+    assert_eq!(s.cells[4].ch.to_string(), "🇺🇸");
+    assert_eq!(s.cells[6].ch, 'x');
+    assert_eq!(line(&s, 0), "❤\u{fe0f}👨\u{200d}👩🇺🇸x");
+}
+
+#[test]
+fn reflow_keeps_wide_characters_whole() {
+    let mut s = screen(5, 3);
+    feed(&mut s, "abcd中文".as_bytes());
+    resize(&mut s, 10, 3);
+    assert_eq!(line(&s, 0), "abcd中文");
+    assert_eq!(s.cells[4].width, CellWidth::Wide);
+    assert_eq!((s.cursor.y, s.cursor.col), (0, 8));
+    resize(&mut s, 7, 3);
+    assert_eq!(line(&s, 0), "abcd中");
+    assert_eq!(s.cells[6].width, CellWidth::Spacer);
+    assert_eq!(line(&s, 1), "文");
+    assert_eq!((s.cursor.y, s.cursor.col), (1, 2));
+}
+
+#[test]
+fn selection_reads_wide_characters_once() {
+    let mut s = screen(10, 3);
+    feed(&mut s, "中文 ab".as_bytes());
+    select(&mut s, SelectionKind::Simple, (0, 0), (9, 0));
+    assert_eq!(s.selection_text().as_deref(), Some("中文 ab"));
+    // Clicking the right half picks the word too
+    s.start_selection(SelectionKind::Word, 3, 0);
+    assert_eq!(s.selection_text().as_deref(), Some("中文"));
+}
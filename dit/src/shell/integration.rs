@@ -0,0 +1,133 @@
+//! Shell integration: scripts that make bash, zsh and fish mark their prompts and commands with
+//! OSC 133 and report their working directory with OSC 7. They're written out at startup and
+//! loaded without touching the user's own startup files.
+use std::{
+    env,
+    fs::{self, DirBuilder},
+    io,
+    os::unix::fs::{DirBuilderExt, MetadataExt},
+    path::{Path, PathBuf},
+};
+
+use super::ShellConfig;
+
+/// Scripts by their path under the integration directory
+const SCRIPTS: [(&str, &str); 4] = [
+    ("dit.bash", include_str!("../../assets/shell/dit.bash")),
+    ("dit.zsh", include_str!("../../assets/shell/dit.zsh")),
+    (
+        "zsh/.zshenv",
+        include_str!("../../assets/shell/zsh/.zshenv"),
+    ),
+    (
+        "fish/vendor_conf.d/dit.fish",
+        include_str!("../../assets/shell/fish/vendor_conf.d/dit.fish"),
+    ),
+];
+
+/// The directory the scripts are written under, private to the user
+fn private_dir() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("dit"),
+        None => env::temp_dir().join(format!("dit-{}", unsafe { libc::getuid() })),
+    }
+}
+/// Creates `dir` unless it exists and makes sure it's a directory only the user can get into.
+/// In the shared temporary directory another user could have made it first to plant the
+/// scripts the shell sources.
+pub(super) fn ensure_private(dir: &Path) -> io::Result<()> {
+    DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    // Not following a symlink put in its place
+    let meta = fs::symlink_metadata(dir)?;
+    if !meta.file_type().is_dir()
+        || meta.uid() != unsafe { libc::getuid() }
+        || meta.mode() & 0o777 != 0o700
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not private to this user", dir.display()),
+        ));
+    }
+    Ok(())
+}
+/// Writes the scripts under `dir`, replacing whatever an older version left there
+fn write_scripts(dir: &Path) -> io::Result<()> {
+    for (name, script) in SCRIPTS {
+        let path = dir.join(name);
+        if let Some(parent) = path.parent() {
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(parent)?;
+        }
+        fs::write(path, script)?;
+    }
+    Ok(())
+}
+/// The value `key` will have in the child, `config.env` going over the inherited environment
+fn child_var(config: &ShellConfig, key: &str) -> Option<String> {
+    config
+        .env
+        .iter()
+        .rev()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value.clone())
+        .or_else(|| env::var(key).ok())
+}
+/// Sets `config` up to load the scripts in `dir`. Returns false for shells without an
+/// integration and for ways of starting one that would skip it.
+pub(super) fn configure(config: &mut ShellConfig, dir: &Path) -> bool {
+    let name = Path::new(&config.shell)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match name.as_str() {
+        // A login shell reads its profile and never an rcfile, arguments may make it run a
+        // script instead of being interactive
+        "bash" if !config.login && config.args.is_empty() => {
+            let rcfile = dir.join("dit.bash").to_string_lossy().into_owned();
+            config.args = vec!["--rcfile".to_string(), rcfile];
+        }
+        "zsh" => {
+            if let Some(zdotdir) = child_var(config, "ZDOTDIR") {
+                config.env.push(("DIT_ZSH_ZDOTDIR".to_string(), zdotdir));
+            }
+            let zdotdir = dir.join("zsh").to_string_lossy().into_owned();
+            config.env.push(("ZDOTDIR".to_string(), zdotdir));
+        }
+        "fish" => {
+            let data_dirs = child_var(config, "XDG_DATA_DIRS");
+            // fish looks for fish/vendor_conf.d in each of them
+            let mut dirs = dir.to_string_lossy().into_owned();
+            // Unset means the spec's default, which has to be spelled out once something's added
+            dirs.push(':');
+            dirs.push_str(
+                data_dirs
+                    .as_deref()
+                    .unwrap_or("/usr/local/share:/usr/share"),
+            );
+            if let Some(data_dirs) = data_dirs {
+                config
+                    .env
+                    .push(("DIT_FISH_XDG_DATA_DIRS".to_string(), data_dirs));
+            }
+            config.env.push(("XDG_DATA_DIRS".to_string(), dirs));
+        }
+        _ => return false,
+    }
+    true
+}
+/// Writes the scripts out and sets `config` up to load them. Returns false, leaving `config`
+/// as is, when the shell has no integration.
+pub fn inject(config: &mut ShellConfig) -> io::Result<bool> {
+    let private = private_dir();
+    let dir = private.join("shell");
+    let mut injected = config.clone();
+    if !configure(&mut injected, &dir) {
+        return Ok(false);
+    }
+    ensure_private(&private)?;
+    write_scripts(&dir)?;
+    *config = injected;
+    Ok(true)
+}
@@ -1,75 +1,45 @@
-use nix::ioctl_write_ptr;
-use nix::sys::termios::{LocalFlags, SetArg, tcgetattr, tcsetattr};
+use nix::ioctl_write_ptr_bad;
 use nix::{
+    errno::Errno,
     fcntl::{FcntlArg::F_SETFL, OFlag, fcntl},
     libc::{STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO, dup2, getpid, setsid},
     poll::{PollFd, PollFlags, PollTimeout},
     pty::{grantpt, posix_openpt, ptsname, unlockpt},
     sys::{
+        signal::{Signal, killpg},
         stat::Mode,
-        wait::{WaitPidFlag, waitpid},
+        wait::{WaitPidFlag, WaitStatus, waitpid},
     },
-    unistd::{ForkResult, close, execvp, fork},
+    unistd::{ForkResult, Pid, close, execvpe, fork},
 };
 use std::io::{BufRead, BufReader};
+pub mod integration;
+#[cfg(test)]
+mod tests;
 use std::{
     ffi::CString,
     fs::File,
     io::{Read, Write},
-    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd},
-    path::Path,
+    os::{
+        fd::{AsFd, AsRawFd, FromRawFd, IntoRawFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, sync_channel},
+    thread,
+    time::{Duration, Instant},
 };
 
-#[inline(always)]
-fn disable_echo(fd: BorrowedFd) -> nix::Result<()> {
-    let mut termios = tcgetattr(fd)?;
-    termios
-        .local_flags
-        .remove(LocalFlags::ECHO | LocalFlags::ICANON);
-    tcsetattr(fd, SetArg::TCSANOW, &termios)?;
-    Ok(())
-}
-/// Tracks marker matching efficiently across streamed input
-pub struct MarkerMatcher {
-    marker: Vec<u8>,
-    matched: usize,
-}
-
-impl MarkerMatcher {
-    pub fn new(marker: &[u8]) -> Self {
-        Self {
-            marker: marker.to_vec(),
-            matched: 0,
-        }
-    }
-
-    /// Feed bytes, returns true if the marker is fully matched
-    pub fn feed(&mut self, bytes: &[u8]) -> bool {
-        for &b in bytes {
-            if b == self.marker[self.matched] {
-                self.matched += 1;
-                if self.matched == self.marker.len() {
-                    // Full marker matched
-                    self.matched = 0; // reset for next match
-                    return true;
-                }
-            } else {
-                // Mismatch: check if current byte starts a partial match
-                self.matched = if b == self.marker[0] { 1 } else { 0 };
-            }
-        }
-        false
-    }
+ioctl_write_ptr_bad!(tiocswinsz, libc::TIOCSWINSZ, libc::winsize);
 
-    pub fn reset(&mut self) {
-        self.matched = 0;
-    }
-}
 // Sent to specify the shell and additional parameters
+/// Shells listed in /etc/shells, empty when the file can't be read
 #[inline(always)]
 #[cfg(target_os = "linux")]
 pub fn available_shells() -> Vec<String> {
-    let file = File::open("/etc/shells").unwrap();
+    let Ok(file) = File::open("/etc/shells") else {
+        return Vec::new();
+    };
     let reader = BufReader::new(file);
     reader
         .lines()
@@ -83,57 +53,164 @@ pub fn available_shells() -> Vec<String> {
         })
         .collect()
 }
-struct ShellConfig {
-    shell: String,
-    args: Vec<String>,
+/// Used when neither `$SHELL` nor /etc/shells give us anything to run
+const FALLBACK_SHELL: &str = "/bin/sh";
+/// Picks `$SHELL` if it is a listed login shell, otherwise the first of `shells`.
+/// An unreadable /etc/shells (empty `shells`) trusts `$SHELL` as is.
+fn resolve_shell(env_shell: Option<String>, shells: &[String]) -> String {
+    match env_shell {
+        Some(shell) if shells.is_empty() || shells.contains(&shell) => shell,
+        _ => shells
+            .iter()
+            .find(|shell| shell.as_str() == FALLBACK_SHELL)
+            .or(shells.first())
+            .cloned()
+            .unwrap_or_else(|| FALLBACK_SHELL.to_string()),
+    }
+}
+/// Variables every child gets, overridable through [`ShellConfig::env`]
+fn terminal_env() -> [(String, String); 4] {
+    [
+        ("TERM".to_string(), "xterm-256color".to_string()),
+        ("COLORTERM".to_string(), "truecolor".to_string()),
+        ("TERM_PROGRAM".to_string(), "dit".to_string()),
+        (
+            "TERM_PROGRAM_VERSION".to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+        ),
+    ]
+}
+#[derive(Clone)]
+pub struct ShellConfig {
+    /// Path of the program to run
+    pub shell: String,
+    /// Arguments after argv[0]
+    pub args: Vec<String>,
+    /// Extra variables set on top of the inherited environment
+    pub env: Vec<(String, String)>,
+    /// Directory the child starts in, the current one when `None`
+    pub cwd: Option<PathBuf>,
+    /// Starts the shell as a login shell, argv[0] gets a leading `-`
+    pub login: bool,
 }
 
 impl ShellConfig {
-    fn new(shell: String) -> Self {
+    pub fn new(shell: String) -> Self {
         Self {
             shell,
             args: Vec::new(),
+            env: Vec::new(),
+            cwd: None,
+            login: false,
         }
     }
-}
-impl Default for ShellConfig {
-    fn default() -> Self {
-        if let Ok(shell) = std::env::var("SHELL") {
-            Self {
-                shell,
-                args: Vec::new(),
-            }
+    /// argv as handed to exec, including argv[0]
+    fn argv(&self) -> Vec<CString> {
+        let name = Path::new(&self.shell)
+            .file_name()
+            .map_or(self.shell.clone(), |name| {
+                name.to_string_lossy().into_owned()
+            });
+        let arg0 = if self.login {
+            format!("-{}", name)
         } else {
-            Self {
-                shell: "".to_string(),
-                args: Vec::new(),
+            name
+        };
+        std::iter::once(arg0)
+            .chain(self.args.iter().cloned())
+            .filter_map(|arg| CString::new(arg).ok())
+            .collect()
+    }
+    /// The child's environment: `base` with the terminal variables and then our own on top
+    fn envp(&self, base: impl Iterator<Item = (String, String)>) -> Vec<CString> {
+        let mut vars: Vec<(String, String)> = Vec::new();
+        for (key, value) in base.chain(terminal_env()).chain(self.env.iter().cloned()) {
+            match vars.iter_mut().find(|(k, _)| *k == key) {
+                Some(var) => var.1 = value,
+                None => vars.push((key, value)),
             }
         }
+        vars.into_iter()
+            .filter_map(|(key, value)| CString::new(format!("{}={}", key, value)).ok())
+            .collect()
     }
 }
-struct PtyConfig {
-    shell: ShellConfig,
-    marker: String,
+impl Default for ShellConfig {
+    fn default() -> Self {
+        Self::new(resolve_shell(
+            std::env::var("SHELL").ok(),
+            &available_shells(),
+        ))
+    }
+}
+#[derive(Clone)]
+pub struct PtyConfig {
+    pub shell: ShellConfig,
+    /// Loads the scripts of [`integration`] into shells that have one
+    pub shell_integration: bool,
+}
+
+/// How the child process ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExitStatus {
+    Exited(i32),
+    Signaled(Signal),
+}
+impl ExitStatus {
+    /// The status as a shell would report it in `$?`, 128 + the signal number for signals
+    pub fn code(&self) -> i32 {
+        match self {
+            ExitStatus::Exited(code) => *code,
+            ExitStatus::Signaled(signal) => 128 + *signal as i32,
+        }
+    }
 }
 
+/// Chunks of output queued before the reader thread, and with it the child, has to wait
+const OUTPUT_QUEUE_LEN: usize = 64;
+/// Upper bound on a single chunk of output
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+/// Most bytes handed to the pty per write, what the line discipline takes at once
+const WRITE_CHUNK_SIZE: usize = 4096;
+/// How long a dropped pty's child gets to exit on SIGHUP before it's killed
+const HANGUP_GRACE: Duration = Duration::from_millis(500);
+
 pub struct Pty {
     pub master: File,
-    pub shell: &'static str,
-    pub marker: &'static str,
+    pub shell: String,
+    /// The shell, leader of the session and process group on the pty
+    pub child: Pid,
+    // a child can only be reaped once, so the status is kept around
+    exit_status: Option<ExitStatus>,
 }
 
 impl Pty {
-    // Adjust this to take a shell executable path
-    // Ex: zsh, fish, bash, sh
-
-    pub fn attempt_create(marker: &'static str, win_size: libc::winsize) -> nix::Result<Self> {
-        ioctl_write_ptr!(tiocswinsz, b'T', libc::TIOCSWINSZ, libc::winsize);
+    /// Spawns the shell described by `config` on a new pty of `win_size`.
+    pub fn attempt_create(mut config: PtyConfig, win_size: libc::winsize) -> nix::Result<Self> {
+        // A shell without its integration still works, only without marks
+        if config.shell_integration
+            && let Err(e) = integration::inject(&mut config.shell)
+        {
+            eprintln!("failed to set up shell integration: {}", e);
+        }
+        // Everything the child needs is prepared before forking
+        let shell = &config.shell;
+        let program = CString::new(shell.shell.as_str()).map_err(|_| Errno::EINVAL)?;
+        let argv = shell.argv();
+        let envp = shell.envp(std::env::vars());
+        let cwd = match &shell.cwd {
+            Some(cwd) => Some(CString::new(cwd.as_os_str().as_bytes()).map_err(|_| Errno::EINVAL)?),
+            None => None,
+        };
+        // Written by the child if exec fails, it can't safely allocate after the fork
+        let exec_failed = format!("dit: failed to run {}\r\n", shell.shell);
         let master_fd = posix_openpt(OFlag::O_RDWR)?;
         grantpt(&master_fd)?;
         unlockpt(&master_fd)?;
         let slave_name = unsafe { ptsname(&master_fd) }?;
         let slave_fd = nix::fcntl::open(Path::new(&slave_name), OFlag::O_RDWR, Mode::empty())?;
-        disable_echo(slave_fd.as_fd())?;
+        // Sized before the fork so the shell sees the right dimensions from its first read
+        unsafe { tiocswinsz(slave_fd.as_raw_fd(), &win_size) }?;
         match unsafe { fork()? } {
             ForkResult::Child => unsafe {
                 setsid();
@@ -144,43 +221,44 @@ impl Pty {
                 dup2(slave_fd.as_raw_fd(), STDIN_FILENO);
                 dup2(slave_fd.as_raw_fd(), STDOUT_FILENO);
                 dup2(slave_fd.as_raw_fd(), STDERR_FILENO);
-                // tiocswinsz(slave_fd.as_raw_fd(), &win_size)?;
                 // The child should only own the slave and thus the master_fd is useless to it.
-                close(master_fd)?;
+                // Nothing can be reported from here, errors are ignored until the exec.
+                let _ = close(master_fd);
                 if slave_fd.as_raw_fd() > 2 {
                     // If slave_Fd is not one of the standard streams close it as it would be redundant
-                    close(slave_fd)?;
+                    let _ = close(slave_fd);
                 }
 
-                let shell = CString::new("/bin/bash").unwrap();
-                let args = [
-                    shell.clone(),
-                    CString::new("--noprofile").unwrap(),
-                    CString::new("--norc").unwrap(),
-                    CString::new("-i").unwrap(),
-                    CString::new("-c").unwrap(),
-                    CString::new("export PS1=''; exec bash").unwrap(),
-                ];
-
-                execvp(&shell, &args)?;
-                //
-                // Tells the compiler that this branch will not ccontinue executing code
-                unreachable!();
+                // A missing directory isn't worth failing over, the shell starts where we are
+                if let Some(cwd) = &cwd {
+                    libc::chdir(cwd.as_ptr());
+                }
+                let _ = execvpe(&program, &argv, &envp);
+                // Only reached when exec failed. Returning would leave a second copy of the app
+                // running in the child, so it exits right here with the shell's code for a
+                // command that couldn't be run.
+                libc::write(
+                    STDERR_FILENO,
+                    exec_failed.as_ptr().cast(),
+                    exec_failed.len(),
+                );
+                libc::_exit(127);
             },
             ForkResult::Parent { child } => {
                 let master = unsafe { File::from_raw_fd(master_fd.into_raw_fd()) };
-                waitpid(child, Some(WaitPidFlag::WNOHANG))?;
                 fcntl(&master, F_SETFL(OFlag::O_NONBLOCK))?;
                 Ok(Self {
                     master,
-                    shell: "/bin/bash",
-                    marker,
+                    shell: config.shell.shell,
+                    child,
+                    exit_status: None,
                 })
             }
         }
     }
     /// Polls for input from the slave side of the pty
     /// Thin wrapper around it just handles checking for POLLIN
+    /// Fails with `EIO` once every process closed the slave side and nothing is left to read.
     pub fn poll(&self, timeout_ms: i32) -> nix::Result<bool> {
         let mut fds = [PollFd::new(self.master.as_fd(), PollFlags::POLLIN)];
         // If timeout is invalid it just ends itself so should prob add some way of safely panicking
@@ -191,17 +269,96 @@ impl Pty {
 
         let revents = fds[0].revents().unwrap_or(PollFlags::empty());
 
-        if revents.contains(PollFlags::POLLERR | PollFlags::POLLHUP) {
-            return Err(nix::errno::Errno::EIO);
+        // Output written right before hanging up is still readable
+        if revents.contains(PollFlags::POLLIN) {
+            return Ok(true);
         }
-
-        Ok(revents.contains(PollFlags::POLLIN))
+        if revents.intersects(PollFlags::POLLERR | PollFlags::POLLHUP) {
+            return Err(Errno::EIO);
+        }
+        Ok(false)
+    }
+    /// Reads the child's output on its own thread. Every chunk is sent through the returned
+    /// channel followed by a call to `wake`. The channel disconnects, with one last `wake`, once
+    /// the child hangs up.
+    pub fn spawn_reader(
+        &self,
+        wake: impl Fn() + Send + 'static,
+    ) -> std::io::Result<Receiver<Vec<u8>>> {
+        let mut master = self.master.try_clone()?;
+        let (sender, receiver) = sync_channel(OUTPUT_QUEUE_LEN);
+        thread::Builder::new()
+            .name("pty-reader".to_string())
+            .spawn(move || {
+                let mut buf = vec![0u8; READ_CHUNK_SIZE];
+                loop {
+                    let mut fds = [PollFd::new(master.as_fd(), PollFlags::POLLIN)];
+                    match nix::poll::poll(&mut fds, PollTimeout::NONE) {
+                        Ok(_) | Err(Errno::EINTR) => {}
+                        Err(_) => break,
+                    }
+                    match master.read(&mut buf) {
+                        Ok(0) => break,
+                        Ok(n) => {
+                            // Blocks while the queue is full, which in turn stalls the child
+                            if sender.send(buf[..n].to_vec()).is_err() {
+                                return;
+                            }
+                            wake();
+                        }
+                        Err(e)
+                            if matches!(
+                                e.kind(),
+                                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted
+                            ) => {}
+                        // EIO once the slave side is closed
+                        Err(_) => break,
+                    }
+                }
+                drop(sender);
+                wake();
+            })?;
+        Ok(receiver)
     }
-    /// This function assumes the user has not included
-    pub fn write(&mut self, input: &String) -> std::io::Result<()> {
-        self.master.write_all(input.as_bytes())?;
+    /// Reaps the child without blocking. `None` while it is still running.
+    pub fn try_wait(&mut self) -> nix::Result<Option<ExitStatus>> {
+        if self.exit_status.is_none() {
+            self.exit_status = match waitpid(self.child, Some(WaitPidFlag::WNOHANG))? {
+                WaitStatus::Exited(_, code) => Some(ExitStatus::Exited(code)),
+                WaitStatus::Signaled(_, signal, _) => Some(ExitStatus::Signaled(signal)),
+                _ => None,
+            };
+        }
+        Ok(self.exit_status)
+    }
+    /// Tells the child about a new window size. The kernel sends SIGWINCH to the foreground
+    /// process group whenever the size actually changes.
+    pub fn resize(&self, rows: u16, cols: u16, xpixel: u16, ypixel: u16) -> nix::Result<()> {
+        let win_size = libc::winsize {
+            ws_row: rows,
+            ws_col: cols,
+            ws_xpixel: xpixel,
+            ws_ypixel: ypixel,
+        };
+        unsafe { tiocswinsz(self.master.as_raw_fd(), &win_size) }?;
         Ok(())
     }
+    /// Writes as much of `input` as the pty takes right now, in chunks, and returns how much
+    /// that was. A full pty isn't an error, the rest has to wait until the child reads some.
+    pub fn write(&mut self, input: &[u8]) -> std::io::Result<usize> {
+        let mut written = 0;
+        while written < input.len() {
+            let end = input.len().min(written + WRITE_CHUNK_SIZE);
+            match self.master.write(&input[written..end]) {
+                Ok(0) => break,
+                Ok(n) => written += n,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(written)
+    }
     /// user supplies their own buffer for reading the data into
     /// If the buffer isn't big enough to read data into it returns a message indicating that
     /// via a tuple
@@ -214,3 +371,24 @@ impl Pty {
         Ok(n)
     }
 }
+impl Drop for Pty {
+    fn drop(&mut self) {
+        if self.exit_status.is_none() {
+            // Hang up on the whole process group like closing a real terminal would
+            let _ = killpg(self.child, Signal::SIGHUP);
+            // Reaped off the event loop, killed if it ignores the hangup
+            let child = self.child;
+            thread::spawn(move || {
+                let hung_up = Instant::now();
+                while hung_up.elapsed() < HANGUP_GRACE {
+                    match waitpid(child, Some(WaitPidFlag::WNOHANG)) {
+                        Ok(WaitStatus::StillAlive) => thread::sleep(Duration::from_millis(10)),
+                        _ => return,
+                    }
+                }
+                let _ = killpg(child, Signal::SIGKILL);
+                let _ = waitpid(child, None);
+            });
+        }
+    }
+}
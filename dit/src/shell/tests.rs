@@ -0,0 +1,272 @@
+use std::{
+    ffi::CString, fs, os::unix::fs::PermissionsExt, path::Path, process::Command, thread,
+    time::Duration,
+};
+
+use nix::sys::signal::Signal;
+
+use crate::shell::{ExitStatus, Pty, PtyConfig, ShellConfig, integration, resolve_shell};
+
+fn shells() -> Vec<String> {
+    vec!["/bin/sh".to_string(), "/usr/bin/zsh".to_string()]
+}
+
+/// Runs `script` through /bin/sh on a fresh 24x80 pty.
+fn spawn(script: &str) -> Pty {
+    let mut shell = ShellConfig::new("/bin/sh".to_string());
+    shell.args = vec!["-c".to_string(), script.to_string()];
+    let config = PtyConfig {
+        shell,
+        shell_integration: false,
+    };
+    let size = libc::winsize {
+        ws_row: 24,
+        ws_col: 80,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    Pty::attempt_create(config, size).unwrap()
+}
+
+/// Polls `try_wait` for up to two seconds.
+fn wait(pty: &mut Pty) -> Option<ExitStatus> {
+    for _ in 0..200 {
+        if let Some(status) = pty.try_wait().unwrap() {
+            return Some(status);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    None
+}
+
+fn strings(values: &[CString]) -> Vec<String> {
+    values
+        .iter()
+        .map(|v| v.to_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn listed_shell_is_used() {
+    let shell = resolve_shell(Some("/usr/bin/zsh".to_string()), &shells());
+    assert_eq!(shell, "/usr/bin/zsh");
+}
+
+#[test]
+fn unlisted_shell_falls_back_to_etc_shells() {
+    let shell = resolve_shell(Some("/tmp/evil".to_string()), &shells());
+    assert_eq!(shell, "/bin/sh");
+    let shell = resolve_shell(None, &["/usr/bin/fish".to_string()]);
+    assert_eq!(shell, "/usr/bin/fish");
+}
+
+#[test]
+fn missing_etc_shells_trusts_env() {
+    assert_eq!(resolve_shell(Some("/bin/zsh".to_string()), &[]), "/bin/zsh");
+    assert_eq!(resolve_shell(None, &[]), "/bin/sh");
+}
+
+#[test]
+fn login_shell_argv0_has_dash() {
+    let mut config = ShellConfig::new("/usr/bin/zsh".to_string());
+    config.args = vec!["-c".to_string(), "true".to_string()];
+    assert_eq!(strings(&config.argv()), vec!["zsh", "-c", "true"]);
+    config.login = true;
+    assert_eq!(strings(&config.argv())[0], "-zsh");
+}
+
+#[test]
+fn env_sets_terminal_variables_and_overrides() {
+    let mut config = ShellConfig::new("/bin/sh".to_string());
+    config.env = vec![("TERM".to_string(), "dumb".to_string())];
+    let base = vec![
+        ("HOME".to_string(), "/home/me".to_string()),
+        ("COLORTERM".to_string(), "".to_string()),
+    ];
+    let env = strings(&config.envp(base.into_iter()));
+    assert!(env.contains(&"HOME=/home/me".to_string()));
+    assert!(env.contains(&"COLORTERM=truecolor".to_string()));
+    assert!(env.contains(&"TERM_PROGRAM=dit".to_string()));
+    assert!(env.contains(&"TERM=dumb".to_string()));
+    assert_eq!(env.iter().filter(|v| v.starts_with("TERM=")).count(), 1);
+}
+
+#[test]
+fn bash_integration_replaces_the_rcfile() {
+    let mut config = ShellConfig::new("/bin/bash".to_string());
+    assert!(integration::configure(&mut config, Path::new("/run/dit")));
+    assert_eq!(config.args, ["--rcfile", "/run/dit/dit.bash"]);
+    // Neither a login shell nor one running a script reads an rcfile
+    let mut config = ShellConfig::new("/bin/bash".to_string());
+    config.login = true;
+    assert!(!integration::configure(&mut config, Path::new("/run/dit")));
+    let mut config = ShellConfig::new("/bin/bash".to_string());
+    config.args = vec!["-c".to_string(), "true".to_string()];
+    assert!(!integration::configure(&mut config, Path::new("/run/dit")));
+}
+
+#[test]
+fn zsh_integration_keeps_the_users_zdotdir() {
+    let mut config = ShellConfig::new("/usr/bin/zsh".to_string());
+    config.env = vec![("ZDOTDIR".to_string(), "/home/me/.zsh".to_string())];
+    assert!(integration::configure(&mut config, Path::new("/run/dit")));
+    let env = strings(&config.envp(std::iter::empty()));
+    assert!(env.contains(&"ZDOTDIR=/run/dit/zsh".to_string()));
+    assert!(env.contains(&"DIT_ZSH_ZDOTDIR=/home/me/.zsh".to_string()));
+}
+
+#[test]
+fn zsh_script_marks_the_prompt_with_the_exit_code() {
+    let script = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/shell/dit.zsh");
+    let run = format!("source {script}; __dit_running=1; (exit 3); __dit_precmd");
+    // Only checked where zsh is installed
+    let Ok(output) = Command::new("zsh").args(["-f", "-c", &run]).output() else {
+        return;
+    };
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("\x1b]133;D;3\x07\x1b]133;A\x07"),
+        "{stdout:?}"
+    );
+}
+
+#[test]
+fn fish_integration_adds_a_data_dir() {
+    let mut config = ShellConfig::new("/usr/bin/fish".to_string());
+    config.env = vec![("XDG_DATA_DIRS".to_string(), "/usr/share".to_string())];
+    assert!(integration::configure(&mut config, Path::new("/run/dit")));
+    let env = strings(&config.envp(std::iter::empty()));
+    assert!(env.contains(&"XDG_DATA_DIRS=/run/dit:/usr/share".to_string()));
+    assert!(env.contains(&"DIT_FISH_XDG_DATA_DIRS=/usr/share".to_string()));
+}
+
+#[test]
+fn integration_dir_must_be_private() {
+    let dir = std::env::temp_dir().join(format!("dit-test-private-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    integration::ensure_private(&dir).unwrap();
+    // An existing directory others can get into is refused rather than reused
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+    assert!(integration::ensure_private(&dir).is_err());
+    fs::remove_dir(&dir).unwrap();
+    // So is a symlink to one
+    std::os::unix::fs::symlink("/tmp", &dir).unwrap();
+    assert!(integration::ensure_private(&dir).is_err());
+    fs::remove_file(&dir).unwrap();
+}
+
+#[test]
+fn other_shells_have_no_integration() {
+    let mut config = ShellConfig::new("/bin/sh".to_string());
+    assert!(!integration::configure(&mut config, Path::new("/run/dit")));
+    assert!(config.args.is_empty() && config.env.is_empty());
+}
+
+#[test]
+fn exit_code_is_reported() {
+    let mut pty = spawn("exit 3");
+    assert_eq!(wait(&mut pty), Some(ExitStatus::Exited(3)));
+    // Asking again returns the same status instead of failing
+    assert_eq!(pty.try_wait().unwrap(), Some(ExitStatus::Exited(3)));
+}
+
+#[test]
+fn failed_exec_exits_the_child() {
+    let config = PtyConfig {
+        shell: ShellConfig::new("/nonexistent/shell".to_string()),
+        shell_integration: false,
+    };
+    let size = libc::winsize {
+        ws_row: 24,
+        ws_col: 80,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut pty = Pty::attempt_create(config, size).unwrap();
+    assert_eq!(wait(&mut pty), Some(ExitStatus::Exited(127)));
+}
+
+#[test]
+fn running_child_has_no_status() {
+    let mut pty = spawn("sleep 5");
+    assert_eq!(pty.try_wait().unwrap(), None);
+}
+
+#[test]
+fn signaled_child_reports_shell_style_code() {
+    let mut pty = spawn("kill -TERM $$");
+    let status = wait(&mut pty).unwrap();
+    assert_eq!(status, ExitStatus::Signaled(Signal::SIGTERM));
+    assert_eq!(status.code(), 143);
+}
+
+/// Whether `pid` is gone for good, not even a zombie left
+fn reaped(pid: nix::unistd::Pid) -> bool {
+    for _ in 0..300 {
+        if nix::sys::signal::kill(pid, None) == Err(nix::errno::Errno::ESRCH) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    false
+}
+
+#[test]
+fn dropped_pty_reaps_its_child() {
+    let pty = spawn("sleep 30");
+    let pid = pty.child;
+    drop(pty);
+    assert!(reaped(pid));
+    // One ignoring the hangup is killed
+    let pty = spawn("trap '' HUP; sleep 30");
+    thread::sleep(Duration::from_millis(100));
+    let pid = pty.child;
+    drop(pty);
+    assert!(reaped(pid));
+}
+
+#[test]
+fn hangup_is_an_error_after_output_is_drained() {
+    let mut pty = spawn("echo bye");
+    wait(&mut pty).unwrap();
+    let mut output = Vec::new();
+    let mut buf = [0u8; 64];
+    while let Ok(true) = pty.poll(100) {
+        match pty.read(&mut buf) {
+            Ok(n) => output.extend_from_slice(&buf[..n]),
+            Err(_) => break,
+        }
+    }
+    assert!(String::from_utf8_lossy(&output).contains("bye"));
+    assert!(pty.poll(0).is_err());
+}
+
+#[test]
+fn reader_thread_forwards_output_then_disconnects() {
+    let pty = spawn("echo hello");
+    let (woken, wakes) = std::sync::mpsc::channel();
+    let output = pty
+        .spawn_reader(move || {
+            let _ = woken.send(());
+        })
+        .unwrap();
+    let mut received = Vec::new();
+    // Iterating ends once the reader drops its sender on hangup
+    while let Ok(chunk) = output.recv_timeout(Duration::from_secs(2)) {
+        received.extend(chunk);
+    }
+    assert!(String::from_utf8_lossy(&received).contains("hello"));
+    // The sender goes away with the thread, after its final wake
+    assert!(wakes.iter().count() >= 2);
+}
+
+#[test]
+fn write_stops_at_a_full_pty_instead_of_failing() {
+    // Raw mode so the line discipline buffers input instead of dropping it
+    let mut pty = spawn("stty raw -echo; sleep 5");
+    thread::sleep(Duration::from_millis(200));
+    let input = vec![b'a'; 1 << 20];
+    let written = pty.write(&input).unwrap();
+    assert!(written > 0 && written < input.len());
+}